@@ -0,0 +1,153 @@
+use std::str::FromStr;
+
+use smithay::{desktop::Window, wayland::seat::WaylandFocus};
+
+use crate::ipc::IpcEvent;
+use crate::screenshot::ScreenshotTarget;
+use crate::state::NanaimoState;
+use crate::window::close_window;
+use crate::workspaces::WORKSPACE_COUNT;
+
+/// Something the compositor can do on its own, triggered by a keybinding or gesture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Spawn(String),
+    CloseWindow,
+    FocusNext,
+    FocusPrevious,
+    /// Switch to a workspace, 0-based.
+    Workspace(usize),
+    WorkspaceNext,
+    WorkspacePrevious,
+    /// Send the focused window to a workspace, 0-based.
+    MoveToWorkspace(usize),
+    ReloadConfig,
+    /// Save the output under the pointer as PNG.
+    Screenshot,
+    /// Save the focused window as PNG.
+    ScreenshotWindow,
+    /// Ask the shell to show or hide the Auto sidebar.
+    ToggleAuto,
+    /// Switch to the next configured keyboard layout.
+    SwitchLayout,
+    Quit,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, arg) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let arg = arg.trim();
+        let workspace = || match arg.parse::<usize>() {
+            Ok(n) if (1..=WORKSPACE_COUNT).contains(&n) => Ok(n - 1),
+            _ => Err(format!("{} needs a workspace between 1 and {}", name, WORKSPACE_COUNT)),
+        };
+        let no_arg = |action: Action| {
+            if arg.is_empty() {
+                Ok(action)
+            } else {
+                Err(format!("action {:?} takes no argument", name))
+            }
+        };
+
+        match name {
+            "spawn" if arg.is_empty() => Err("spawn needs a command".into()),
+            "spawn" => Ok(Action::Spawn(arg.to_string())),
+            "close" => no_arg(Action::CloseWindow),
+            "focus-next" => no_arg(Action::FocusNext),
+            "focus-previous" => no_arg(Action::FocusPrevious),
+            "workspace" => workspace().map(Action::Workspace),
+            "workspace-next" => no_arg(Action::WorkspaceNext),
+            "workspace-previous" => no_arg(Action::WorkspacePrevious),
+            "move-to-workspace" => workspace().map(Action::MoveToWorkspace),
+            "reload-config" => no_arg(Action::ReloadConfig),
+            "screenshot" => no_arg(Action::Screenshot),
+            "screenshot-window" => no_arg(Action::ScreenshotWindow),
+            "toggle-auto" => no_arg(Action::ToggleAuto),
+            "switch-layout" => no_arg(Action::SwitchLayout),
+            "quit" => no_arg(Action::Quit),
+            _ => Err(format!("unknown action {:?}", name)),
+        }
+    }
+}
+
+impl NanaimoState {
+    pub fn run_action(&mut self, action: Action) {
+        tracing::debug!("Running action {:?}", action);
+        match action {
+            Action::Spawn(command) => {
+                if let Err(err) = std::process::Command::new("/bin/sh").arg("-c").arg(&command).spawn() {
+                    tracing::warn!("Failed to spawn {:?}: {}", command, err);
+                }
+            }
+            Action::CloseWindow => {
+                if let Some(window) = self.focused_window() {
+                    close_window(&window);
+                }
+            }
+            Action::FocusNext => self.cycle_focus(true),
+            Action::FocusPrevious => self.cycle_focus(false),
+            Action::Workspace(index) => self.switch_workspace(index),
+            Action::WorkspaceNext => self.switch_workspace(self.workspaces.active + 1),
+            Action::WorkspacePrevious => {
+                if let Some(index) = self.workspaces.active.checked_sub(1) {
+                    self.switch_workspace(index);
+                }
+            }
+            Action::MoveToWorkspace(index) => {
+                if let Some(window) = self.focused_window() {
+                    self.move_window_to_workspace(&window, index);
+                }
+            }
+            Action::ReloadConfig => {
+                let _ = self.reload_config();
+            }
+            Action::Screenshot => self.screenshot(ScreenshotTarget::Output(None)),
+            Action::ScreenshotWindow => self.screenshot(ScreenshotTarget::Window(None)),
+            Action::ToggleAuto => self.ipc_event(IpcEvent::AutoToggled),
+            Action::SwitchLayout => {
+                let next = (self.keyboard_layout + 1) % self.config.keyboard.layouts.len();
+                self.set_keyboard_layout(next);
+            }
+            Action::Quit => {
+                tracing::info!("Quit requested");
+                self.running = false;
+            }
+        }
+    }
+
+    fn screenshot(&mut self, target: ScreenshotTarget) {
+        if let Err(err) = self.request_screenshot(target, None, None) {
+            tracing::warn!("Cannot take screenshot: {}", err);
+        }
+    }
+
+    /// The window that currently holds keyboard focus.
+    pub fn focused_window(&self) -> Option<Window> {
+        let focus = self.seat.get_keyboard()?.current_focus()?;
+        self.space
+            .elements()
+            .find(|w| w.wl_surface().as_deref() == Some(&focus))
+            .cloned()
+    }
+
+    /// Focuses the next (or previous) window in stacking order.
+    fn cycle_focus(&mut self, forward: bool) {
+        let windows: Vec<Window> = self.managed_windows().cloned().collect();
+        if windows.is_empty() {
+            return;
+        }
+        let current = self
+            .focused_window()
+            .and_then(|focused| windows.iter().position(|w| w == &focused));
+        let next = match (current, forward) {
+            (Some(i), true) => (i + 1) % windows.len(),
+            (Some(i), false) => (i + windows.len() - 1) % windows.len(),
+            (None, _) => windows.len() - 1,
+        };
+        let serial = self.serial_counter.next_serial();
+        self.focus_window(&windows[next], serial);
+    }
+}
@@ -1,86 +1,556 @@
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
-use smithay::desktop::Window;
+use smithay::{
+    desktop::Window,
+    utils::{Logical, Point, Size},
+};
 
+use crate::config::AnimationConfig;
+
+/// A cubic bezier from (0, 0) to (1, 1), as in CSS `cubic-bezier()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CubicBezier {
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+}
+
+impl CubicBezier {
+    pub const fn new(x1: f64, y1: f64, x2: f64, y2: f64) -> Self {
+        Self { x1, y1, x2, y2 }
+    }
+
+    fn sample(a1: f64, a2: f64, t: f64) -> f64 {
+        // Bernstein form with the end points fixed at 0 and 1.
+        let mt = 1.0 - t;
+        3.0 * mt * mt * t * a1 + 3.0 * mt * t * t * a2 + t * t * t
+    }
+
+    fn slope(a1: f64, a2: f64, t: f64) -> f64 {
+        let mt = 1.0 - t;
+        3.0 * mt * mt * a1 + 6.0 * mt * t * (a2 - a1) + 3.0 * t * t * (1.0 - a2)
+    }
+
+    /// Eased progress for linear progress `x` in [0, 1].
+    pub fn ease(&self, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+        if x >= 1.0 {
+            return 1.0;
+        }
+
+        // Find the curve parameter for `x`: Newton's method, then bisection if it stalls.
+        let mut t = x;
+        for _ in 0..8 {
+            let error = Self::sample(self.x1, self.x2, t) - x;
+            if error.abs() < 1e-7 {
+                return Self::sample(self.y1, self.y2, t);
+            }
+            let slope = Self::slope(self.x1, self.x2, t);
+            if slope.abs() < 1e-6 {
+                break;
+            }
+            t -= error / slope;
+        }
+
+        let (mut low, mut high) = (0.0, 1.0);
+        t = x;
+        for _ in 0..64 {
+            let value = Self::sample(self.x1, self.x2, t);
+            if (value - x).abs() < 1e-7 {
+                break;
+            }
+            if value < x {
+                low = t;
+            } else {
+                high = t;
+            }
+            t = (low + high) / 2.0;
+        }
+        Self::sample(self.y1, self.y2, t)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    CubicBezier(CubicBezier),
+}
+
+impl Easing {
+    pub const EASE: Easing = Easing::CubicBezier(CubicBezier::new(0.25, 0.1, 0.25, 1.0));
+    pub const EASE_IN: Easing = Easing::CubicBezier(CubicBezier::new(0.42, 0.0, 1.0, 1.0));
+    pub const EASE_OUT: Easing = Easing::CubicBezier(CubicBezier::new(0.0, 0.0, 0.58, 1.0));
+    pub const EASE_IN_OUT: Easing = Easing::CubicBezier(CubicBezier::new(0.42, 0.0, 0.58, 1.0));
+    pub const EASE_IN_CUBIC: Easing = Easing::CubicBezier(CubicBezier::new(0.32, 0.0, 0.67, 0.0));
+    pub const EASE_OUT_CUBIC: Easing = Easing::CubicBezier(CubicBezier::new(0.33, 1.0, 0.68, 1.0));
+
+    pub fn ease(&self, x: f64) -> f64 {
+        match self {
+            Easing::Linear => x.clamp(0.0, 1.0),
+            Easing::CubicBezier(curve) => curve.ease(x),
+        }
+    }
+}
+
+/// How close to rest a spring has to be, in property units (and units per second), to stop.
+const SPRING_EPSILON: f64 = 1e-3;
+
+/// A critically damped spring of unit mass: the fastest motion that never overshoots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spring {
+    /// Angular frequency, the square root of the stiffness.
+    pub omega: f64,
+}
+
+impl Spring {
+    pub fn new(stiffness: f64) -> Self {
+        Self { omega: stiffness.max(0.0).sqrt() }
+    }
+
+    /// A spring that comes to rest from a unit displacement in roughly `duration`.
+    pub fn settling_in(duration: Duration) -> Self {
+        // (1 + wt) e^(-wt) drops below the epsilon at wt ≈ 9.2.
+        let secs = duration.as_secs_f64().max(1e-3);
+        Self { omega: 9.2 / secs }
+    }
+
+    /// Displacement from the target and velocity `t` seconds after starting at `displacement`
+    /// with `velocity`.
+    fn state(&self, displacement: f64, velocity: f64, t: f64) -> (f64, f64) {
+        let w = self.omega;
+        let c = velocity + w * displacement;
+        let decay = (-w * t).exp();
+        ((displacement + c * t) * decay, (velocity - w * c * t) * decay)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    Eased { easing: Easing, duration: Duration },
+    Spring(Spring),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Animation {
     pub start_time: Instant,
-    pub duration: Duration,
     pub start_val: f64,
     pub end_val: f64,
+    /// Velocity at `start_time`, in units per second. Only springs carry it.
+    pub initial_velocity: f64,
+    pub curve: Curve,
 }
 
 impl Animation {
-    pub fn new(start: f64, end: f64, duration_ms: u64) -> Self {
+    pub fn eased(start: f64, end: f64, duration: Duration, easing: Easing) -> Self {
+        Self::starting_at(Instant::now(), start, end, 0.0, Curve::Eased { easing, duration })
+    }
+
+    pub fn starting_at(start_time: Instant, start: f64, end: f64, velocity: f64, curve: Curve) -> Self {
         Self {
-            start_time: Instant::now(),
-            duration: Duration::from_millis(duration_ms),
+            start_time,
             start_val: start,
             end_val: end,
+            initial_velocity: velocity,
+            curve,
         }
     }
 
+    fn elapsed(&self, now: Instant) -> f64 {
+        now.saturating_duration_since(self.start_time).as_secs_f64()
+    }
+
     pub fn value(&self, now: Instant) -> f64 {
-        let elapsed = now.duration_since(self.start_time);
-        if elapsed >= self.duration {
-            return self.end_val;
+        let elapsed = self.elapsed(now);
+        match self.curve {
+            Curve::Eased { easing, duration } => {
+                if elapsed >= duration.as_secs_f64() {
+                    return self.end_val;
+                }
+                let progress = easing.ease(elapsed / duration.as_secs_f64());
+                self.start_val + (self.end_val - self.start_val) * progress
+            }
+            Curve::Spring(spring) => {
+                let (displacement, _) = spring.state(self.start_val - self.end_val, self.initial_velocity, elapsed);
+                self.end_val + displacement
+            }
         }
-        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
-        // Linear interpolation for now
-        self.start_val + (self.end_val - self.start_val) * progress
     }
-    
+
+    /// Rate of change at `now`, in units per second.
+    pub fn velocity(&self, now: Instant) -> f64 {
+        let elapsed = self.elapsed(now);
+        match self.curve {
+            Curve::Eased { duration, .. } => {
+                if elapsed >= duration.as_secs_f64() {
+                    return 0.0;
+                }
+                let dt = Duration::from_millis(1);
+                (self.value(now + dt) - self.value(now)) / dt.as_secs_f64()
+            }
+            Curve::Spring(spring) => {
+                spring.state(self.start_val - self.end_val, self.initial_velocity, elapsed).1
+            }
+        }
+    }
+
     pub fn is_done(&self, now: Instant) -> bool {
-        now >= self.start_time + self.duration
+        let elapsed = self.elapsed(now);
+        match self.curve {
+            Curve::Eased { duration, .. } => elapsed >= duration.as_secs_f64(),
+            Curve::Spring(spring) => {
+                let (displacement, velocity) =
+                    spring.state(self.start_val - self.end_val, self.initial_velocity, elapsed);
+                displacement.abs() < SPRING_EPSILON && velocity.abs() < SPRING_EPSILON
+            }
+        }
+    }
+
+    /// Heads for `end` from wherever the animation is at `now`. Springs keep their velocity,
+    /// eased curves start over from the current value.
+    pub fn retarget(&mut self, now: Instant, end: f64) {
+        let value = self.value(now);
+        self.initial_velocity = match self.curve {
+            Curve::Eased { .. } => 0.0,
+            Curve::Spring(_) => self.velocity(now),
+        };
+        self.start_time = now;
+        self.start_val = value;
+        self.end_val = end;
+    }
+}
+
+/// What a window animation drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Property {
+    Opacity,
+    /// Zoom around the window centre.
+    Scale,
+    /// Displacement from the mapped location.
+    PositionX,
+    PositionY,
+    /// Stretch relative to the committed size.
+    Width,
+    Height,
+}
+
+impl Property {
+    /// Value when nothing is animating.
+    fn rest(self) -> f64 {
+        match self {
+            Property::Opacity | Property::Scale | Property::Width | Property::Height => 1.0,
+            Property::PositionX | Property::PositionY => 0.0,
+        }
     }
 }
 
 pub struct WindowAnimationState {
     pub alpha: f64,
-    pub animations: HashMap<String, Animation>, // e.g. "fade", "scale"
+    /// Zoom around the window centre, used when opening and closing.
+    pub scale: f64,
+    /// Displacement from the mapped location, used when the window is moved.
+    pub offset: Point<f64, Logical>,
+    /// Stretch from the top-left corner, relative to the committed size, used when resized.
+    pub resize: (f64, f64),
+    pub animations: HashMap<Property, Animation>,
 }
 
 impl Default for WindowAnimationState {
     fn default() -> Self {
         Self {
             alpha: 1.0,
+            scale: 1.0,
+            offset: Point::default(),
+            resize: (1.0, 1.0),
             animations: HashMap::new(),
         }
     }
 }
 
+impl WindowAnimationState {
+    fn current(&self, property: Property) -> f64 {
+        match property {
+            Property::Opacity => self.alpha,
+            Property::Scale => self.scale,
+            Property::PositionX => self.offset.x,
+            Property::PositionY => self.offset.y,
+            Property::Width => self.resize.0,
+            Property::Height => self.resize.1,
+        }
+    }
+
+    fn set(&mut self, property: Property, value: f64) {
+        match property {
+            Property::Opacity => self.alpha = value,
+            Property::Scale => self.scale = value,
+            Property::PositionX => self.offset.x = value,
+            Property::PositionY => self.offset.y = value,
+            Property::Width => self.resize.0 = value,
+            Property::Height => self.resize.1 = value,
+        }
+    }
+
+    /// Eases `property` from its current value to `end`.
+    fn ease_to(&mut self, property: Property, end: f64, duration: Duration, easing: Easing) {
+        let start = self.current(property);
+        self.animations.insert(property, Animation::eased(start, end, duration, easing));
+    }
+
+    /// Springs `property` back to rest after it jumped by `delta`, keeping the velocity of any
+    /// animation already in flight.
+    fn spring_back(&mut self, property: Property, delta: f64, spring: Spring) {
+        let now = Instant::now();
+        let current = self.current(property);
+        let animation = self.animations.entry(property).or_insert_with(|| {
+            Animation::starting_at(now, current, property.rest(), 0.0, Curve::Spring(spring))
+        });
+        animation.retarget(now, property.rest());
+        animation.curve = Curve::Spring(spring);
+        animation.start_val += delta;
+        let start = animation.start_val;
+        self.set(property, start);
+    }
+}
+
+/// Scale an opening window grows from, and a closing one shrinks to.
+const OPEN_CLOSE_SCALE: f64 = 0.9;
+
+/// A window that is gone from the space but still fading out.
+pub struct ClosingWindow {
+    pub window: Window,
+    pub location: Point<i32, Logical>,
+}
+
 pub struct AnimationManager {
     pub states: HashMap<Window, WindowAnimationState>,
+    pub closing: Vec<ClosingWindow>,
+    pub fade_in: Duration,
+    pub fade_out: Duration,
+    pub movement: Spring,
+    pub resize: Spring,
 }
 
 impl AnimationManager {
     pub fn new() -> Self {
-        Self { states: HashMap::new() }
+        Self {
+            states: HashMap::new(),
+            closing: Vec::new(),
+            fade_in: Duration::from_millis(250),
+            fade_out: Duration::from_millis(250),
+            movement: Spring::settling_in(Duration::from_millis(200)),
+            resize: Spring::settling_in(Duration::from_millis(200)),
+        }
+    }
+
+    pub fn set_durations(&mut self, config: &AnimationConfig) {
+        self.fade_in = config.window_open;
+        self.fade_out = config.window_close;
+        self.movement = Spring::settling_in(config.window_move);
+        self.resize = Spring::settling_in(config.window_resize);
     }
-    
+
     pub fn start_fade_in(&mut self, window: &Window) {
         let state = self.states.entry(window.clone()).or_default();
-        state.animations.insert("fade".to_string(), Animation::new(0.0, 1.0, 250));
+        state.alpha = 0.0;
+        state.scale = OPEN_CLOSE_SCALE;
+        state.ease_to(Property::Opacity, 1.0, self.fade_in, Easing::EASE_OUT);
+        state.ease_to(Property::Scale, 1.0, self.fade_in, Easing::EASE_OUT_CUBIC);
     }
-    
-    pub fn start_fade_out(&mut self, window: &Window) {
+
+    /// Fades out a window that has just left the space at `location`.
+    pub fn start_fade_out(&mut self, window: &Window, location: Point<i32, Logical>) {
         let state = self.states.entry(window.clone()).or_default();
-        state.animations.insert("fade".to_string(), Animation::new(1.0, 0.0, 250));
+        state.ease_to(Property::Opacity, 0.0, self.fade_out, Easing::EASE_IN);
+        state.ease_to(Property::Scale, OPEN_CLOSE_SCALE, self.fade_out, Easing::EASE_IN_CUBIC);
+        self.closing.retain(|closing| &closing.window != window);
+        self.closing.push(ClosingWindow {
+            window: window.clone(),
+            location,
+        });
+    }
+
+    /// Slides a window that was just mapped at `to` from where it was drawn at `from`.
+    pub fn start_move(&mut self, window: &Window, from: Point<i32, Logical>, to: Point<i32, Logical>) {
+        if from == to {
+            return;
+        }
+        let state = self.states.entry(window.clone()).or_default();
+        let delta = (from - to).to_f64();
+        state.spring_back(Property::PositionX, delta.x, self.movement);
+        state.spring_back(Property::PositionY, delta.y, self.movement);
+    }
+
+    /// Stretches a window that just committed `to` from its previous size `from`.
+    pub fn start_resize(&mut self, window: &Window, from: Size<i32, Logical>, to: Size<i32, Logical>) {
+        if from == to || from.is_empty() || to.is_empty() {
+            return;
+        }
+        let state = self.states.entry(window.clone()).or_default();
+        // The stretch is relative to the new size, so the old size is a ratio away from rest.
+        let (width, height) = state.resize;
+        let delta_w = width * from.w as f64 / to.w as f64 - width;
+        let delta_h = height * from.h as f64 / to.h as f64 - height;
+        state.spring_back(Property::Width, delta_w, self.resize);
+        state.spring_back(Property::Height, delta_h, self.resize);
     }
 
     pub fn tick(&mut self) {
         let now = Instant::now();
-        
+
         for state in self.states.values_mut() {
-            // Processing "fade"
-            if let Some(anim) = state.animations.get("fade") {
-                state.alpha = anim.value(now);
+            let values: Vec<(Property, f64)> = state
+                .animations
+                .iter()
+                .map(|(property, animation)| {
+                    let value = if animation.is_done(now) { animation.end_val } else { animation.value(now) };
+                    (*property, value)
+                })
+                .collect();
+            for (property, value) in values {
+                state.set(property, value);
             }
             // Cleanup done animations
             state.animations.retain(|_, anim| !anim.is_done(now));
         }
+
+        let states = &self.states;
+        self.closing
+            .retain(|closing| states.get(&closing.window).is_some_and(|state| !state.animations.is_empty()));
+        let closing = &self.closing;
+        self.states.retain(|window, state| {
+            !state.animations.is_empty() || closing.iter().any(|c| &c.window == window)
+        });
+    }
+
+    /// Whether anything is still moving, so the output has to keep being redrawn.
+    pub fn is_animating(&self) -> bool {
+        !self.states.is_empty() || !self.closing.is_empty()
+    }
+
+    pub fn get(&self, window: &Window) -> Option<&WindowAnimationState> {
+        self.states.get(window)
     }
-    
+
     pub fn get_alpha(&self, window: &Window) -> f32 {
         self.states.get(window).map(|s| s.alpha as f32).unwrap_or(1.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn linear_easing_interpolates() {
+        let start = Instant::now();
+        let curve = Curve::Eased { easing: Easing::Linear, duration: Duration::from_millis(100) };
+        let animation = Animation::starting_at(start, 10.0, 20.0, 0.0, curve);
+        assert_eq!(animation.value(start), 10.0);
+        assert!((animation.value(at(start, 50)) - 15.0).abs() < 1e-9);
+        assert_eq!(animation.value(at(start, 100)), 20.0);
+        assert_eq!(animation.value(at(start, 500)), 20.0);
+        assert!(!animation.is_done(at(start, 99)));
+        assert!(animation.is_done(at(start, 100)));
+    }
+
+    #[test]
+    fn bezier_hits_end_points_and_is_monotonic() {
+        let easings = [
+            Easing::EASE,
+            Easing::EASE_IN,
+            Easing::EASE_OUT,
+            Easing::EASE_IN_OUT,
+            Easing::EASE_IN_CUBIC,
+            Easing::EASE_OUT_CUBIC,
+        ];
+        for easing in easings {
+            assert_eq!(easing.ease(0.0), 0.0);
+            assert_eq!(easing.ease(1.0), 1.0);
+            let mut last = 0.0;
+            for i in 1..=100 {
+                let value = easing.ease(i as f64 / 100.0);
+                assert!(value >= last - 1e-9, "{:?} is not monotonic at {}", easing, i);
+                last = value;
+            }
+        }
+    }
+
+    #[test]
+    fn bezier_matches_known_values() {
+        // Control points on the diagonal give a straight line.
+        let diagonal = CubicBezier::new(1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0);
+        for x in [0.1, 0.25, 0.5, 0.9] {
+            assert!((diagonal.ease(x) - x).abs() < 1e-6);
+        }
+        // ease-in-out is point symmetric around the midpoint.
+        assert!((Easing::EASE_IN_OUT.ease(0.5) - 0.5).abs() < 1e-6);
+        assert!((Easing::EASE_IN_OUT.ease(0.2) + Easing::EASE_IN_OUT.ease(0.8) - 1.0).abs() < 1e-6);
+        // ease-out runs ahead of linear, ease-in behind it.
+        assert!(Easing::EASE_OUT.ease(0.3) > 0.3);
+        assert!(Easing::EASE_IN.ease(0.3) < 0.3);
+    }
+
+    #[test]
+    fn spring_settles_without_overshoot() {
+        let start = Instant::now();
+        let spring = Spring::settling_in(Duration::from_millis(200));
+        let animation = Animation::starting_at(start, 100.0, 0.0, 0.0, Curve::Spring(spring));
+        assert_eq!(animation.value(start), 100.0);
+
+        let mut last = 100.0;
+        for millis in (10..=400).step_by(10) {
+            let value = animation.value(at(start, millis));
+            assert!(value >= 0.0, "overshot at {}ms", millis);
+            assert!(value <= last, "moved backwards at {}ms", millis);
+            last = value;
+        }
+        assert!(!animation.is_done(at(start, 50)));
+        assert!(animation.is_done(at(start, 1000)));
+    }
+
+    #[test]
+    fn spring_velocity_matches_value() {
+        let start = Instant::now();
+        let animation = Animation::starting_at(start, 0.0, 1.0, 3.0, Curve::Spring(Spring::new(400.0)));
+        assert!((animation.velocity(start) - 3.0).abs() < 1e-9);
+
+        let now = at(start, 40);
+        let dt = Duration::from_micros(10);
+        let numeric = (animation.value(now + dt) - animation.value(now)) / dt.as_secs_f64();
+        assert!((animation.velocity(now) - numeric).abs() < 1e-2);
+    }
+
+    #[test]
+    fn spring_retarget_keeps_value_and_velocity() {
+        let start = Instant::now();
+        let mut animation = Animation::starting_at(start, 0.0, 100.0, 0.0, Curve::Spring(Spring::new(200.0)));
+        let now = at(start, 60);
+        let value = animation.value(now);
+        let velocity = animation.velocity(now);
+        assert!(velocity > 0.0);
+
+        animation.retarget(now, -50.0);
+        assert!((animation.value(now) - value).abs() < 1e-9);
+        assert!((animation.velocity(now) - velocity).abs() < 1e-9);
+        assert!(animation.is_done(at(start, 10_000)));
+        assert!((animation.value(at(start, 10_000)) + 50.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn eased_retarget_restarts_from_current_value() {
+        let start = Instant::now();
+        let curve = Curve::Eased { easing: Easing::Linear, duration: Duration::from_millis(100) };
+        let mut animation = Animation::starting_at(start, 0.0, 1.0, 0.0, curve);
+        let now = at(start, 50);
+        animation.retarget(now, 0.0);
+        assert!((animation.value(now) - 0.5).abs() < 1e-9);
+        assert!((animation.value(at(start, 100)) - 0.25).abs() < 1e-9);
+        assert_eq!(animation.value(at(start, 150)), 0.0);
+    }
+}
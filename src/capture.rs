@@ -0,0 +1,370 @@
+//! Fills screencopy frames from both capture protocols.
+//!
+//! Frames are queued by the protocol handlers and filled by the backend right after it renders,
+//! with the same elements that went on screen. Each session has its own damage tracker, so frames
+//! that should only be sent once something changed wait until it does. Window captures render
+//! the window on its own at full size, whether it is shown or not. Thumbnail captures are copied
+//! from the thumbnail cache and wait for the next thumbnail instead.
+
+use std::time::Duration;
+
+use smithay::{
+    backend::renderer::{
+        Bind, ExportMem, ImportAll, ImportMem, Offscreen, Renderer,
+        damage::OutputDamageTracker,
+        element::{AsRenderElements, surface::WaylandSurfaceRenderElement},
+    },
+    desktop::Window,
+    output::Output,
+    reexports::{
+        wayland_protocols::ext::image_copy_capture::v1::server::ext_image_copy_capture_frame_v1::FailureReason,
+        wayland_server::protocol::wl_buffer::WlBuffer,
+    },
+    utils::{Buffer, Clock, Monotonic, Physical, Point, Rectangle, Scale, Size, Transform},
+    wayland::shm::with_buffer_contents_mut,
+};
+
+use crate::protocols::image_copy_capture::{CaptureFrame, ImageCaptureSource};
+use crate::protocols::is_capture_buffer;
+use crate::protocols::screencopy::Screencopy;
+use crate::render::{
+    OutputRenderElements, Pixels, RenderCache, cursor_elements, lock_elements, output_elements, read_texture,
+    render_to_memory,
+};
+use crate::state::NanaimoState;
+use crate::thumbnails::{ThumbnailCache, thumbnail_size};
+
+pub enum PendingCapture {
+    Wlr(Screencopy),
+    Ext(CaptureFrame),
+}
+
+impl PendingCapture {
+    fn is_alive(&self) -> bool {
+        match self {
+            PendingCapture::Wlr(frame) => frame.is_alive(),
+            PendingCapture::Ext(frame) => frame.is_alive(),
+        }
+    }
+}
+
+/// An output as it is on screen, with what changed since the session's last frame.
+struct OutputFrame<R: ImportAll + ImportMem> {
+    elements: Vec<OutputRenderElements<R>>,
+    damage: Vec<Rectangle<i32, Buffer>>,
+}
+
+impl NanaimoState {
+    /// Fills every pending capture that has something to show. Ones waiting for damage stay
+    /// queued for the next frame.
+    pub fn process_captures<R>(&mut self, renderer: &mut R, cache: &mut RenderCache<R>, thumbnails: &ThumbnailCache<R>)
+    where
+        R: Renderer + ImportAll + ImportMem + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+        R::TextureId: Clone + Send + 'static,
+    {
+        let time: Duration = Clock::<Monotonic>::new().now().into();
+        let mut waiting = Vec::new();
+
+        for capture in std::mem::take(&mut self.pending_captures) {
+            if !capture.is_alive() {
+                continue;
+            }
+            match capture {
+                PendingCapture::Wlr(frame) => {
+                    let output = frame.output.clone();
+                    let Some(contents) = self.output_frame(renderer, cache, &output, frame.overlay_cursor, frame.session)
+                    else {
+                        continue;
+                    };
+                    if frame.with_damage && contents.damage.is_empty() {
+                        waiting.push(PendingCapture::Wlr(frame));
+                        continue;
+                    }
+                    match copy_output(renderer, &output, &contents, frame.region, &frame.buffer) {
+                        Ok(()) => {
+                            let region = frame.region;
+                            frame.damage(contents.damage.iter().filter_map(|rect| {
+                                let rect = rect.intersection(region)?;
+                                Some(Rectangle::new(rect.loc - region.loc, rect.size))
+                            }));
+                            frame.submit(time);
+                        }
+                        Err(err) => tracing::warn!("Screencopy of {} failed: {}", output.name(), err),
+                    }
+                }
+                PendingCapture::Ext(frame) => {
+                    if let ImageCaptureSource::Toplevel(id) = frame.source {
+                        if let Some(frame) = self.capture_toplevel(renderer, frame, id, time) {
+                            waiting.push(PendingCapture::Ext(frame));
+                        }
+                        continue;
+                    }
+                    if let ImageCaptureSource::Thumbnail(id) = frame.source {
+                        if let Some(frame) = self.capture_thumbnail(renderer, thumbnails, frame, id, time) {
+                            waiting.push(PendingCapture::Ext(frame));
+                        }
+                        continue;
+                    }
+                    let ImageCaptureSource::Output(output) = &frame.source else {
+                        frame.fail(FailureReason::Stopped);
+                        continue;
+                    };
+                    let Some(output) = output.upgrade() else {
+                        frame.fail(FailureReason::Stopped);
+                        continue;
+                    };
+                    let Some(contents) = self.output_frame(renderer, cache, &output, frame.paint_cursors, frame.session)
+                    else {
+                        frame.fail(FailureReason::Stopped);
+                        continue;
+                    };
+                    if contents.damage.is_empty() {
+                        waiting.push(PendingCapture::Ext(frame));
+                        continue;
+                    }
+                    let Some(mode) = output.current_mode() else {
+                        continue;
+                    };
+                    let region = Rectangle::from_size(mode.size.to_logical(1).to_buffer(1, Transform::Normal));
+                    match copy_output(renderer, &output, &contents, region, &frame.buffer) {
+                        Ok(()) => frame.success(output.current_transform(), contents.damage, time),
+                        Err(err) => tracing::warn!("Capture of {} failed: {}", output.name(), err),
+                    }
+                }
+            }
+        }
+
+        self.pending_captures = waiting;
+    }
+
+    /// The size window captures of `window` have: its bounding box with popups, at the scale of
+    /// the output it is on.
+    pub fn window_capture_size(&self, window: &Window) -> Option<Size<i32, Physical>> {
+        let bbox = window.bbox_with_popups();
+        if bbox.is_empty() {
+            return None;
+        }
+        Some(bbox.size.to_physical_precise_round(self.window_scale(window)))
+    }
+
+    /// Renders window `id` into a toplevel capture frame, or hands the frame back if nothing
+    /// changed since the session's last frame.
+    fn capture_toplevel<R>(&mut self, renderer: &mut R, frame: CaptureFrame, id: u64, time: Duration) -> Option<CaptureFrame>
+    where
+        R: Renderer + ImportAll + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+        R::TextureId: Clone + 'static,
+    {
+        let Some((window, _, _)) = self.find_window(id) else {
+            frame.fail(FailureReason::Stopped);
+            return None;
+        };
+        if self.is_locked() {
+            return Some(frame);
+        }
+        let Some(size) = self.window_capture_size(&window) else {
+            return Some(frame);
+        };
+        let region = Rectangle::from_size(size.to_logical(1).to_buffer(1, Transform::Normal));
+        if !is_capture_buffer(&frame.buffer, region.size) {
+            frame.fail(FailureReason::BufferConstraints);
+            return None;
+        }
+
+        let scale = self.window_scale(&window);
+        let location: Point<i32, Physical> = (-window.bbox_with_popups().loc).to_physical_precise_round(scale);
+        let elements = window.render_elements::<WaylandSurfaceRenderElement<R>>(renderer, location, Scale::from(scale), 1.0);
+
+        // A resized window starts over with full damage, the session already announced the new size.
+        let (tracked, damage_tracker) = self
+            .window_capture_damage
+            .entry(frame.session)
+            .or_insert_with(|| ((size, scale), OutputDamageTracker::new(size, scale, Transform::Normal)));
+        if *tracked != (size, scale) {
+            *tracked = (size, scale);
+            *damage_tracker = OutputDamageTracker::new(size, scale, Transform::Normal);
+        }
+        let damage: Vec<_> = match damage_tracker.damage_output(1, &elements) {
+            Ok((damage, _)) => damage
+                .into_iter()
+                .flatten()
+                .map(|rect| rect.to_logical(1).to_buffer(1, Transform::Normal, &size.to_logical(1)))
+                .collect(),
+            Err(err) => {
+                tracing::warn!("Failed to damage capture of window {}: {:?}", id, err);
+                return Some(frame);
+            }
+        };
+        if damage.is_empty() {
+            return Some(frame);
+        }
+
+        let copied = render_to_memory(renderer, size, scale, Transform::Normal, &elements, region)
+            .and_then(|pixels| copy_to_shm(&pixels, &frame.buffer));
+        match copied {
+            Ok(()) => frame.success(Transform::Normal, damage, time),
+            Err(err) => tracing::warn!("Capture of window {} failed: {}", id, err),
+        }
+
+        // Windows on other workspaces and minimized ones get no frame callbacks from an output,
+        // so the capture keeps them drawing.
+        if !self.space.elements().any(|w| w == &window) {
+            if let Some(output) = self.space.outputs().next() {
+                window.send_frame(output, time, None, |_, _| Some(output.clone()));
+            }
+        }
+        None
+    }
+
+    /// Copies the thumbnail of window `id` into a thumbnail capture frame, or hands the frame back
+    /// if the thumbnail hasn't changed since the session's last frame.
+    fn capture_thumbnail<R>(
+        &mut self,
+        renderer: &mut R,
+        thumbnails: &ThumbnailCache<R>,
+        frame: CaptureFrame,
+        id: u64,
+        time: Duration,
+    ) -> Option<CaptureFrame>
+    where
+        R: Renderer + ImportAll + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+        R::TextureId: Clone + 'static,
+    {
+        let Some((window, _, _)) = self.find_window(id) else {
+            frame.fail(FailureReason::Stopped);
+            return None;
+        };
+        let (Some(thumbnail), Some(size)) = (thumbnails.get(&window), thumbnail_size(&window)) else {
+            return Some(frame);
+        };
+        if !is_capture_buffer(&frame.buffer, size) {
+            frame.fail(FailureReason::BufferConstraints);
+            return None;
+        }
+        // A resized window's thumbnail is re-rendered shortly, the session already announced the
+        // new size.
+        if thumbnail.size != size || self.capture_thumbnails.get(&frame.session) == Some(&thumbnail.updated()) {
+            return Some(frame);
+        }
+
+        let copied = read_texture(renderer, &thumbnail.texture, size).and_then(|pixels| copy_to_shm(&pixels, &frame.buffer));
+        match copied {
+            Ok(()) => {
+                self.capture_thumbnails.insert(frame.session, thumbnail.updated());
+                frame.success(Transform::Normal, [Rectangle::from_size(size)], time);
+            }
+            Err(err) => tracing::warn!("Thumbnail capture of window {} failed: {}", id, err),
+        }
+        None
+    }
+
+    /// Builds what `output` shows right now and damages it against the session's previous frame.
+    fn output_frame<R>(
+        &mut self,
+        renderer: &mut R,
+        cache: &mut RenderCache<R>,
+        output: &Output,
+        with_cursor: bool,
+        session: u64,
+    ) -> Option<OutputFrame<R>>
+    where
+        R: Renderer + ImportAll + ImportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+        R::TextureId: Clone + Send + 'static,
+    {
+        if !self.space.outputs().any(|o| o == output) {
+            return None;
+        }
+        let mode = output.current_mode()?;
+
+        let custom_elements = if with_cursor {
+            let output_location = output.current_location();
+            let pointer_location = self.pointer.current_location();
+            let mut elements = cursor_elements(
+                renderer,
+                output,
+                output_location,
+                pointer_location,
+                &self.cursor_status,
+                &mut self.cursor_theme,
+                self.start_time.elapsed(),
+            );
+            elements.extend(self.tablet_cursor_elements(renderer, output));
+            elements
+        } else {
+            Vec::new()
+        };
+        let elements = if self.is_locked() {
+            lock_elements(renderer, output, self.lock_surface(output), custom_elements)
+        } else {
+            output_elements(output, &self.space, &self.animation_manager, cache, renderer, custom_elements).elements
+        };
+
+        let damage_tracker = self
+            .capture_damage
+            .entry((session, output.name()))
+            .or_insert_with(|| OutputDamageTracker::from_output(output));
+        let (damage, _) = match damage_tracker.damage_output(1, &elements) {
+            Ok(result) => result,
+            Err(err) => {
+                tracing::warn!("Failed to damage capture of {}: {:?}", output.name(), err);
+                return None;
+            }
+        };
+
+        // Damage comes in output coordinates, frames are in the untransformed mode orientation.
+        let transform = output.current_transform();
+        let area = transform.transform_size(mode.size).to_logical(1);
+        let damage = damage
+            .into_iter()
+            .flatten()
+            .map(|rect| rect.to_logical(1).to_buffer(1, transform, &area))
+            .collect();
+
+        Some(OutputFrame { elements, damage })
+    }
+}
+
+/// Renders `contents` like `output` would and writes `region` of it into the shm `buffer`.
+fn copy_output<R>(
+    renderer: &mut R,
+    output: &Output,
+    contents: &OutputFrame<R>,
+    region: Rectangle<i32, Buffer>,
+    buffer: &WlBuffer,
+) -> Result<(), String>
+where
+    R: Renderer + ImportAll + ImportMem + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+    R::TextureId: Clone + Send + 'static,
+{
+    let mode = output.current_mode().ok_or("output has no mode")?;
+    let pixels = render_to_memory(
+        renderer,
+        mode.size,
+        output.current_scale().fractional_scale(),
+        output.current_transform(),
+        &contents.elements,
+        region,
+    )?;
+    copy_to_shm(&pixels, buffer)
+}
+
+/// Writes RGBA `pixels` into a little-endian ARGB/XRGB8888 shm buffer of the same size.
+fn copy_to_shm(pixels: &Pixels, buffer: &WlBuffer) -> Result<(), String> {
+    with_buffer_contents_mut(buffer, |ptr, len, data| {
+        let width = pixels.size.w as usize;
+        let height = pixels.size.h as usize;
+        let offset = data.offset as usize;
+        let stride = data.stride as usize;
+        if data.width != pixels.size.w || data.height != pixels.size.h || offset + stride * height > len {
+            return Err("buffer does not fit the frame".to_string());
+        }
+
+        let contents = unsafe { std::slice::from_raw_parts_mut(ptr.add(offset), stride * height) };
+        for (src, dst) in pixels.rgba.chunks_exact(width * 4).zip(contents.chunks_exact_mut(stride)) {
+            for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+                dst.copy_from_slice(&[src[2], src[1], src[0], src[3]]);
+            }
+        }
+        Ok(())
+    })
+    .map_err(|err| format!("{:?}", err))?
+}
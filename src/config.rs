@@ -0,0 +1,902 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/nanaimo/config.toml`.
+//!
+//! The file is deserialized into `Raw*` structs that mirror the TOML layout, then validated
+//! into [`Config`]. Validation collects every problem it finds so a single reload reports all
+//! mistakes at once, each prefixed with the key it came from.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use smithay::{
+    input::keyboard::{ModifiersState, XkbConfig, keysyms, xkb},
+    reexports::calloop::{
+        LoopHandle, channel,
+        timer::{TimeoutAction, Timer},
+    },
+};
+
+use crate::actions::Action;
+use crate::ipc::IpcEvent;
+use crate::output::{OutputConfig, parse_transform};
+use crate::state::NanaimoState;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "could not parse {}: {}", path.display(), err),
+            ConfigError::Invalid(path, errors) => {
+                write!(f, "invalid configuration in {}:", path.display())?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardConfig {
+    pub rules: String,
+    pub model: String,
+    /// Layouts `switch-layout` cycles through, each with its variant. Never empty.
+    pub layouts: Vec<(String, String)>,
+    pub options: Option<String>,
+    pub repeat_delay: i32,
+    pub repeat_rate: i32,
+    /// Whether each window keeps the layout it was last typed in.
+    pub per_window_layout: bool,
+}
+
+impl KeyboardConfig {
+    /// The keymap with layout `index` active, clamped to the configured layouts.
+    pub fn xkb_config(&self, index: usize) -> XkbConfig<'_> {
+        let (layout, variant) = &self.layouts[index.min(self.layouts.len() - 1)];
+        XkbConfig {
+            rules: &self.rules,
+            model: &self.model,
+            layout,
+            variant,
+            options: self.options.clone(),
+        }
+    }
+}
+
+/// A key combination bound to a compositor action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keybinding {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+    pub keysym: xkb::Keysym,
+    pub action: Action,
+}
+
+impl Keybinding {
+    pub fn matches(&self, modifiers: &ModifiersState, keysym: xkb::Keysym) -> bool {
+        self.ctrl == modifiers.ctrl
+            && self.alt == modifiers.alt
+            && self.shift == modifiers.shift
+            && self.logo == modifiers.logo
+            && self.keysym == keysym
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A touchpad swipe with a number of fingers bound to a compositor action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GestureBinding {
+    pub fingers: u32,
+    pub direction: SwipeDirection,
+    pub action: Action,
+}
+
+/// How keyboard focus follows the pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusPolicy {
+    /// Windows are focused by clicking them.
+    Click,
+    /// The window under the pointer is focused; empty space focuses nothing.
+    FollowsMouse,
+    /// Like `FollowsMouse`, but focus stays put over empty space.
+    Sloppy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FocusConfig {
+    pub policy: FocusPolicy,
+    /// Whether windows focused by hovering are raised. Clicking always raises.
+    pub raise: bool,
+    /// How long the pointer must stay on a hover-focused window before it is raised.
+    pub raise_delay: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppearanceConfig {
+    /// Colour drawn behind all windows, as RGBA.
+    pub background: [f32; 4],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationConfig {
+    pub window_open: Duration,
+    pub window_close: Duration,
+    pub window_move: Duration,
+    pub window_resize: Duration,
+}
+
+/// Settings for the Auto area. The shell draws it and reads these over IPC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoConfig {
+    pub width: i32,
+    pub glow_color: [f32; 4],
+    pub glow_intensity: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub keyboard: KeyboardConfig,
+    pub outputs: HashMap<String, OutputConfig>,
+    pub keybindings: Vec<Keybinding>,
+    pub gestures: Vec<GestureBinding>,
+    pub focus: FocusConfig,
+    pub appearance: AppearanceConfig,
+    pub animations: AnimationConfig,
+    pub auto: AutoConfig,
+    /// Absolute paths of the executables allowed to create virtual keyboards and pointers and to
+    /// act as input methods.
+    pub virtual_input_clients: Vec<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let raw: RawConfig = toml::from_str("").expect("empty config must parse");
+        raw.validate().expect("default config must be valid")
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    input: RawInput,
+    outputs: HashMap<String, RawOutput>,
+    keybindings: Option<HashMap<String, String>>,
+    gestures: Option<HashMap<String, String>>,
+    focus: RawFocus,
+    appearance: RawAppearance,
+    animations: RawAnimations,
+    auto: RawAuto,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawInput {
+    keyboard: RawKeyboard,
+    virtual_input_clients: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawKeyboard {
+    rules: String,
+    model: String,
+    layout: String,
+    variant: String,
+    options: Option<String>,
+    repeat_delay: i32,
+    repeat_rate: i32,
+    per_window_layout: bool,
+}
+
+impl Default for RawKeyboard {
+    fn default() -> Self {
+        Self {
+            rules: String::new(),
+            model: String::new(),
+            layout: "us".into(),
+            variant: String::new(),
+            options: None,
+            repeat_delay: 200,
+            repeat_rate: 25,
+            per_window_layout: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawOutput {
+    enabled: bool,
+    transform: String,
+    scale: f64,
+    position: Option<[i32; 2]>,
+    /// Refresh rate in Hz.
+    refresh: Option<f64>,
+    blank_after_minutes: Option<u64>,
+}
+
+impl Default for RawOutput {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            transform: "normal".into(),
+            scale: 1.0,
+            position: None,
+            refresh: None,
+            blank_after_minutes: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAppearance {
+    background: String,
+}
+
+impl Default for RawAppearance {
+    fn default() -> Self {
+        Self {
+            background: "#1a1a1a".into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawFocus {
+    policy: String,
+    raise: bool,
+    raise_delay_ms: u64,
+}
+
+impl Default for RawFocus {
+    fn default() -> Self {
+        Self {
+            policy: "click".into(),
+            raise: true,
+            raise_delay_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAnimations {
+    window_open_ms: u64,
+    window_close_ms: u64,
+    window_move_ms: u64,
+    window_resize_ms: u64,
+}
+
+impl Default for RawAnimations {
+    fn default() -> Self {
+        Self {
+            window_open_ms: 250,
+            window_close_ms: 250,
+            window_move_ms: 200,
+            window_resize_ms: 200,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAuto {
+    width: i32,
+    glow_color: String,
+    glow_intensity: f32,
+}
+
+impl Default for RawAuto {
+    fn default() -> Self {
+        Self {
+            width: 360,
+            glow_color: "#7aa2f7".into(),
+            glow_intensity: 0.6,
+        }
+    }
+}
+
+const DEFAULT_KEYBINDINGS: &[(&str, &str)] = &[
+    ("Super+Shift+Q", "close"),
+    ("Super+Shift+E", "quit"),
+    ("Super+Shift+R", "reload-config"),
+    ("Print", "screenshot"),
+    ("Super+Print", "screenshot-window"),
+    ("Alt+Tab", "focus-next"),
+    ("Alt+Shift+Tab", "focus-previous"),
+    ("Super+Space", "switch-layout"),
+    ("Super+1", "workspace 1"),
+    ("Super+2", "workspace 2"),
+    ("Super+3", "workspace 3"),
+    ("Super+4", "workspace 4"),
+    ("Super+Shift+1", "move-to-workspace 1"),
+    ("Super+Shift+2", "move-to-workspace 2"),
+    ("Super+Shift+3", "move-to-workspace 3"),
+    ("Super+Shift+4", "move-to-workspace 4"),
+];
+
+const DEFAULT_GESTURES: &[(&str, &str)] = &[
+    ("swipe-3-left", "workspace-next"),
+    ("swipe-3-right", "workspace-previous"),
+    ("swipe-4-up", "toggle-auto"),
+];
+
+impl RawConfig {
+    fn validate(self) -> Result<Config, Vec<String>> {
+        let mut errors = Vec::new();
+
+        let keyboard = self.input.keyboard;
+        if keyboard.repeat_delay < 0 {
+            errors.push("input.keyboard.repeat_delay: must not be negative".into());
+        }
+        if keyboard.repeat_rate < 0 {
+            errors.push("input.keyboard.repeat_rate: must not be negative".into());
+        }
+        // Comma-separated like in xkb, but each layout gets a keymap of its own.
+        let layouts: Vec<&str> = keyboard.layout.split(',').map(str::trim).collect();
+        let variants: Vec<&str> = keyboard.variant.split(',').map(str::trim).collect();
+        if layouts.len() > 1 && layouts.iter().any(|layout| layout.is_empty()) {
+            errors.push("input.keyboard.layout: layouts must not be empty".into());
+        }
+        if variants.len() > layouts.len() {
+            errors.push(format!(
+                "input.keyboard.variant: {} variants given for {} layouts",
+                variants.len(),
+                layouts.len()
+            ));
+        }
+        let layouts = layouts
+            .iter()
+            .enumerate()
+            .map(|(i, layout)| (layout.to_string(), variants.get(i).unwrap_or(&"").to_string()))
+            .collect();
+        let keyboard = KeyboardConfig {
+            rules: keyboard.rules,
+            model: keyboard.model,
+            layouts,
+            options: keyboard.options,
+            repeat_delay: keyboard.repeat_delay,
+            repeat_rate: keyboard.repeat_rate,
+            per_window_layout: keyboard.per_window_layout,
+        };
+
+        for path in &self.input.virtual_input_clients {
+            if !path.is_absolute() {
+                errors.push(format!("input.virtual_input_clients: {:?} is not an absolute path", path));
+            }
+        }
+
+        let mut outputs = HashMap::new();
+        for (name, raw) in self.outputs {
+            let transform = parse_transform(&raw.transform).unwrap_or_else(|| {
+                errors.push(format!(
+                    "outputs.{}.transform: unknown transform {:?} (expected normal, 90, 180, 270, flipped, flipped-90, flipped-180 or flipped-270)",
+                    name, raw.transform
+                ));
+                Default::default()
+            });
+            if !(0.25..=10.0).contains(&raw.scale) {
+                errors.push(format!("outputs.{}.scale: {} is outside 0.25..=10", name, raw.scale));
+            }
+            if raw.refresh.is_some_and(|hz| hz <= 0.0) {
+                errors.push(format!("outputs.{}.refresh: must be positive", name));
+            }
+            if raw.blank_after_minutes == Some(0) {
+                errors.push(format!("outputs.{}.blank_after_minutes: must be positive", name));
+            }
+            outputs.insert(
+                name,
+                OutputConfig {
+                    enabled: raw.enabled,
+                    transform,
+                    scale: raw.scale,
+                    position: raw.position.map(|[x, y]| (x, y).into()),
+                    refresh: raw.refresh.map(|hz| (hz * 1000.0).round() as i32),
+                    blank_after: raw.blank_after_minutes.map(|minutes| Duration::from_secs(minutes * 60)),
+                },
+            );
+        }
+
+        let raw_bindings = self.keybindings.unwrap_or_else(|| {
+            DEFAULT_KEYBINDINGS
+                .iter()
+                .map(|(keys, action)| (keys.to_string(), action.to_string()))
+                .collect()
+        });
+        let mut keybindings = Vec::new();
+        for (keys, action) in raw_bindings {
+            let action = match action.parse::<Action>() {
+                Ok(action) => action,
+                Err(err) => {
+                    errors.push(format!("keybindings.{:?}: {}", keys, err));
+                    continue;
+                }
+            };
+            match parse_keybinding(&keys, action) {
+                Ok(binding) => keybindings.push(binding),
+                Err(err) => errors.push(format!("keybindings.{:?}: {}", keys, err)),
+            }
+        }
+
+        let raw_gestures = self.gestures.unwrap_or_else(|| {
+            DEFAULT_GESTURES
+                .iter()
+                .map(|(gesture, action)| (gesture.to_string(), action.to_string()))
+                .collect()
+        });
+        let mut gestures = Vec::new();
+        for (gesture, action) in raw_gestures {
+            let action = match action.parse::<Action>() {
+                Ok(action) => action,
+                Err(err) => {
+                    errors.push(format!("gestures.{:?}: {}", gesture, err));
+                    continue;
+                }
+            };
+            match parse_gesture(&gesture, action) {
+                Ok(binding) => gestures.push(binding),
+                Err(err) => errors.push(format!("gestures.{:?}: {}", gesture, err)),
+            }
+        }
+
+        let policy = match self.focus.policy.as_str() {
+            "click" => FocusPolicy::Click,
+            "follows-mouse" => FocusPolicy::FollowsMouse,
+            "sloppy" => FocusPolicy::Sloppy,
+            other => {
+                errors.push(format!(
+                    "focus.policy: unknown policy {:?} (expected click, follows-mouse or sloppy)",
+                    other
+                ));
+                FocusPolicy::Click
+            }
+        };
+
+        let background = parse_color(&self.appearance.background).unwrap_or_else(|err| {
+            errors.push(format!("appearance.background: {}", err));
+            [0.0, 0.0, 0.0, 1.0]
+        });
+
+        let glow_color = parse_color(&self.auto.glow_color).unwrap_or_else(|err| {
+            errors.push(format!("auto.glow_color: {}", err));
+            [1.0, 1.0, 1.0, 1.0]
+        });
+        if self.auto.width <= 0 {
+            errors.push("auto.width: must be positive".into());
+        }
+        if !(0.0..=1.0).contains(&self.auto.glow_intensity) {
+            errors.push("auto.glow_intensity: must be between 0 and 1".into());
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Config {
+            keyboard,
+            outputs,
+            keybindings,
+            gestures,
+            focus: FocusConfig {
+                policy,
+                raise: self.focus.raise,
+                raise_delay: Duration::from_millis(self.focus.raise_delay_ms),
+            },
+            appearance: AppearanceConfig { background },
+            animations: AnimationConfig {
+                window_open: Duration::from_millis(self.animations.window_open_ms),
+                window_close: Duration::from_millis(self.animations.window_close_ms),
+                window_move: Duration::from_millis(self.animations.window_move_ms),
+                window_resize: Duration::from_millis(self.animations.window_resize_ms),
+            },
+            auto: AutoConfig {
+                width: self.auto.width,
+                glow_color,
+                glow_intensity: self.auto.glow_intensity,
+            },
+            virtual_input_clients: self.input.virtual_input_clients,
+        })
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa` into RGBA components.
+fn parse_color(value: &str) -> Result<[f32; 4], String> {
+    let hex = value
+        .strip_prefix('#')
+        .ok_or_else(|| format!("{:?} is not a colour, expected #rrggbb or #rrggbbaa", value))?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{:?} is not a colour, expected #rrggbb or #rrggbbaa", value));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap() as f32 / 255.0;
+    let alpha = if hex.len() == 8 { channel(6) } else { 1.0 };
+    Ok([channel(0), channel(2), channel(4), alpha])
+}
+
+/// Parses a combination like `Super+Shift+Return`.
+fn parse_keybinding(keys: &str, action: Action) -> Result<Keybinding, String> {
+    let mut binding = Keybinding {
+        ctrl: false,
+        alt: false,
+        shift: false,
+        logo: false,
+        keysym: xkb::Keysym::new(keysyms::KEY_NoSymbol),
+        action,
+    };
+    let mut parts = keys.split('+').map(str::trim).peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            let keysym = xkb::keysym_from_name(part, xkb::KEYSYM_CASE_INSENSITIVE);
+            if keysym.raw() == keysyms::KEY_NoSymbol {
+                return Err(format!("unknown key {:?}", part));
+            }
+            binding.keysym = keysym;
+            break;
+        }
+        match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => binding.ctrl = true,
+            "alt" | "mod1" => binding.alt = true,
+            "shift" => binding.shift = true,
+            "super" | "logo" | "mod4" => binding.logo = true,
+            other => return Err(format!("unknown modifier {:?}", other)),
+        }
+    }
+    Ok(binding)
+}
+
+/// Parses gestures like `swipe-3-left`: a kind, a finger count and a direction.
+fn parse_gesture(gesture: &str, action: Action) -> Result<GestureBinding, String> {
+    let mut parts = gesture.split('-').map(str::trim);
+    let (Some("swipe"), Some(fingers), Some(direction), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("expected swipe-<fingers>-<left|right|up|down>".into());
+    };
+    let fingers = match fingers.parse::<u32>() {
+        Ok(fingers @ 3..=5) => fingers,
+        _ => return Err(format!("finger count {:?} is not between 3 and 5", fingers)),
+    };
+    let direction = match direction {
+        "left" => SwipeDirection::Left,
+        "right" => SwipeDirection::Right,
+        "up" => SwipeDirection::Up,
+        "down" => SwipeDirection::Down,
+        other => return Err(format!("unknown direction {:?}", other)),
+    };
+    Ok(GestureBinding {
+        fingers,
+        direction,
+        action,
+    })
+}
+
+pub fn config_dir() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("/etc/xdg"))
+        .join("nanaimo")
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+impl Config {
+    /// Loads the configuration from `path`. A missing file yields the defaults.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::info!("No config at {}, using defaults", path.display());
+                return Ok(Config::default());
+            }
+            Err(err) => return Err(ConfigError::Io(path.to_owned(), err)),
+        };
+        let raw: RawConfig = toml::from_str(&contents).map_err(|err| ConfigError::Parse(path.to_owned(), err))?;
+        raw.validate().map_err(|errors| ConfigError::Invalid(path.to_owned(), errors))
+    }
+
+    /// Loads the configuration at startup, falling back to the defaults on error.
+    pub fn load_or_default() -> Config {
+        let path = config_path();
+        Config::load(&path).unwrap_or_else(|err| {
+            tracing::error!("{}", err);
+            tracing::error!("Starting with the default configuration");
+            Config::default()
+        })
+    }
+}
+
+/// Watches the config directory and reloads shortly after the file changes.
+///
+/// The returned watcher must be kept alive for notifications to keep arriving.
+pub fn watch_config(loop_handle: &LoopHandle<'static, NanaimoState>) -> Option<notify::RecommendedWatcher> {
+    use notify::Watcher;
+
+    let dir = config_dir();
+    if !dir.is_dir() {
+        tracing::info!("{} does not exist, config reloading disabled", dir.display());
+        return None;
+    }
+
+    let (sender, receiver) = channel::channel::<()>();
+    let config_file = config_path();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            if event.paths.iter().any(|path| path == &config_file) {
+                let _ = sender.send(());
+            }
+        }
+    })
+    .map_err(|err| tracing::warn!("Could not create config watcher: {}", err))
+    .ok()?;
+    if let Err(err) = watcher.watch(&dir, notify::RecursiveMode::NonRecursive) {
+        tracing::warn!("Could not watch {}: {}", dir.display(), err);
+        return None;
+    }
+
+    // Editors usually write a file in several steps, so wait for things to settle.
+    let handle = loop_handle.clone();
+    let inserted = loop_handle.insert_source(receiver, move |event, _, state: &mut NanaimoState| {
+        if !matches!(event, channel::Event::Msg(())) || state.config_reload_pending {
+            return;
+        }
+        state.config_reload_pending = true;
+        let _ = handle.insert_source(Timer::from_duration(Duration::from_millis(100)), |_, _, state| {
+            state.config_reload_pending = false;
+            let _ = state.reload_config();
+            TimeoutAction::Drop
+        });
+    });
+    if let Err(err) = inserted {
+        tracing::warn!("Could not insert config watcher: {}", err);
+        return None;
+    }
+
+    tracing::info!("Watching {} for changes", dir.display());
+    Some(watcher)
+}
+
+impl NanaimoState {
+    /// Re-reads the config file and applies it. On error the previous configuration stays active.
+    pub fn reload_config(&mut self) -> Result<(), ConfigError> {
+        let path = config_path();
+        match Config::load(&path) {
+            Ok(config) => {
+                tracing::info!("Reloaded configuration from {}", path.display());
+                self.apply_config(config);
+                Ok(())
+            }
+            Err(err) => {
+                tracing::error!("{}", err);
+                tracing::error!("Keeping the previous configuration");
+                Err(err)
+            }
+        }
+    }
+
+    /// Applies `config` to the running compositor, touching only the parts that changed.
+    pub fn apply_config(&mut self, config: Config) {
+        let old = std::mem::replace(&mut self.config, config);
+
+        if old.keyboard != self.config.keyboard {
+            if old.keyboard.layouts != self.config.keyboard.layouts {
+                self.window_layouts.clear();
+            }
+            self.set_keyboard_layout(self.keyboard_layout);
+            let keyboard = self.seat.get_keyboard().unwrap();
+            let keyboard_config = &self.config.keyboard;
+            keyboard.change_repeat_info(keyboard_config.repeat_rate, keyboard_config.repeat_delay);
+        }
+
+        if old.outputs != self.config.outputs {
+            self.output_configs = self.config.outputs.clone();
+            for output in self.outputs.clone() {
+                self.apply_output_config(&output);
+            }
+            self.outputs_changed();
+        }
+
+        self.animation_manager.set_durations(&self.config.animations);
+
+        if old.virtual_input_clients != self.config.virtual_input_clients {
+            self.input_client_allowlist.set(&self.config.virtual_input_clients);
+        }
+
+        if old.auto != self.config.auto {
+            let settings = self.auto_settings();
+            self.ipc_event(IpcEvent::AutoSettingsChanged { settings });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(toml: &str) -> Result<Config, Vec<String>> {
+        let raw: RawConfig = toml::from_str(toml).expect("test config must parse");
+        raw.validate()
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let config = Config::default();
+        assert_eq!(config.keyboard.layouts, vec![("us".to_string(), String::new())]);
+        assert_eq!(config.keybindings.len(), DEFAULT_KEYBINDINGS.len());
+        assert_eq!(config.gestures.len(), DEFAULT_GESTURES.len());
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("#ff0000"), Ok([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#00ff0000"), Ok([0.0, 1.0, 0.0, 0.0]));
+        assert_eq!(parse_color("#FFFFFF"), Ok([1.0, 1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn rejects_invalid_colors() {
+        for value in ["", "ff0000", "#", "#fff", "#ff00000", "#ff0000000", "#gg0000", "#ff 000"] {
+            assert!(parse_color(value).is_err(), "{:?} should be rejected", value);
+        }
+    }
+
+    #[test]
+    fn parses_keybindings() {
+        let binding = parse_keybinding("Super+Shift+Return", Action::Quit).unwrap();
+        assert!(binding.logo && binding.shift && !binding.ctrl && !binding.alt);
+        assert_eq!(binding.keysym, xkb::Keysym::Return);
+        assert_eq!(binding.action, Action::Quit);
+
+        let binding = parse_keybinding("ctrl + mod1 + q", Action::CloseWindow).unwrap();
+        assert!(binding.ctrl && binding.alt && !binding.shift && !binding.logo);
+
+        let binding = parse_keybinding("Print", Action::Screenshot).unwrap();
+        assert!(!binding.ctrl && !binding.alt && !binding.shift && !binding.logo);
+        assert_eq!(binding.keysym, xkb::Keysym::Print);
+    }
+
+    #[test]
+    fn rejects_unknown_modifiers_and_keys() {
+        let err = parse_keybinding("Hyper+Q", Action::Quit).unwrap_err();
+        assert!(err.contains("unknown modifier"), "{:?}", err);
+        let err = parse_keybinding("Super+NotAKey", Action::Quit).unwrap_err();
+        assert!(err.contains("unknown key"), "{:?}", err);
+        assert!(parse_keybinding("", Action::Quit).is_err());
+        assert!(parse_keybinding("Super+", Action::Quit).is_err());
+    }
+
+    #[test]
+    fn parses_gestures() {
+        let gesture = parse_gesture("swipe-3-left", Action::WorkspaceNext).unwrap();
+        assert_eq!(gesture.fingers, 3);
+        assert_eq!(gesture.direction, SwipeDirection::Left);
+        assert_eq!(gesture.action, Action::WorkspaceNext);
+        assert_eq!(parse_gesture("swipe-5-down", Action::Quit).unwrap().fingers, 5);
+    }
+
+    #[test]
+    fn rejects_finger_counts_outside_three_to_five() {
+        for gesture in ["swipe-2-left", "swipe-6-left", "swipe-0-up", "swipe-x-up", "swipe--1-up"] {
+            assert!(parse_gesture(gesture, Action::Quit).is_err(), "{:?} should be rejected", gesture);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_gestures() {
+        for gesture in ["pinch-3-in", "swipe-3", "swipe-3-sideways", "swipe-3-left-again"] {
+            assert!(parse_gesture(gesture, Action::Quit).is_err(), "{:?} should be rejected", gesture);
+        }
+    }
+
+    #[test]
+    fn validate_collects_all_errors() {
+        let errors = validate(
+            r##"
+            [input.keyboard]
+            repeat_rate = -1
+
+            [keybindings]
+            "Hyper+Q" = "quit"
+            "Super+Q" = "explode"
+
+            [gestures]
+            "swipe-7-left" = "quit"
+
+            [focus]
+            policy = "telepathic"
+
+            [appearance]
+            background = "red"
+
+            [auto]
+            width = 0
+            "##,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 7, "{:#?}", errors);
+        for key in [
+            "input.keyboard.repeat_rate",
+            "keybindings.\"Hyper+Q\"",
+            "keybindings.\"Super+Q\"",
+            "gestures.\"swipe-7-left\"",
+            "focus.policy",
+            "appearance.background",
+            "auto.width",
+        ] {
+            assert!(errors.iter().any(|err| err.starts_with(key)), "no error for {}: {:#?}", key, errors);
+        }
+    }
+
+    #[test]
+    fn virtual_input_clients_must_be_absolute() {
+        let config = validate(
+            r#"
+            [input]
+            virtual_input_clients = ["/usr/bin/wtype"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.virtual_input_clients, vec![PathBuf::from("/usr/bin/wtype")]);
+
+        let errors = validate(
+            r#"
+            [input]
+            virtual_input_clients = ["wtype", ""]
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+    }
+
+    #[test]
+    fn validate_splits_layouts_and_variants() {
+        let config = validate(
+            r#"
+            [input.keyboard]
+            layout = "us, de"
+            variant = ",nodeadkeys"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.keyboard.layouts,
+            vec![("us".to_string(), String::new()), ("de".to_string(), "nodeadkeys".to_string())]
+        );
+
+        let errors = validate(
+            r#"
+            [input.keyboard]
+            layout = "us"
+            variant = "intl,dvorak"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+    }
+}
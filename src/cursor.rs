@@ -0,0 +1,136 @@
+//! Compositor-drawn cursors.
+//!
+//! Named cursors are loaded from the xcursor theme in `XCURSOR_THEME`/`XCURSOR_SIZE` and kept
+//! as memory buffers, one per icon, size and animation frame. If the theme has neither the
+//! requested icon nor a default one, a plain arrow is drawn instead.
+
+use std::{collections::HashMap, time::Duration};
+
+use smithay::{
+    backend::{allocator::Fourcc, renderer::element::memory::MemoryRenderBuffer},
+    input::pointer::CursorIcon,
+    utils::{Logical, Point, Transform},
+};
+use xcursor::{
+    CursorTheme as XCursorTheme,
+    parser::{Image, parse_xcursor},
+};
+
+const DEFAULT_SIZE: u32 = 24;
+
+pub struct CursorTheme {
+    theme: XCursorTheme,
+    pub size: u32,
+    icons: HashMap<CursorIcon, Vec<Image>>,
+    buffers: HashMap<(CursorIcon, u32, usize), MemoryRenderBuffer>,
+}
+
+impl CursorTheme {
+    pub fn load() -> Self {
+        let name = std::env::var("XCURSOR_THEME").unwrap_or_else(|_| "default".into());
+        let size = std::env::var("XCURSOR_SIZE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_SIZE);
+        tracing::info!("Using cursor theme {:?} at size {}", name, size);
+        Self {
+            theme: XCursorTheme::load(&name),
+            size,
+            icons: HashMap::new(),
+            buffers: HashMap::new(),
+        }
+    }
+
+    /// The buffer to draw for `icon` at an integer output scale, and its hotspot in logical pixels.
+    pub fn image(&mut self, icon: CursorIcon, scale: u32, time: Duration) -> (MemoryRenderBuffer, Point<f64, Logical>) {
+        let scale = scale.max(1);
+        let size = self.size * scale;
+        let theme = &self.theme;
+        let images = self
+            .icons
+            .entry(icon)
+            .or_insert_with(|| load_icon(theme, icon).unwrap_or_else(fallback_images));
+        let (index, image) = frame(time.as_millis() as u32, size, images);
+        // Themes without an image at `size` fall back to a smaller one, which is then drawn at a
+        // lower buffer scale rather than shrunk further.
+        let buffer_scale = (image.size / self.size).clamp(1, scale) as i32;
+        let hotspot = Point::from((image.xhot as f64, image.yhot as f64)).downscale(buffer_scale as f64);
+        let buffer = self
+            .buffers
+            .entry((icon, size, index))
+            .or_insert_with(|| {
+                MemoryRenderBuffer::from_slice(
+                    &image.pixels_rgba,
+                    Fourcc::Argb8888,
+                    (image.width as i32, image.height as i32),
+                    buffer_scale,
+                    Transform::Normal,
+                    None,
+                )
+            })
+            .clone();
+        (buffer, hotspot)
+    }
+}
+
+fn load_icon(theme: &XCursorTheme, icon: CursorIcon) -> Option<Vec<Image>> {
+    std::iter::once(icon.name())
+        .chain(icon.alt_names().iter().copied())
+        .chain(std::iter::once("default"))
+        .find_map(|name| {
+            let path = theme.load_icon(name)?;
+            let data = std::fs::read(path).ok()?;
+            parse_xcursor(&data).filter(|images| !images.is_empty())
+        })
+}
+
+/// A 16x16 white triangle with a black outline.
+fn fallback_images() -> Vec<Image> {
+    tracing::warn!("No usable cursor theme, drawing a fallback arrow");
+    const SIZE: u32 = 16;
+    let mut pixels = vec![0u8; (SIZE * SIZE * 4) as usize];
+    for y in 0..SIZE {
+        let width = y * 2 / 3;
+        for x in 0..=width {
+            let edge = x == 0 || x == width || y == SIZE - 1;
+            let value = if edge { 0x00 } else { 0xff };
+            let offset = ((y * SIZE + x) * 4) as usize;
+            pixels[offset..offset + 4].copy_from_slice(&[value, value, value, 0xff]);
+        }
+    }
+    vec![Image {
+        size: SIZE,
+        width: SIZE,
+        height: SIZE,
+        xhot: 0,
+        yhot: 0,
+        delay: 0,
+        pixels_argb: pixels.clone(),
+        pixels_rgba: pixels,
+    }]
+}
+
+/// Picks the animation frame for `millis` among the images closest to `size`.
+fn frame(millis: u32, size: u32, images: &[Image]) -> (usize, &Image) {
+    let nearest = images
+        .iter()
+        .min_by_key(|image| (size as i32 - image.size as i32).abs())
+        .expect("cursor without images");
+    let mut frames = images
+        .iter()
+        .enumerate()
+        .filter(|(_, image)| image.width == nearest.width && image.height == nearest.height);
+
+    let total: u32 = frames.clone().map(|(_, image)| image.delay).sum();
+    if total == 0 {
+        return frames.next().unwrap();
+    }
+    let mut millis = millis % total;
+    for (index, image) in frames {
+        if millis < image.delay {
+            return (index, image);
+        }
+        millis -= image.delay;
+    }
+    unreachable!()
+}
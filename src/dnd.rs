@@ -0,0 +1,295 @@
+//! Drag-and-drop across the X11/Wayland boundary.
+//!
+//! Every drag runs through `DndBridgeGrab`. It offers the drag to whatever is under the pointer:
+//! Wayland surfaces get a `wl_data_offer`, while X11 windows are driven over XDND by the window
+//! manager. The source can be a Wayland data source or an X11 client's `XdndSelection`, so drags
+//! work in all four directions.
+
+use std::sync::Arc;
+
+use smithay::{
+    desktop::Window,
+    input::{
+        Seat,
+        dnd::{DndFocus, Source},
+        pointer::{
+            AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
+            GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent,
+            GestureSwipeUpdateEvent, GrabStartData, MotionEvent, PointerGrab, PointerInnerHandle,
+            RelativeMotionEvent,
+        },
+    },
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{IsAlive, Logical, Point},
+    wayland::selection::data_device::WlOfferData,
+    xwayland::{X11Surface, xwm::XwmOfferData},
+};
+
+use crate::state::NanaimoState;
+
+/// What a drag is currently over.
+#[derive(Debug, Clone, PartialEq)]
+enum DropTarget {
+    Wayland(WlSurface),
+    /// An X11 window, reached over XDND rather than through XWayland's own `wl_surface`.
+    X11(X11Surface),
+}
+
+impl DropTarget {
+    fn for_surface(state: &NanaimoState, surface: WlSurface) -> Self {
+        state
+            .space
+            .elements()
+            .filter_map(Window::x11_surface)
+            .find(|x11| x11.wl_surface().as_ref() == Some(&surface))
+            .map(|x11| DropTarget::X11(x11.clone()))
+            .unwrap_or(DropTarget::Wayland(surface))
+    }
+
+    fn alive(&self) -> bool {
+        match self {
+            DropTarget::Wayland(surface) => surface.alive(),
+            DropTarget::X11(surface) => surface.alive(),
+        }
+    }
+}
+
+/// The offer made to a `DropTarget`, if it accepted the drag at all.
+enum DropOffer<S: Source> {
+    Wayland(WlOfferData<S>),
+    X11(XwmOfferData<S>),
+}
+
+pub struct DndBridgeGrab<S: Source> {
+    start_data: GrabStartData<NanaimoState>,
+    source: Arc<S>,
+    seat: Seat<NanaimoState>,
+    target: Option<(DropTarget, Option<DropOffer<S>>)>,
+    dropped: bool,
+}
+
+impl<S: Source> DndBridgeGrab<S> {
+    pub fn new(
+        start_data: GrabStartData<NanaimoState>,
+        source: S,
+        seat: Seat<NanaimoState>,
+    ) -> Self {
+        Self {
+            start_data,
+            source: Arc::new(source),
+            seat,
+            target: None,
+            dropped: false,
+        }
+    }
+
+    fn leave(&mut self, data: &mut NanaimoState) {
+        let Some((target, mut offer)) = self.target.take() else {
+            return;
+        };
+        match (&target, offer.as_mut()) {
+            (DropTarget::Wayland(surface), Some(DropOffer::Wayland(offer))) => {
+                DndFocus::leave(surface, data, Some(offer), &self.seat)
+            }
+            (DropTarget::X11(surface), Some(DropOffer::X11(offer))) => {
+                DndFocus::leave(surface, data, Some(offer), &self.seat)
+            }
+            (DropTarget::Wayland(surface), _) => DndFocus::<NanaimoState>::leave::<S>(surface, data, None, &self.seat),
+            (DropTarget::X11(surface), _) => DndFocus::<NanaimoState>::leave::<S>(surface, data, None, &self.seat),
+        }
+    }
+
+    fn enter(&mut self, data: &mut NanaimoState, target: DropTarget, location: Point<f64, Logical>) {
+        let serial = data.serial_counter.next_serial();
+        let dh = data.display_handle.clone();
+        let offer = match &target {
+            DropTarget::Wayland(surface) => {
+                DndFocus::enter(surface, data, &dh, self.source.clone(), &self.seat, location, &serial)
+                    .map(DropOffer::Wayland)
+            }
+            DropTarget::X11(surface) => {
+                DndFocus::enter(surface, data, &dh, self.source.clone(), &self.seat, location, &serial)
+                    .map(DropOffer::X11)
+            }
+        };
+        self.target = Some((target, offer));
+    }
+
+    fn motion(&mut self, data: &mut NanaimoState, location: Point<f64, Logical>, time: u32) {
+        let Some((target, offer)) = self.target.as_mut() else {
+            return;
+        };
+        match (target, offer.as_mut()) {
+            (DropTarget::Wayland(surface), Some(DropOffer::Wayland(offer))) => {
+                DndFocus::motion(surface, data, Some(offer), &self.seat, location, time)
+            }
+            (DropTarget::X11(surface), Some(DropOffer::X11(offer))) => {
+                DndFocus::motion(surface, data, Some(offer), &self.seat, location, time)
+            }
+            _ => {}
+        }
+    }
+
+    /// Drops on the current target, or cancels the drag if nothing there accepted it.
+    fn drop(&mut self, data: &mut NanaimoState) {
+        self.dropped = true;
+        match self.target.take() {
+            Some((DropTarget::Wayland(surface), Some(DropOffer::Wayland(mut offer)))) => {
+                DndFocus::drop(&surface, data, Some(&mut offer), &self.seat)
+            }
+            Some((DropTarget::X11(surface), Some(DropOffer::X11(mut offer)))) => {
+                DndFocus::drop(&surface, data, Some(&mut offer), &self.seat)
+            }
+            _ => self.source.cancel(),
+        }
+    }
+}
+
+impl<S: Source> PointerGrab<NanaimoState> for DndBridgeGrab<S> {
+    fn motion(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // Clients see the drag through their offers, not through pointer events.
+        handle.motion(data, None, event);
+
+        let Some((surface, origin)) = focus else {
+            self.leave(data);
+            return;
+        };
+        let target = DropTarget::for_surface(data, surface);
+        let location = event.location - origin;
+        let current = self.target.as_ref().map(|(target, _)| target);
+        if current != Some(&target) || !target.alive() {
+            self.leave(data);
+            self.enter(data, target, location);
+        } else {
+            self.motion(data, location, event.time);
+        }
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &ButtonEvent,
+    ) {
+        if handle.current_pressed().is_empty() {
+            self.drop(data);
+            handle.unset_grab(self, data, event.serial, event.time, true);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        _data: &mut NanaimoState,
+        _handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        _details: AxisFrame,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+    ) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event);
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event);
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event);
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event);
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event);
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event);
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event);
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut PointerInnerHandle<'_, NanaimoState>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event);
+    }
+
+    fn start_data(&self) -> &GrabStartData<NanaimoState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, data: &mut NanaimoState) {
+        // Ungrabbed before the buttons were released (e.g. by a lock): the drag is off.
+        if !self.dropped {
+            self.leave(data);
+            self.source.cancel();
+        }
+    }
+}
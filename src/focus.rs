@@ -0,0 +1,177 @@
+//! Keyboard focus: the configured focus policy, delayed raising, the focus history that picks
+//! the next window when the focused one goes away, and focus-stealing prevention for
+//! xdg-activation.
+
+use std::time::{Duration, Instant};
+
+use smithay::{
+    desktop::Window,
+    input::Seat,
+    reexports::wayland_server::Resource,
+    utils::{Logical, Point, Serial},
+    wayland::{seat::WaylandFocus, xdg_activation::XdgActivationTokenData},
+};
+
+use crate::config::FocusPolicy;
+use crate::ipc::IpcEvent;
+use crate::state::NanaimoState;
+use crate::window::{is_override_redirect, set_window_activated, window_id};
+
+/// How long an activation token may move focus after it was created.
+const ACTIVATION_TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl NanaimoState {
+    /// Puts `window` on top of the stack, for X11 clients too.
+    pub fn raise_window(&mut self, window: &Window) {
+        self.space.raise_element(window, true);
+        if let (Some(xwm), Some(x11)) = (self.xwm.as_mut(), window.x11_surface()) {
+            if let Err(err) = xwm.raise_window(x11) {
+                tracing::warn!("Failed to raise X11 window: {}", err);
+            }
+        }
+    }
+
+    /// Marks `window` activated and gives it keyboard focus without raising it.
+    pub fn set_focused_window(&mut self, window: &Window, serial: Serial) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if keyboard.current_focus().as_ref().map(|f| f.wl_surface().as_deref() == window.wl_surface().as_deref()).unwrap_or(false) {
+            tracing::trace!("Window already focused");
+            return;
+        }
+
+        // Deactivate other windows
+        for other in self.space.elements() {
+            if other != window {
+                set_window_activated(other, false);
+            }
+        }
+
+        set_window_activated(window, true);
+        if let Some(surface) = window.wl_surface() {
+            tracing::info!("Setting keyboard focus to window: {:?}", window);
+            keyboard.set_focus(self, Some(surface.into_owned()), serial);
+        }
+    }
+
+    /// Applies a hover-based focus policy after the pointer moved to `pos`.
+    pub fn focus_follows_pointer(&mut self, pos: Point<f64, Logical>) {
+        let policy = self.config.focus.policy;
+        if policy == FocusPolicy::Click || self.is_locked() || self.pointer.is_grabbed() {
+            return;
+        }
+        let serial = self.serial_counter.next_serial();
+        let Some((window, _)) = self.space.element_under(pos).map(|(w, p)| (w.clone(), p)) else {
+            // Panels and other surfaces that are not windows keep whatever has focus.
+            if policy == FocusPolicy::FollowsMouse && self.surface_under(pos).is_none() {
+                self.pending_raise = None;
+                for window in self.space.elements() {
+                    set_window_activated(window, false);
+                }
+                let keyboard = self.seat.get_keyboard().unwrap();
+                keyboard.set_focus(self, None, serial);
+            }
+            return;
+        };
+        if self.focused_window().as_ref() == Some(&window) || is_override_redirect(&window) {
+            return;
+        }
+
+        self.set_focused_window(&window, serial);
+        self.pending_raise = None;
+        if !self.config.focus.raise {
+            return;
+        }
+        if self.config.focus.raise_delay.is_zero() {
+            self.raise_window(&window);
+        } else {
+            self.pending_raise = Some((window, Instant::now() + self.config.focus.raise_delay));
+        }
+    }
+
+    /// Raises the window waiting for its raise delay once it is up, provided it kept focus.
+    /// Runs once per loop iteration.
+    pub fn apply_pending_raise(&mut self) {
+        let Some((window, at)) = &self.pending_raise else {
+            return;
+        };
+        if Instant::now() < *at {
+            return;
+        }
+        let window = window.clone();
+        self.pending_raise = None;
+        if self.focused_window().as_ref() == Some(&window) {
+            self.raise_window(&window);
+        }
+    }
+
+    /// Remembers `window` as the most recently focused one. Focus also settles any urgency.
+    pub fn record_focus(&mut self, window: &Window) {
+        self.focus_history.retain(|w| w != window && w.alive());
+        self.focus_history.push(window.clone());
+        if self.urgent_windows.contains(&window_id(window).0) {
+            // Called while the keyboard is busy changing focus, which the shell update needs.
+            let window = window.clone();
+            self.loop_handle.insert_idle(move |state| state.set_window_urgent(&window, false));
+        }
+    }
+
+    /// Whether an activation token may move focus: it must be recent and carry the serial of an
+    /// input event the focused client received since it got keyboard focus.
+    pub fn activation_token_valid(&self, data: &XdgActivationTokenData) -> bool {
+        if data.timestamp.elapsed() > ACTIVATION_TOKEN_TIMEOUT {
+            return false;
+        }
+        let Some((serial, seat)) = &data.serial else {
+            return false;
+        };
+        let Some(keyboard) = Seat::<Self>::from_resource(seat).and_then(|seat| seat.get_keyboard()) else {
+            return false;
+        };
+        let focused_client = keyboard.current_focus().and_then(|surface| surface.client()).map(|client| client.id());
+        if focused_client.is_none() || focused_client != data.client_id {
+            return false;
+        }
+        keyboard.last_enter().is_some_and(|enter| serial.is_no_older_than(&enter))
+    }
+
+    /// Marks `window` as wanting attention, or clears that, and tells the shell.
+    pub fn set_window_urgent(&mut self, window: &Window, urgent: bool) {
+        let id = window_id(window).0;
+        let changed = if urgent {
+            self.urgent_windows.insert(id)
+        } else {
+            self.urgent_windows.remove(&id)
+        };
+        if !changed {
+            return;
+        }
+        if let Some((window, workspace, location)) = self.find_window(id) {
+            let info = self.window_info(&window, workspace, location);
+            self.ipc_event(IpcEvent::WindowChanged { window: info });
+        }
+    }
+
+    /// Focuses the window on the active workspace that had focus most recently, falling back to
+    /// the topmost one, or nothing if the workspace is empty.
+    pub fn restore_focus(&mut self) {
+        if self.is_locked() {
+            return;
+        }
+        self.focus_history.retain(|w| w.alive());
+        let serial = self.serial_counter.next_serial();
+        let window = self
+            .focus_history
+            .iter()
+            .rev()
+            .find(|w| self.managed_windows().any(|e| e == *w))
+            .or_else(|| self.managed_windows().last())
+            .cloned();
+        match window {
+            Some(window) => self.focus_window(&window, serial),
+            None => {
+                let keyboard = self.seat.get_keyboard().unwrap();
+                keyboard.set_focus(self, None, serial);
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Touchpad gestures: swipes bound in the config run compositor actions, everything else goes to
+//! the client under the pointer through pointer-gestures.
+
+use smithay::{
+    input::pointer::{
+        GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent, GesturePinchEndEvent,
+        GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent,
+    },
+    utils::{Logical, Point},
+};
+
+use crate::config::SwipeDirection;
+use crate::state::NanaimoState;
+
+/// How far the fingers must travel, in touchpad pixels, before a swipe counts.
+const SWIPE_THRESHOLD: f64 = 100.0;
+
+/// A swipe the compositor took for itself because a binding uses its finger count.
+pub struct Swipe {
+    fingers: u32,
+    delta: Point<f64, Logical>,
+}
+
+impl Swipe {
+    fn direction(&self) -> Option<SwipeDirection> {
+        let Point { x, y, .. } = self.delta;
+        if x.abs().max(y.abs()) < SWIPE_THRESHOLD {
+            return None;
+        }
+        Some(match (x.abs() > y.abs(), x > 0.0, y > 0.0) {
+            (true, true, _) => SwipeDirection::Right,
+            (true, false, _) => SwipeDirection::Left,
+            (false, _, true) => SwipeDirection::Down,
+            (false, _, false) => SwipeDirection::Up,
+        })
+    }
+}
+
+impl NanaimoState {
+    pub fn on_gesture_swipe_begin(&mut self, fingers: u32, time: u32) {
+        self.notify_activity();
+        let bound = self.config.gestures.iter().any(|binding| binding.fingers == fingers);
+        if bound && !self.is_locked() {
+            self.swipe = Some(Swipe {
+                fingers,
+                delta: Point::default(),
+            });
+            return;
+        }
+        let serial = self.serial_counter.next_serial();
+        let pointer = self.pointer.clone();
+        pointer.gesture_swipe_begin(self, &GestureSwipeBeginEvent { serial, time, fingers });
+    }
+
+    pub fn on_gesture_swipe_update(&mut self, delta: Point<f64, Logical>, time: u32) {
+        self.notify_activity();
+        if let Some(swipe) = &mut self.swipe {
+            swipe.delta += delta;
+            return;
+        }
+        let pointer = self.pointer.clone();
+        pointer.gesture_swipe_update(self, &GestureSwipeUpdateEvent { time, delta });
+    }
+
+    pub fn on_gesture_swipe_end(&mut self, cancelled: bool, time: u32) {
+        self.notify_activity();
+        if let Some(swipe) = self.swipe.take() {
+            let direction = swipe.direction().filter(|_| !cancelled);
+            let action = self
+                .config
+                .gestures
+                .iter()
+                .find(|binding| binding.fingers == swipe.fingers && Some(binding.direction) == direction)
+                .map(|binding| binding.action.clone());
+            if let Some(action) = action {
+                self.run_action(action);
+            }
+            return;
+        }
+        let serial = self.serial_counter.next_serial();
+        let pointer = self.pointer.clone();
+        pointer.gesture_swipe_end(self, &GestureSwipeEndEvent { serial, time, cancelled });
+    }
+
+    pub fn on_gesture_pinch_begin(&mut self, fingers: u32, time: u32) {
+        self.notify_activity();
+        let serial = self.serial_counter.next_serial();
+        let pointer = self.pointer.clone();
+        pointer.gesture_pinch_begin(self, &GesturePinchBeginEvent { serial, time, fingers });
+    }
+
+    pub fn on_gesture_pinch_update(&mut self, delta: Point<f64, Logical>, scale: f64, rotation: f64, time: u32) {
+        self.notify_activity();
+        let pointer = self.pointer.clone();
+        pointer.gesture_pinch_update(
+            self,
+            &GesturePinchUpdateEvent {
+                time,
+                delta,
+                scale,
+                rotation,
+            },
+        );
+    }
+
+    pub fn on_gesture_pinch_end(&mut self, cancelled: bool, time: u32) {
+        self.notify_activity();
+        let serial = self.serial_counter.next_serial();
+        let pointer = self.pointer.clone();
+        pointer.gesture_pinch_end(self, &GesturePinchEndEvent { serial, time, cancelled });
+    }
+
+    pub fn on_gesture_hold_begin(&mut self, fingers: u32, time: u32) {
+        self.notify_activity();
+        let serial = self.serial_counter.next_serial();
+        let pointer = self.pointer.clone();
+        pointer.gesture_hold_begin(self, &GestureHoldBeginEvent { serial, time, fingers });
+    }
+
+    pub fn on_gesture_hold_end(&mut self, cancelled: bool, time: u32) {
+        self.notify_activity();
+        let serial = self.serial_counter.next_serial();
+        let pointer = self.pointer.clone();
+        pointer.gesture_hold_end(self, &GestureHoldEndEvent { serial, time, cancelled });
+    }
+}
@@ -6,11 +6,15 @@ use smithay::{
         GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent, GrabStartData,
         MotionEvent, PointerGrab, PointerInnerHandle, RelativeMotionEvent,
     },
+    input::touch::{
+        DownEvent, GrabStartData as TouchGrabStartData, MotionEvent as TouchMotionEvent, OrientationEvent, ShapeEvent,
+        TouchGrab, TouchInnerHandle, UpEvent,
+    },
     reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel,
         wayland_server::protocol::wl_surface::WlSurface,
     },
-    utils::{Logical, Point, Serial, Size},
+    utils::{Logical, Point, Rectangle, Serial, Size},
     wayland::{compositor::with_states, seat::WaylandFocus},
 };
 use std::cell::RefCell;
@@ -62,6 +66,125 @@ pub fn cursor_icon_for_edge(edge: ResizeEdge) -> CursorIcon {
     }
 }
 
+/// Moves `window` to where an interactive move that started at `initial_location` has taken it.
+fn drag_window(
+    data: &mut NanaimoState,
+    window: &Window,
+    initial_location: Point<i32, Logical>,
+    delta: Point<f64, Logical>,
+) {
+    let new_location = (initial_location.to_f64() + delta).to_i32_round();
+    data.space.map_element(window.clone(), new_location, true);
+
+    // X11 clients position their popups from the geometry we last told them about.
+    if let Some(x11) = window.x11_surface() {
+        let mut geometry = x11.geometry();
+        geometry.loc = new_location;
+        let _ = x11.configure(geometry);
+    }
+}
+
+/// Asks `window` for the size an interactive resize has reached after moving `delta` from where
+/// it started, and returns that size.
+fn resize_window(
+    data: &mut NanaimoState,
+    window: &Window,
+    edges: ResizeEdge,
+    initial_location: Point<i32, Logical>,
+    initial_size: Size<i32, Logical>,
+    delta: Point<f64, Logical>,
+) -> Size<i32, Logical> {
+    let (mut dx, mut dy) = delta.into();
+
+    let mut new_window_width = initial_size.w;
+    let mut new_window_height = initial_size.h;
+
+    match edges {
+        ResizeEdge::Left | ResizeEdge::TopLeft | ResizeEdge::BottomLeft => {
+            dx = -dx;
+            new_window_width = (initial_size.w as f64 + dx) as i32;
+        }
+        ResizeEdge::Right | ResizeEdge::TopRight | ResizeEdge::BottomRight => {
+            new_window_width = (initial_size.w as f64 + dx) as i32;
+        }
+        _ => {}
+    }
+
+    match edges {
+        ResizeEdge::Top | ResizeEdge::TopLeft | ResizeEdge::TopRight => {
+            dy = -dy;
+            new_window_height = (initial_size.h as f64 + dy) as i32;
+        }
+        ResizeEdge::Bottom | ResizeEdge::BottomLeft | ResizeEdge::BottomRight => {
+            new_window_height = (initial_size.h as f64 + dy) as i32;
+        }
+        _ => {}
+    }
+
+    let size: Size<i32, Logical> = (new_window_width.max(1), new_window_height.max(1)).into();
+
+    // Only xdg toplevels go through the configure/ack/commit dance tracked here.
+    if let Some(surface) = window.toplevel().map(|tl| tl.wl_surface().clone()) {
+        with_states(&surface, |states| {
+            let mut data = states
+                .data_map
+                .get::<RefCell<SurfaceData>>()
+                .unwrap()
+                .borrow_mut();
+            data.resize_state = ResizeState::Resizing(ResizeData {
+                edges,
+                initial_window_location: initial_location,
+                initial_window_size: initial_size,
+            });
+        });
+    }
+
+    if let Some(toplevel) = window.toplevel() {
+        toplevel.with_pending_state(|state| {
+            state.states.set(xdg_toplevel::State::Resizing);
+            state.size = Some(size);
+        });
+        toplevel.send_configure();
+    } else if let Some(x11) = window.x11_surface() {
+        // X11 windows take the new size immediately, so the location for left/top edges
+        // can be adjusted right away instead of waiting for a commit.
+        let mut location = initial_location;
+        if matches!(edges, ResizeEdge::Left | ResizeEdge::TopLeft | ResizeEdge::BottomLeft) {
+            location.x += initial_size.w - size.w;
+        }
+        if matches!(edges, ResizeEdge::Top | ResizeEdge::TopLeft | ResizeEdge::TopRight) {
+            location.y += initial_size.h - size.h;
+        }
+        let _ = x11.configure(Rectangle::new(location, size));
+        data.space.map_element(window.clone(), location, true);
+    }
+
+    size
+}
+
+/// Sends the final size of an interactive resize that ended with `serial`.
+fn finish_resize(window: &Window, size: Size<i32, Logical>, serial: Serial) {
+    let Some(toplevel) = window.toplevel() else {
+        return;
+    };
+    toplevel.with_pending_state(|state| {
+        state.states.unset(xdg_toplevel::State::Resizing);
+        state.size = Some(size);
+    });
+    toplevel.send_configure();
+
+    with_states(toplevel.wl_surface(), |states| {
+        let mut data = states
+            .data_map
+            .get::<RefCell<SurfaceData>>()
+            .unwrap()
+            .borrow_mut();
+        if let ResizeState::Resizing(resize_data) = data.resize_state {
+            data.resize_state = ResizeState::WaitingForFinalAck(resize_data, serial);
+        }
+    });
+}
+
 pub struct PointerMoveSurfaceGrab {
     pub start_data: GrabStartData<NanaimoState>,
     pub window: Window,
@@ -83,10 +206,7 @@ impl PointerGrab<NanaimoState> for PointerMoveSurfaceGrab {
         // data.cursor_status = CursorImageStatus::Named(CursorIcon::Grabbing);
 
         let delta = event.location - self.start_data.location;
-        let new_location = self.initial_window_location.to_f64() + delta;
-
-        data.space
-            .map_element(self.window.clone(), new_location.to_i32_round(), true);
+        drag_window(data, &self.window, self.initial_window_location, delta);
     }
 
     fn relative_motion(
@@ -228,60 +348,15 @@ impl PointerGrab<NanaimoState> for PointerResizeSurfaceGrab {
         handle.motion(data, None, event);
         data.cursor_status = CursorImageStatus::Named(cursor_icon_for_edge(self.edges));
 
-        let (mut dx, mut dy) = (event.location - self.start_data.location).into();
-
-        let mut new_window_width = self.initial_window_size.w;
-        let mut new_window_height = self.initial_window_size.h;
-
-        match self.edges {
-            ResizeEdge::Left | ResizeEdge::TopLeft | ResizeEdge::BottomLeft => {
-                dx = -dx;
-                new_window_width = (self.initial_window_size.w as f64 + dx) as i32;
-            }
-            ResizeEdge::Right | ResizeEdge::TopRight | ResizeEdge::BottomRight => {
-                new_window_width = (self.initial_window_size.w as f64 + dx) as i32;
-            }
-            _ => {}
-        }
-
-        match self.edges {
-            ResizeEdge::Top | ResizeEdge::TopLeft | ResizeEdge::TopRight => {
-                dy = -dy;
-                new_window_height = (self.initial_window_size.h as f64 + dy) as i32;
-            }
-            ResizeEdge::Bottom | ResizeEdge::BottomLeft | ResizeEdge::BottomRight => {
-                new_window_height = (self.initial_window_size.h as f64 + dy) as i32;
-            }
-            _ => {}
-        }
-
-        new_window_width = new_window_width.max(1);
-        new_window_height = new_window_height.max(1);
-
-        self.last_window_size = (new_window_width, new_window_height).into();
-
-        if let Some(surface) = self.window.wl_surface() {
-            with_states(&surface, |states| {
-                let mut data = states
-                    .data_map
-                    .get::<RefCell<SurfaceData>>()
-                    .unwrap()
-                    .borrow_mut();
-                data.resize_state = ResizeState::Resizing(ResizeData {
-                    edges: self.edges,
-                    initial_window_location: self.initial_window_location,
-                    initial_window_size: self.initial_window_size,
-                });
-            });
-        }
-
-        if let Some(toplevel) = self.window.toplevel() {
-            toplevel.with_pending_state(|state| {
-                state.states.set(xdg_toplevel::State::Resizing);
-                state.size = Some(self.last_window_size);
-            });
-            toplevel.send_configure();
-        }
+        let delta = event.location - self.start_data.location;
+        self.last_window_size = resize_window(
+            data,
+            &self.window,
+            self.edges,
+            self.initial_window_location,
+            self.initial_window_size,
+            delta,
+        );
     }
 
     fn relative_motion(
@@ -303,28 +378,7 @@ impl PointerGrab<NanaimoState> for PointerResizeSurfaceGrab {
         handle.button(data, event);
         if handle.current_pressed().is_empty() {
             handle.unset_grab(self, data, event.serial, event.time, true);
-
-            if let Some(toplevel) = self.window.toplevel() {
-                toplevel.with_pending_state(|state| {
-                    state.states.unset(xdg_toplevel::State::Resizing);
-                    state.size = Some(self.last_window_size);
-                });
-                toplevel.send_configure();
-
-                if let Some(surface) = self.window.wl_surface() {
-                    with_states(&surface, |states| {
-                        let mut data = states
-                            .data_map
-                            .get::<RefCell<SurfaceData>>()
-                            .unwrap()
-                            .borrow_mut();
-                        if let ResizeState::Resizing(resize_data) = data.resize_state {
-                            data.resize_state =
-                                ResizeState::WaitingForFinalAck(resize_data, event.serial);
-                        }
-                    });
-                }
-            }
+            finish_resize(&self.window, self.last_window_size, event.serial);
         }
     }
 
@@ -423,3 +477,194 @@ impl PointerGrab<NanaimoState> for PointerResizeSurfaceGrab {
 
     fn unset(&mut self, _data: &mut NanaimoState) {}
 }
+
+pub struct TouchMoveSurfaceGrab {
+    pub start_data: TouchGrabStartData<NanaimoState>,
+    pub window: Window,
+    pub initial_window_location: Point<i32, Logical>,
+}
+
+impl TouchGrab<NanaimoState> for TouchMoveSurfaceGrab {
+    fn down(
+        &mut self,
+        _data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        _event: &DownEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn up(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        event: &UpEvent,
+        seq: Serial,
+    ) {
+        if event.slot == self.start_data.slot {
+            handle.up(data, event, seq);
+            handle.unset_grab(self, data);
+        }
+    }
+
+    fn motion(
+        &mut self,
+        data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &TouchMotionEvent,
+        _seq: Serial,
+    ) {
+        // Other fingers don't move the window, and no client sees them while it moves.
+        if event.slot != self.start_data.slot {
+            return;
+        }
+        let delta = event.location - self.start_data.location;
+        drag_window(data, &self.window, self.initial_window_location, delta);
+    }
+
+    fn frame(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        seq: Serial,
+    ) {
+        handle.frame(data, seq);
+    }
+
+    fn cancel(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        seq: Serial,
+    ) {
+        handle.cancel(data, seq);
+        handle.unset_grab(self, data);
+    }
+
+    fn shape(
+        &mut self,
+        _data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _event: &ShapeEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn orientation(
+        &mut self,
+        _data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _event: &OrientationEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn start_data(&self) -> &TouchGrabStartData<NanaimoState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut NanaimoState) {}
+}
+
+pub struct TouchResizeSurfaceGrab {
+    pub start_data: TouchGrabStartData<NanaimoState>,
+    pub window: Window,
+    pub edges: ResizeEdge,
+    pub initial_window_location: Point<i32, Logical>,
+    pub initial_window_size: Size<i32, Logical>,
+    pub last_window_size: Size<i32, Logical>,
+}
+
+impl TouchGrab<NanaimoState> for TouchResizeSurfaceGrab {
+    fn down(
+        &mut self,
+        _data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        _event: &DownEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn up(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        event: &UpEvent,
+        seq: Serial,
+    ) {
+        if event.slot == self.start_data.slot {
+            handle.up(data, event, seq);
+            handle.unset_grab(self, data);
+            finish_resize(&self.window, self.last_window_size, event.serial);
+        }
+    }
+
+    fn motion(
+        &mut self,
+        data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &TouchMotionEvent,
+        _seq: Serial,
+    ) {
+        if event.slot != self.start_data.slot {
+            return;
+        }
+        let delta = event.location - self.start_data.location;
+        self.last_window_size = resize_window(
+            data,
+            &self.window,
+            self.edges,
+            self.initial_window_location,
+            self.initial_window_size,
+            delta,
+        );
+    }
+
+    fn frame(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        seq: Serial,
+    ) {
+        handle.frame(data, seq);
+    }
+
+    fn cancel(
+        &mut self,
+        data: &mut NanaimoState,
+        handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        seq: Serial,
+    ) {
+        handle.cancel(data, seq);
+        handle.unset_grab(self, data);
+        finish_resize(&self.window, self.last_window_size, seq);
+    }
+
+    fn shape(
+        &mut self,
+        _data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _event: &ShapeEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn orientation(
+        &mut self,
+        _data: &mut NanaimoState,
+        _handle: &mut TouchInnerHandle<'_, NanaimoState>,
+        _event: &OrientationEvent,
+        _seq: Serial,
+    ) {
+    }
+
+    fn start_data(&self) -> &TouchGrabStartData<NanaimoState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut NanaimoState) {}
+}
@@ -5,6 +5,7 @@ use smithay::{
         compositor::{CompositorClientState, CompositorHandler, CompositorState},
     },
 };
+use smithay::wayland::seat::WaylandFocus;
 use std::cell::RefCell;
 
 use crate::grabs::{ResizeEdge, ResizeState, SurfaceData};
@@ -38,7 +39,9 @@ impl CompositorHandler for NanaimoState {
 
     fn commit(&mut self, surface: &WlSurface) {
         tracing::trace!("Surface commit: {:?}", surface);
+        smithay::xwayland::X11Wm::commit_hook::<Self>(surface);
         smithay::backend::renderer::utils::on_commit_buffer_handler::<Self>(surface);
+        self.popups.commit(surface);
 
         let mut resize_state = ResizeState::NotResizing;
         smithay::wayland::compositor::with_states(surface, |states| {
@@ -50,11 +53,7 @@ impl CompositorHandler for NanaimoState {
         let window = self
             .space
             .elements()
-            .find(|w| {
-                w.toplevel()
-                    .map(|tl| tl.wl_surface() == surface)
-                    .unwrap_or(false)
-            })
+            .find(|w| w.wl_surface().as_deref() == Some(surface))
             .cloned();
 
         if let Some(window) = window {
@@ -97,7 +96,13 @@ impl CompositorHandler for NanaimoState {
             }
 
             tracing::trace!("Window commit: {:?}", window);
+            let old_size = window.bbox().size;
             window.on_commit();
+
+            // Interactive resizes follow the pointer; anything else eases into the new size.
+            if matches!(resize_state, ResizeState::NotResizing) && !self.pointer.is_grabbed() {
+                self.animation_manager.start_resize(&window, old_size, window.bbox().size);
+            }
         }
     }
 }
@@ -1,18 +1,83 @@
+use std::os::fd::OwnedFd;
+
 use smithay::{
+    input::{
+        Seat,
+        dnd::{DnDGrab, GrabType, Source},
+        pointer::Focus,
+    },
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::Serial,
     wayland::{
-        selection::{SelectionHandler},
+        selection::{SelectionHandler, SelectionSource, SelectionTarget},
         selection::data_device::{DataDeviceState, DataDeviceHandler, WaylandDndGrabHandler},
         selection::primary_selection::{PrimarySelectionState, PrimarySelectionHandler},
     },
 };
 
+use crate::dnd::DndBridgeGrab;
 use crate::state::{NanaimoState};
 
 impl SelectionHandler for NanaimoState {
     type SelectionUserData = ();
+
+    // A Wayland client took a selection: offer it to X11 clients too.
+    fn new_selection(&mut self, ty: SelectionTarget, source: Option<SelectionSource>, _seat: Seat<Self>) {
+        if let Some(xwm) = self.xwm.as_mut() {
+            if let Err(err) = xwm.new_selection(ty, source.map(|source| source.mime_types())) {
+                tracing::warn!("Failed to forward {:?} selection to XWayland: {}", ty, err);
+            }
+        }
+    }
+
+    // A Wayland client wants to paste a selection we set on behalf of an X11 client.
+    // Large X11 transfers (INCR) are streamed into `fd` by the window manager.
+    fn send_selection(&mut self, ty: SelectionTarget, mime_type: String, fd: OwnedFd, _seat: Seat<Self>, _user_data: &()) {
+        if let Some(xwm) = self.xwm.as_mut() {
+            if let Err(err) = xwm.send_selection(ty, mime_type, fd, self.loop_handle.clone()) {
+                tracing::warn!("Failed to request {:?} selection from XWayland: {}", ty, err);
+            }
+        }
+    }
 }
 
-impl WaylandDndGrabHandler for NanaimoState {}
+impl WaylandDndGrabHandler for NanaimoState {
+    fn dnd_requested<S: Source>(
+        &mut self,
+        source: S,
+        _icon: Option<WlSurface>,
+        seat: Seat<Self>,
+        serial: Serial,
+        type_: GrabType,
+    ) {
+        self.start_dnd(source, seat, serial, type_);
+    }
+}
+
+impl NanaimoState {
+    /// Starts a drag from `source`, whichever side of XWayland it came from. Pointer drags go
+    /// through `DndBridgeGrab` so they can be dropped on X11 windows too.
+    pub fn start_dnd<S: Source>(&mut self, source: S, seat: Seat<Self>, serial: Serial, type_: GrabType) {
+        match type_ {
+            GrabType::Pointer => {
+                let Some((pointer, start_data)) = seat.get_pointer().and_then(|p| p.grab_start_data().map(|d| (p, d))) else {
+                    source.cancel();
+                    return;
+                };
+                let grab = DndBridgeGrab::new(start_data, source, seat);
+                pointer.set_grab(self, grab, serial, Focus::Keep);
+            }
+            GrabType::Touch => {
+                let Some((touch, start_data)) = seat.get_touch().and_then(|t| t.grab_start_data().map(|d| (t, d))) else {
+                    source.cancel();
+                    return;
+                };
+                let grab = DnDGrab::new_touch(&self.display_handle, start_data, source, seat);
+                touch.set_grab(self, grab, serial);
+            }
+        }
+    }
+}
 
 impl DataDeviceHandler for NanaimoState {
     fn data_device_state(&mut self) -> &mut DataDeviceState {
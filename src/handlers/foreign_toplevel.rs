@@ -0,0 +1,113 @@
+use smithay::{
+    desktop::Window,
+    wayland::foreign_toplevel_list::{ForeignToplevelListHandler, ForeignToplevelListState},
+};
+
+use crate::protocols::foreign_toplevel::{
+    ForeignToplevelManagementHandler, ForeignToplevelManagementState, ToplevelInfo,
+};
+use crate::state::NanaimoState;
+use crate::window::{close_window, window_id, window_maximized_and_fullscreen, window_title_and_app_id};
+
+impl ForeignToplevelListHandler for NanaimoState {
+    fn foreign_toplevel_list_state(&mut self) -> &mut ForeignToplevelListState {
+        &mut self.foreign_toplevel_list_state
+    }
+}
+
+impl ForeignToplevelManagementHandler for NanaimoState {
+    fn foreign_toplevel_management_state(&mut self) -> &mut ForeignToplevelManagementState {
+        &mut self.foreign_toplevel_management_state
+    }
+
+    fn activate_toplevel(&mut self, id: u64) {
+        if let Some((window, _, _)) = self.find_window(id) {
+            self.activate_window(&window);
+        }
+    }
+
+    fn close_toplevel(&mut self, id: u64) {
+        if let Some((window, _, _)) = self.find_window(id) {
+            close_window(&window);
+        }
+    }
+
+    fn set_toplevel_minimized(&mut self, id: u64, minimized: bool) {
+        let Some((window, _, _)) = self.find_window(id) else {
+            return;
+        };
+        if minimized {
+            self.minimize_window(&window);
+        } else if self.workspaces.is_minimized(&window) {
+            self.activate_window(&window);
+        }
+    }
+}
+
+impl NanaimoState {
+    /// Sends window changes to taskbars on both foreign toplevel protocols. Nothing is sent when
+    /// nothing changed, so this simply runs once per loop iteration.
+    pub fn refresh_foreign_toplevels(&mut self) {
+        let focused = self.focused_window();
+        let toplevels: Vec<(Window, ToplevelInfo)> = self
+            .all_windows()
+            .into_iter()
+            .filter(|(window, _, _)| window.alive())
+            .map(|(window, _, _)| {
+                let info = self.toplevel_info(&window, focused.as_ref());
+                (window, info)
+            })
+            .collect();
+
+        self.foreign_toplevels.retain(|window, (handle, _)| {
+            let alive = toplevels.iter().any(|(w, _)| w == window);
+            if !alive {
+                self.foreign_toplevel_list_state.remove_toplevel(handle);
+            }
+            alive
+        });
+        for (window, info) in &toplevels {
+            match self.foreign_toplevels.get_mut(window) {
+                Some((handle, last)) => {
+                    if last.title == info.title && last.app_id == info.app_id {
+                        continue;
+                    }
+                    if last.title != info.title {
+                        handle.send_title(&info.title);
+                    }
+                    if last.app_id != info.app_id {
+                        handle.send_app_id(&info.app_id);
+                    }
+                    handle.send_done();
+                    *last = info.clone();
+                }
+                None => {
+                    let handle = self
+                        .foreign_toplevel_list_state
+                        .new_toplevel::<Self>(info.title.clone(), info.app_id.clone());
+                    self.foreign_toplevels.insert(window.clone(), (handle, info.clone()));
+                }
+            }
+        }
+
+        let toplevels = toplevels
+            .into_iter()
+            .map(|(window, info)| (window_id(&window).0, info));
+        self.foreign_toplevel_management_state
+            .update::<Self>(&self.display_handle, toplevels);
+    }
+
+    fn toplevel_info(&self, window: &Window, focused: Option<&Window>) -> ToplevelInfo {
+        let (title, app_id) = window_title_and_app_id(window);
+        let (maximized, fullscreen) = window_maximized_and_fullscreen(window);
+        ToplevelInfo {
+            title: title.unwrap_or_default(),
+            app_id: app_id.unwrap_or_default(),
+            activated: focused == Some(window),
+            maximized,
+            minimized: self.workspaces.is_minimized(window),
+            fullscreen,
+            outputs: self.space.outputs_for_element(window),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Instant;
+
+use smithay::{
+    reexports::wayland_server::{Resource, protocol::wl_surface::WlSurface},
+    wayland::{
+        compositor::get_parent,
+        idle_inhibit::IdleInhibitHandler,
+        idle_notify::{IdleNotifierHandler, IdleNotifierState},
+        seat::WaylandFocus,
+    },
+};
+
+use crate::state::NanaimoState;
+
+impl IdleNotifierHandler for NanaimoState {
+    fn idle_notifier_state(&mut self) -> &mut IdleNotifierState<Self> {
+        &mut self.idle_notifier_state
+    }
+}
+
+impl IdleInhibitHandler for NanaimoState {
+    fn inhibit(&mut self, surface: WlSurface) {
+        self.idle_inhibitors.push(surface);
+    }
+
+    fn uninhibit(&mut self, surface: WlSurface) {
+        self.idle_inhibitors.retain(|s| s != &surface);
+    }
+}
+
+impl NanaimoState {
+    /// Records user input: restarts idle timers and wakes blanked outputs.
+    pub fn notify_activity(&mut self) {
+        self.last_activity = Instant::now();
+        self.idle_notifier_state.notify_activity(&self.seat);
+    }
+
+    /// Applies idle inhibitors and blanks outputs that have been idle for longer than configured.
+    /// Runs once per loop iteration.
+    pub fn refresh_idle(&mut self) {
+        self.idle_inhibitors.retain(|surface| surface.is_alive());
+        let inhibited = self.idle_inhibitors.iter().any(|surface| self.is_surface_visible(surface));
+        if inhibited != self.idle_inhibited {
+            self.idle_inhibited = inhibited;
+            self.idle_notifier_state.set_is_inhibited(inhibited);
+        }
+        // The blanking timeout starts over once the last inhibitor goes away.
+        let now = Instant::now();
+        if inhibited {
+            self.last_activity = now;
+        }
+
+        let idle = now - self.last_activity;
+        for output in &self.outputs {
+            let blank_after = self.output_configs.get(&output.name()).and_then(|config| config.blank_after);
+            if blank_after.is_some_and(|blank_after| idle >= blank_after) {
+                if self.blanked_outputs.insert(output.clone()) {
+                    tracing::info!("Blanking idle output {}", output.name());
+                }
+            } else if self.blanked_outputs.remove(output) {
+                tracing::info!("Waking output {}", output.name());
+            }
+        }
+        self.blanked_outputs.retain(|output| self.outputs.contains(output));
+    }
+
+    /// Whether `surface` belongs to a window or lock surface currently on an output.
+    fn is_surface_visible(&self, surface: &WlSurface) -> bool {
+        let mut root = surface.clone();
+        while let Some(parent) = get_parent(&root) {
+            root = parent;
+        }
+        if self.is_locked() {
+            return self.space.outputs().any(|output| self.lock_surface(output) == Some(&root));
+        }
+        self.space
+            .elements()
+            .find(|window| window.wl_surface().as_deref() == Some(&root))
+            .is_some_and(|window| !self.space.outputs_for_element(window).is_empty())
+    }
+}
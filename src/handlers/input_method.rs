@@ -0,0 +1,36 @@
+use smithay::{
+    desktop::{PopupKind, PopupManager},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Rectangle},
+    wayland::{
+        input_method::{InputMethodHandler, PopupSurface},
+        seat::WaylandFocus,
+    },
+};
+
+use crate::state::NanaimoState;
+
+impl InputMethodHandler for NanaimoState {
+    fn new_popup(&mut self, surface: PopupSurface) {
+        // Tracked popups are drawn with their parent window, at the text cursor rectangle.
+        if let Err(err) = self.popups.track_popup(PopupKind::from(surface)) {
+            tracing::warn!("Failed to track input method popup: {}", err);
+        }
+    }
+
+    fn popup_repositioned(&mut self, _surface: PopupSurface) {}
+
+    fn dismiss_popup(&mut self, surface: PopupSurface) {
+        if let Some(parent) = surface.get_parent().map(|parent| parent.surface.clone()) {
+            let _ = PopupManager::dismiss_popup(&parent, &PopupKind::from(surface));
+        }
+    }
+
+    fn parent_geometry(&self, parent: &WlSurface) -> Rectangle<i32, Logical> {
+        self.space
+            .elements()
+            .find(|window| window.wl_surface().as_deref() == Some(parent))
+            .map(|window| window.geometry())
+            .unwrap_or_default()
+    }
+}
@@ -3,13 +3,14 @@ use smithay::{
         shm::{ShmState, ShmHandler},
         output::{OutputHandler},
         xdg_activation::{XdgActivationState, XdgActivationHandler},
-        fractional_scale::{FractionalScaleHandler, with_fractional_scale},
+        fractional_scale::FractionalScaleHandler,
         compositor::{get_parent, with_states},
         seat::WaylandFocus,
     },
     desktop::utils::surface_primary_scanout_output,
 };
 
+use crate::output::send_preferred_scale;
 use crate::state::{NanaimoState};
 
 impl ShmHandler for NanaimoState {
@@ -25,10 +26,21 @@ impl XdgActivationHandler for NanaimoState {
         &mut self.xdg_activation_state
     }
     
-    fn request_activation(&mut self, _token: smithay::wayland::xdg_activation::XdgActivationToken, _token_data: smithay::wayland::xdg_activation::XdgActivationTokenData, surface: smithay::reexports::wayland_server::protocol::wl_surface::WlSurface) {
-        let window = self.space.elements().find(|w| w.wl_surface().map(|s| *s == surface).unwrap_or(false)).cloned();
-        if let Some(window) = window {
-            self.space.raise_element(&window, true);
+    fn request_activation(&mut self, _token: smithay::wayland::xdg_activation::XdgActivationToken, token_data: smithay::wayland::xdg_activation::XdgActivationTokenData, surface: smithay::reexports::wayland_server::protocol::wl_surface::WlSurface) {
+        let window = self
+            .all_windows()
+            .into_iter()
+            .map(|(window, _, _)| window)
+            .find(|w| w.wl_surface().map(|s| *s == surface).unwrap_or(false));
+        let Some(window) = window else {
+            return;
+        };
+        if self.activation_token_valid(&token_data) {
+            self.activate_window(&window);
+        } else if self.focused_window().as_ref() != Some(&window) {
+            // Focus-stealing prevention: tell the user instead of taking the keyboard away.
+            tracing::debug!("Activation token is stale or from an unfocused client, marking window urgent");
+            self.set_window_urgent(&window, true);
         }
     }
 }
@@ -39,14 +51,18 @@ impl FractionalScaleHandler for NanaimoState {
         while let Some(parent) = get_parent(&root) {
             root = parent;
         }
+        let window_output = self
+            .space
+            .elements()
+            .find(|w| w.wl_surface().as_deref() == Some(&root))
+            .and_then(|w| self.space.outputs_for_element(w).into_iter().next());
         
         with_states(&surface, |states| {
             let primary_scanout_output = surface_primary_scanout_output(&surface, states)
+                .or(window_output)
                 .or_else(|| self.space.outputs().next().cloned());
             if let Some(output) = primary_scanout_output {
-                with_fractional_scale(states, |fractional_scale| {
-                    fractional_scale.set_preferred_scale(output.current_scale().fractional_scale());
-                });
+                send_preferred_scale(&surface, states, &output);
             }
         });
     }
@@ -4,3 +4,13 @@ pub mod seat;
 pub mod layer_shell;
 pub mod data_device;
 pub mod misc;
+pub mod output_management;
+pub mod screencopy;
+pub mod xwayland;
+pub mod foreign_toplevel;
+pub mod session_lock;
+pub mod idle;
+pub mod pointer_constraints;
+pub mod input_method;
+pub mod virtual_input;
+pub mod shortcuts_inhibit;
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use crate::protocols::output_management::{
+    HeadConfiguration, OutputManagementHandler, OutputManagementState, RequestedMode,
+};
+use crate::state::NanaimoState;
+
+impl OutputManagementHandler for NanaimoState {
+    fn output_management_state(&mut self) -> &mut OutputManagementState {
+        &mut self.output_management_state
+    }
+
+    fn apply_output_configuration(&mut self, heads: HashMap<String, HeadConfiguration>, test_only: bool) -> bool {
+        // Validate everything up front so a bad head never leaves the others half-applied.
+        let mut changes = Vec::new();
+        for (name, head) in heads {
+            let Some(output) = self.outputs.iter().find(|o| o.name() == name).cloned() else {
+                tracing::warn!("Output configuration references unknown output {}", name);
+                return false;
+            };
+            let mode = match head.mode {
+                None => None,
+                Some(RequestedMode::Existing(mode)) => Some(mode),
+                Some(RequestedMode::Custom { size, refresh }) => {
+                    let matching = output
+                        .modes()
+                        .into_iter()
+                        .find(|m| m.size == size && (refresh == 0 || m.refresh == refresh));
+                    match matching {
+                        Some(mode) => Some(mode),
+                        None => {
+                            tracing::warn!("Output {} does not support custom mode {:?}@{}", name, size, refresh);
+                            return false;
+                        }
+                    }
+                }
+            };
+            if let Some(mode) = mode {
+                if !output.modes().contains(&mode) {
+                    tracing::warn!("Output {} does not support mode {:?}", name, mode);
+                    return false;
+                }
+            }
+            if let Some(scale) = head.scale {
+                if !(0.25..=10.0).contains(&scale) {
+                    tracing::warn!("Output {} scale {} out of range", name, scale);
+                    return false;
+                }
+            }
+            changes.push((output, head, mode));
+        }
+
+        if !changes.iter().any(|(_, head, _)| head.enabled) {
+            tracing::warn!("Refusing output configuration that disables every output");
+            return false;
+        }
+        if test_only {
+            return true;
+        }
+
+        for (output, head, mode) in changes {
+            let config = self.output_configs.entry(output.name()).or_default();
+            config.enabled = head.enabled;
+            if let Some(transform) = head.transform {
+                config.transform = transform;
+            }
+            if let Some(scale) = head.scale {
+                config.scale = scale;
+            }
+            if let Some(position) = head.position {
+                config.position = Some(position);
+            }
+            if let Some(mode) = mode {
+                output.change_current_state(Some(mode), None, None, None);
+            }
+            self.apply_output_config(&output);
+        }
+        self.outputs_changed();
+        true
+    }
+}
@@ -0,0 +1,91 @@
+use smithay::{
+    input::pointer::PointerHandle,
+    reexports::wayland_server::{Resource, protocol::wl_surface::WlSurface},
+    utils::{Logical, Point},
+    wayland::{
+        compositor::RegionAttributes,
+        pointer_constraints::{PointerConstraint, PointerConstraintsHandler, with_pointer_constraint},
+    },
+};
+
+use crate::state::NanaimoState;
+
+/// What the focused surface's active constraint does to pointer motion.
+pub enum ActiveConstraint {
+    Locked,
+    /// Motion must stay on the surface, whose origin is given in global coordinates, and inside
+    /// its region, given in surface coordinates.
+    Confined(WlSurface, Point<f64, Logical>, Option<RegionAttributes>),
+}
+
+impl PointerConstraintsHandler for NanaimoState {
+    fn new_constraint(&mut self, _surface: &WlSurface, _pointer: &PointerHandle<Self>) {
+        self.maybe_activate_pointer_constraint();
+    }
+
+    fn cursor_position_hint(&mut self, surface: &WlSurface, pointer: &PointerHandle<Self>, location: Point<f64, Logical>) {
+        let active = with_pointer_constraint(surface, pointer, |constraint| constraint.is_some_and(|c| c.is_active()));
+        if !active {
+            return;
+        }
+        let Some((_, origin)) = self.surface_under(pointer.current_location()).filter(|(s, _)| s == surface) else {
+            return;
+        };
+        // Applied once the lock ends, so the cursor reappears where the client drew it.
+        self.pointer_lock_hint = Some((surface.clone(), origin + location));
+    }
+}
+
+impl NanaimoState {
+    /// The active constraint of the surface under the pointer, if any.
+    pub fn active_pointer_constraint(&self) -> Option<ActiveConstraint> {
+        let (surface, origin) = self.surface_under(self.pointer.current_location())?;
+        with_pointer_constraint(&surface, &self.pointer, |constraint| {
+            let constraint = constraint.filter(|c| c.is_active())?;
+            Some(match &*constraint {
+                PointerConstraint::Locked(_) => ActiveConstraint::Locked,
+                PointerConstraint::Confined(_) => {
+                    ActiveConstraint::Confined(surface.clone(), origin, constraint.region().cloned())
+                }
+            })
+        })
+    }
+
+    /// Activates the constraint of the surface under the pointer once the pointer is inside its
+    /// region.
+    pub fn maybe_activate_pointer_constraint(&self) {
+        let pos = self.pointer.current_location();
+        let Some((surface, origin)) = self.surface_under(pos) else {
+            return;
+        };
+        if self.pointer.current_focus().as_ref() != Some(&surface) {
+            return;
+        }
+        with_pointer_constraint(&surface, &self.pointer, |constraint| {
+            let Some(constraint) = constraint else {
+                return;
+            };
+            let point = (pos - origin).to_i32_round();
+            if !constraint.is_active() && constraint.region().is_none_or(|region| region.contains(point)) {
+                constraint.activate();
+            }
+        });
+    }
+
+    /// Moves the pointer to the position hinted by a client whose pointer lock has since ended.
+    pub fn apply_pointer_lock_hint(&mut self) {
+        let Some((surface, location)) = &self.pointer_lock_hint else {
+            return;
+        };
+        let locked = with_pointer_constraint(surface, &self.pointer, |constraint| {
+            constraint.is_some_and(|c| c.is_active() && matches!(&*c, PointerConstraint::Locked(_)))
+        });
+        if locked {
+            return;
+        }
+        if surface.is_alive() {
+            self.pointer.set_location(*location);
+        }
+        self.pointer_lock_hint = None;
+    }
+}
@@ -0,0 +1,71 @@
+use smithay::{
+    reexports::wayland_protocols::ext::foreign_toplevel_list::v1::server::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+    utils::{Buffer, Size, Transform},
+    wayland::foreign_toplevel_list::ForeignToplevelHandle,
+};
+
+use crate::capture::PendingCapture;
+use crate::protocols::image_copy_capture::{
+    CaptureFrame, ImageCaptureSource, ImageCopyCaptureHandler, ImageCopyCaptureState,
+};
+use crate::protocols::screencopy::{Screencopy, ScreencopyHandler};
+use crate::state::NanaimoState;
+use crate::thumbnails::thumbnail_size;
+use crate::window::window_id;
+
+impl ScreencopyHandler for NanaimoState {
+    fn screencopy_frame(&mut self, frame: Screencopy) {
+        self.pending_captures.push(PendingCapture::Wlr(frame));
+    }
+
+    fn screencopy_session_ended(&mut self, session: u64) {
+        self.capture_damage.retain(|(id, _), _| *id != session);
+    }
+}
+
+impl ImageCopyCaptureHandler for NanaimoState {
+    fn image_copy_capture_state(&mut self) -> &mut ImageCopyCaptureState {
+        &mut self.image_copy_capture_state
+    }
+
+    fn toplevel_capture_source(&self, handle: &ExtForeignToplevelHandleV1) -> Option<u64> {
+        let handle = ForeignToplevelHandle::from_resource(handle)?;
+        self.foreign_toplevels
+            .iter()
+            .find(|(_, (h, _))| h.identifier() == handle.identifier())
+            .map(|(window, _)| window_id(window).0)
+    }
+
+    fn capture_source_size(&self, source: &ImageCaptureSource) -> Option<Size<i32, Buffer>> {
+        match source {
+            ImageCaptureSource::Output(output) => {
+                let output = output.upgrade()?;
+                if !self.space.outputs().any(|o| o == &output) {
+                    return None;
+                }
+                let mode = output.current_mode()?;
+                Some(mode.size.to_logical(1).to_buffer(1, Transform::Normal))
+            }
+            ImageCaptureSource::Toplevel(id) => {
+                let (window, _, _) = self.find_window(*id)?;
+                let size = self.window_capture_size(&window)?;
+                Some(size.to_logical(1).to_buffer(1, Transform::Normal))
+            }
+            ImageCaptureSource::Thumbnail(id) => {
+                let (window, _, _) = self.find_window(*id)?;
+                thumbnail_size(&window)
+            }
+            ImageCaptureSource::Gone => None,
+        }
+    }
+
+    fn capture_frame(&mut self, frame: CaptureFrame) {
+        self.pending_captures.push(PendingCapture::Ext(frame));
+    }
+
+    fn capture_session_ended(&mut self, session: u64) {
+        self.capture_damage.retain(|(id, _), _| *id != session);
+        self.window_capture_damage.remove(&session);
+        self.capture_thumbnails.remove(&session);
+    }
+}
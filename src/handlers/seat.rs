@@ -1,10 +1,13 @@
 use smithay::{
+    backend::input::TabletToolDescriptor,
     input::{Seat, SeatHandler, SeatState, pointer::CursorImageStatus},
     reexports::wayland_server::{Resource, protocol::wl_surface::WlSurface},
-    wayland::tablet_manager::TabletSeatHandler,
+    wayland::{seat::WaylandFocus, tablet_manager::TabletSeatHandler},
 };
 
+use crate::ipc::IpcEvent;
 use crate::state::NanaimoState;
+use crate::window::window_id;
 
 impl SeatHandler for NanaimoState {
     type KeyboardFocus = WlSurface;
@@ -27,15 +30,29 @@ impl SeatHandler for NanaimoState {
 
         smithay::wayland::selection::data_device::set_data_device_focus(dh, seat, client.clone());
         smithay::wayland::selection::primary_selection::set_primary_focus(dh, seat, client);
+
+        let window = focus.and_then(|surface| {
+            self.space
+                .elements()
+                .find(|w| w.wl_surface().as_deref() == Some(surface))
+                .cloned()
+        });
+        if let Some(window) = &window {
+            self.record_focus(window);
+        }
+        let id = window.map(|w| window_id(&w).0);
+        self.ipc_event(IpcEvent::FocusChanged { id });
+
+        if id.is_some() && self.config.keyboard.per_window_layout {
+            // The keyboard stays busy until the focus change is done, so the keymap is swapped
+            // right after it.
+            self.loop_handle.insert_idle(|state| state.restore_window_layout());
+        }
     }
 }
 
 impl TabletSeatHandler for NanaimoState {
-    fn tablet_tool_image(
-        &mut self,
-        _tool: &smithay::backend::input::TabletToolDescriptor,
-        _image: CursorImageStatus,
-    ) {
-        // TODO: Handle tablet tool images
+    fn tablet_tool_image(&mut self, tool: &TabletToolDescriptor, image: CursorImageStatus) {
+        self.set_tablet_tool_cursor(tool, image);
     }
 }
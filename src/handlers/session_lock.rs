@@ -0,0 +1,132 @@
+use smithay::{
+    desktop::{WindowSurfaceType, utils::under_from_surface_tree},
+    output::Output,
+    reexports::{
+        wayland_protocols::ext::session_lock::v1::server::ext_session_lock_v1::ExtSessionLockV1,
+        wayland_server::{Resource, protocol::{wl_output::WlOutput, wl_surface::WlSurface}},
+    },
+    utils::{Logical, Point, Size},
+    wayland::session_lock::{LockSurface, SessionLockHandler, SessionLockManagerState, SessionLocker},
+};
+
+use crate::state::NanaimoState;
+
+/// Whether the session is locked. While it is, outputs show nothing but their lock surface and
+/// all input goes to lock surfaces.
+pub enum LockState {
+    Unlocked,
+    /// The locker asked to lock; it is told it did once a frame without windows was drawn.
+    Locking(SessionLocker),
+    /// Stays locked when the locker dies, until a new locker takes over and unlocks.
+    Locked(ExtSessionLockV1),
+}
+
+impl SessionLockHandler for NanaimoState {
+    fn lock_state(&mut self) -> &mut SessionLockManagerState {
+        &mut self.session_lock_state
+    }
+
+    fn lock(&mut self, confirmation: SessionLocker) {
+        // Dropping the locker tells it the session could not be locked.
+        match &self.lock_state {
+            LockState::Locking(_) => return,
+            LockState::Locked(lock) if lock.is_alive() => return,
+            _ => {}
+        }
+        tracing::info!("Locking session");
+        self.lock_state = LockState::Locking(confirmation);
+        self.lock_surfaces.retain(|_, surface| surface.wl_surface().is_alive());
+        self.focus_lock_surface();
+    }
+
+    fn unlock(&mut self) {
+        tracing::info!("Unlocking session");
+        self.lock_state = LockState::Unlocked;
+        self.lock_surfaces.clear();
+        self.restore_focus();
+    }
+
+    fn new_surface(&mut self, surface: LockSurface, output: WlOutput) {
+        let Some(output) = Output::from_resource(&output) else {
+            return;
+        };
+        self.configure_lock_surface(&surface, &output);
+        self.lock_surfaces.insert(output, surface);
+        self.focus_lock_surface();
+    }
+}
+
+impl NanaimoState {
+    pub fn is_locked(&self) -> bool {
+        !matches!(self.lock_state, LockState::Unlocked)
+    }
+
+    /// Tells a waiting locker the session is locked. Called after a frame was rendered, so by then
+    /// no window is on screen any more.
+    pub fn confirm_lock(&mut self) {
+        if !matches!(self.lock_state, LockState::Locking(_)) {
+            return;
+        }
+        let LockState::Locking(locker) = std::mem::replace(&mut self.lock_state, LockState::Unlocked) else {
+            unreachable!();
+        };
+        let lock = locker.ext_session_lock().clone();
+        locker.lock();
+        self.lock_state = LockState::Locked(lock);
+    }
+
+    /// The lock surface shown on `output`, if the session is locked and the locker made one.
+    pub fn lock_surface(&self, output: &Output) -> Option<&WlSurface> {
+        if !self.is_locked() {
+            return None;
+        }
+        self.lock_surfaces
+            .get(output)
+            .map(LockSurface::wl_surface)
+            .filter(|surface| surface.is_alive())
+    }
+
+    /// The lock surface under `pos`, with its location in global coordinates.
+    pub fn lock_surface_under(&self, pos: Point<f64, Logical>) -> Option<(WlSurface, Point<f64, Logical>)> {
+        let output = self.space.output_under(pos).next()?;
+        let location = self.space.output_geometry(output)?.loc;
+        let surface = self.lock_surface(output)?;
+        under_from_surface_tree(surface, pos, location, WindowSurfaceType::ALL)
+            .map(|(surface, loc)| (surface, loc.to_f64()))
+    }
+
+    /// Gives keyboard focus to the lock surface of the output under the pointer, or any lock
+    /// surface if that output has none yet.
+    pub fn focus_lock_surface(&mut self) {
+        let pointer_output = self.space.output_under(self.pointer.current_location()).next().cloned();
+        let surface = pointer_output
+            .and_then(|output| self.lock_surface(&output))
+            .or_else(|| self.lock_surfaces.values().map(LockSurface::wl_surface).find(|s| s.is_alive()))
+            .cloned();
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if surface.is_some() && keyboard.current_focus() == surface {
+            return;
+        }
+        let serial = self.serial_counter.next_serial();
+        keyboard.set_focus(self, surface, serial);
+    }
+
+    /// Resizes every lock surface to its output, after outputs changed.
+    pub fn configure_lock_surfaces(&mut self) {
+        self.lock_surfaces.retain(|output, _| self.space.outputs().any(|o| o == output));
+        for (output, surface) in &self.lock_surfaces {
+            self.configure_lock_surface(surface, output);
+        }
+    }
+
+    fn configure_lock_surface(&self, surface: &LockSurface, output: &Output) {
+        let Some(geometry) = self.space.output_geometry(output) else {
+            return;
+        };
+        let size = Size::from((geometry.size.w as u32, geometry.size.h as u32));
+        let changed = surface.with_pending_state(|state| state.size.replace(size) != Some(size));
+        if changed {
+            surface.send_configure();
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use smithay::{
+    input::keyboard::{ModifiersState, xkb},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    wayland::keyboard_shortcuts_inhibit::{
+        KeyboardShortcutsInhibitHandler, KeyboardShortcutsInhibitState, KeyboardShortcutsInhibitor,
+        KeyboardShortcutsInhibitorSeat,
+    },
+};
+
+use crate::state::NanaimoState;
+
+impl KeyboardShortcutsInhibitHandler for NanaimoState {
+    fn keyboard_shortcuts_inhibit_state(&mut self) -> &mut KeyboardShortcutsInhibitState {
+        &mut self.keyboard_shortcuts_inhibit_state
+    }
+
+    fn new_inhibitor(&mut self, inhibitor: KeyboardShortcutsInhibitor) {
+        // Remote desktops and VM viewers ask for this; the escape combo gets the shortcuts back.
+        inhibitor.activate();
+    }
+}
+
+/// Whether `keysym` with `modifiers` is the combo that toggles shortcut inhibition. It always
+/// reaches the compositor while the focused surface has an inhibitor, so a fullscreen VM viewer
+/// cannot trap the user. Without one it is an ordinary key.
+pub fn is_inhibit_escape(modifiers: &ModifiersState, keysym: xkb::Keysym) -> bool {
+    modifiers.logo && !modifiers.ctrl && !modifiers.alt && !modifiers.shift && keysym == xkb::Keysym::Escape
+}
+
+impl NanaimoState {
+    /// Whether `surface` holds an active inhibitor, so compositor keybindings pass through to it.
+    pub fn shortcuts_inhibited(&self, surface: &WlSurface) -> bool {
+        self.seat
+            .keyboard_shortcuts_inhibitor_for_surface(surface)
+            .is_some_and(|inhibitor| inhibitor.is_active())
+    }
+}
+
+/// Turns `inhibitor` off, or back on if the escape combo turned it off before.
+pub fn toggle_shortcuts_inhibitor(inhibitor: &KeyboardShortcutsInhibitor) {
+    if inhibitor.is_active() {
+        tracing::info!("Restoring compositor shortcuts");
+        inhibitor.inactivate();
+    } else {
+        inhibitor.activate();
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use smithay::{
+    backend::input::{ButtonState, KeyState},
+    input::{keyboard::Keycode, pointer::AxisFrame},
+    output::Output,
+    reexports::wayland_server::Client,
+    utils::{Logical, Point, Rectangle},
+};
+
+use crate::protocols::virtual_keyboard::VirtualKeyboardHandler;
+use crate::protocols::virtual_pointer::VirtualPointerHandler;
+use crate::state::{ClientState, NanaimoState};
+
+/// The executables in `input.virtual_input_clients`, with symlinks resolved. Global filters run
+/// without the state, so this is shared with them and updated on config reloads.
+#[derive(Clone, Default)]
+pub struct InputClientAllowlist(Arc<RwLock<Vec<PathBuf>>>);
+
+impl InputClientAllowlist {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let allowlist = Self::default();
+        allowlist.set(paths);
+        allowlist
+    }
+
+    pub fn set(&self, paths: &[PathBuf]) {
+        let paths = paths.iter().filter_map(|path| std::fs::canonicalize(path).ok()).collect();
+        *self.0.write().unwrap() = paths;
+    }
+
+    /// Whether `client` ran an allowlisted executable when it connected.
+    pub fn allows(&self, client: &Client) -> bool {
+        client
+            .get_data::<ClientState>()
+            .and_then(|data| data.executable.get())
+            .is_some_and(|exe| self.0.read().unwrap().contains(exe))
+    }
+}
+
+impl VirtualKeyboardHandler for NanaimoState {
+    fn virtual_keyboard_allowed(&self, client: &Client) -> bool {
+        self.may_inject_input(client)
+    }
+
+    fn virtual_keyboard_key(&mut self, keymap: &str, keycode: Keycode, state: KeyState, time: u32) {
+        self.use_virtual_keymap(keymap);
+        self.on_keyboard_key(keycode, state, time);
+    }
+
+    fn virtual_keyboard_modifiers(&mut self, keymap: &str, depressed: u32, latched: u32, locked: u32, group: u32) {
+        self.use_virtual_keymap(keymap);
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let mut modifiers = keyboard.modifier_state();
+        modifiers.serialized.depressed = depressed;
+        modifiers.serialized.latched = latched;
+        modifiers.serialized.locked = locked;
+        modifiers.serialized.layout_effective = group;
+        keyboard.set_modifier_state(modifiers);
+        keyboard.advertise_modifier_state(self);
+    }
+}
+
+impl VirtualPointerHandler for NanaimoState {
+    fn virtual_pointer_allowed(&self, client: &Client) -> bool {
+        self.may_inject_input(client)
+    }
+
+    fn virtual_pointer_motion(&mut self, delta: Point<f64, Logical>, time: u32) {
+        self.on_pointer_move_relative(delta, delta, u64::from(time) * 1000);
+    }
+
+    fn virtual_pointer_motion_absolute(&mut self, output: Option<&Output>, position: Point<f64, Logical>, time: u32) {
+        let area = match output {
+            Some(output) => self.space.output_geometry(output),
+            None => self
+                .space
+                .outputs()
+                .filter_map(|output| self.space.output_geometry(output))
+                .reduce(|a, b| a.merge(b)),
+        };
+        let Some(area) = area.map(Rectangle::to_f64) else {
+            return;
+        };
+        let pos = area.loc + Point::from((position.x * area.size.w, position.y * area.size.h));
+        self.on_pointer_move_absolute(pos, time);
+    }
+
+    fn virtual_pointer_button(&mut self, button: u32, state: ButtonState, time: u32) {
+        self.on_pointer_button(button, state, time);
+    }
+
+    fn virtual_pointer_axis(&mut self, frame: AxisFrame) {
+        self.notify_activity();
+        self.on_pointer_axis_frame(frame);
+    }
+}
+
+impl NanaimoState {
+    /// Remembers the executable a newly connected client runs, for [`InputClientAllowlist`].
+    /// It is looked up with symlinks resolved, so a client can't get in by renaming or linking a
+    /// binary.
+    pub fn record_client_executable(&self, client: &Client) {
+        let Ok(credentials) = client.get_credentials(&self.display_handle) else {
+            return;
+        };
+        let Ok(exe) = std::fs::canonicalize(format!("/proc/{}/exe", credentials.pid)) else {
+            return;
+        };
+        if let Some(data) = client.get_data::<ClientState>() {
+            let _ = data.executable.set(exe);
+        }
+    }
+
+    /// Whether `client` is listed in `input.virtual_input_clients`.
+    fn may_inject_input(&self, client: &Client) -> bool {
+        let allowed = self.input_client_allowlist.allows(client);
+        if !allowed {
+            let exe = client.get_data::<ClientState>().and_then(|data| data.executable.get());
+            tracing::warn!("Refusing virtual input from {:?}", exe);
+        }
+        allowed
+    }
+}
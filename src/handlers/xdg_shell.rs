@@ -16,7 +16,7 @@ use smithay::{
 };
 use std::cell::RefCell;
 
-use crate::{grabs::cursor_icon_for_edge, state::NanaimoState};
+use crate::{grabs::cursor_icon_for_edge, ipc::IpcEvent, state::NanaimoState, window::window_id};
 
 impl XdgShellHandler for NanaimoState {
     fn xdg_shell_state(&mut self) -> &mut XdgShellState {
@@ -27,6 +27,7 @@ impl XdgShellHandler for NanaimoState {
         tracing::info!("New toplevel surface created: {:?}", surface);
         let window = Window::new_wayland_window(surface.clone());
         self.space.map_element(window.clone(), (0, 0), true);
+        self.send_window_scale(&window);
 
         // Configuration
         surface.with_pending_state(|state| {
@@ -44,6 +45,48 @@ impl XdgShellHandler for NanaimoState {
 
         self.animation_manager.start_fade_in(&window);
         tracing::info!("Window mapped and animation started");
+
+        let location = self.space.element_location(&window).unwrap_or_default();
+        let info = self.window_info(&window, self.workspaces.active, location);
+        self.ipc_event(IpcEvent::WindowOpened { window: info });
+    }
+
+    fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        let window = self
+            .all_windows()
+            .into_iter()
+            .find(|(w, _, _)| w.toplevel().map(|tl| tl == &surface).unwrap_or(false));
+        if let Some((window, _, location)) = window {
+            if self.space.elements().any(|w| w == &window) {
+                let focused = self.focused_window().as_ref() == Some(&window);
+                self.space.unmap_elem(&window);
+                self.animation_manager.start_fade_out(&window, location);
+                if focused {
+                    self.restore_focus();
+                }
+            }
+            self.urgent_windows.remove(&window_id(&window).0);
+            self.ipc_event(IpcEvent::WindowClosed { id: window_id(&window).0 });
+        }
+    }
+
+    fn title_changed(&mut self, surface: ToplevelSurface) {
+        self.toplevel_info_changed(&surface);
+    }
+
+    fn app_id_changed(&mut self, surface: ToplevelSurface) {
+        self.toplevel_info_changed(&surface);
+    }
+
+    fn minimize_request(&mut self, surface: ToplevelSurface) {
+        let window = self
+            .space
+            .elements()
+            .find(|w| w.toplevel().map(|tl| tl == &surface).unwrap_or(false))
+            .cloned();
+        if let Some(window) = window {
+            self.minimize_window(&window);
+        }
     }
 
     fn new_popup(&mut self, _surface: PopupSurface, _positioner: PositionerState) {
@@ -91,10 +134,24 @@ impl XdgShellHandler for NanaimoState {
             .cloned();
         if let Some(window) = window {
             let seat = Seat::from_resource(&wl_seat).unwrap();
-            let pointer = seat.get_pointer().unwrap();
-            let start_data = pointer.grab_start_data().unwrap();
             let initial_window_location = self.space.element_location(&window).unwrap();
 
+            // The serial tells whether a finger or the pointer started the move.
+            if let Some(touch) = seat.get_touch().filter(|touch| touch.has_grab(serial)) {
+                let Some(start_data) = touch.grab_start_data() else {
+                    return;
+                };
+                let grab = crate::grabs::TouchMoveSurfaceGrab {
+                    start_data,
+                    window,
+                    initial_window_location,
+                };
+                touch.set_grab(self, grab, serial);
+                return;
+            }
+
+            let pointer = seat.get_pointer().unwrap();
+            let start_data = pointer.grab_start_data().unwrap();
             let grab = crate::grabs::PointerMoveSurfaceGrab {
                 start_data,
                 window,
@@ -119,11 +176,27 @@ impl XdgShellHandler for NanaimoState {
             .cloned();
         if let Some(window) = window {
             let seat = Seat::from_resource(&wl_seat).unwrap();
-            let pointer = seat.get_pointer().unwrap();
-            let start_data = pointer.grab_start_data().unwrap();
             let initial_window_location = self.space.element_location(&window).unwrap();
             let initial_window_size = window.geometry().size;
 
+            if let Some(touch) = seat.get_touch().filter(|touch| touch.has_grab(serial)) {
+                let Some(start_data) = touch.grab_start_data() else {
+                    return;
+                };
+                let grab = crate::grabs::TouchResizeSurfaceGrab {
+                    start_data,
+                    window,
+                    edges,
+                    initial_window_location,
+                    initial_window_size,
+                    last_window_size: initial_window_size,
+                };
+                touch.set_grab(self, grab, serial);
+                return;
+            }
+
+            let pointer = seat.get_pointer().unwrap();
+            let start_data = pointer.grab_start_data().unwrap();
             let grab = crate::grabs::PointerResizeSurfaceGrab {
                 start_data,
                 window,
@@ -139,6 +212,19 @@ impl XdgShellHandler for NanaimoState {
     }
 }
 
+impl NanaimoState {
+    fn toplevel_info_changed(&mut self, surface: &ToplevelSurface) {
+        let window = self
+            .all_windows()
+            .into_iter()
+            .find(|(w, _, _)| w.toplevel().map(|tl| tl == surface).unwrap_or(false));
+        if let Some((window, workspace, location)) = window {
+            let info = self.window_info(&window, workspace, location);
+            self.ipc_event(IpcEvent::WindowChanged { window: info });
+        }
+    }
+}
+
 impl XdgDecorationHandler for NanaimoState {
     fn new_decoration(&mut self, _toplevel: ToplevelSurface) {}
     fn request_mode(
@@ -0,0 +1,243 @@
+use std::os::fd::OwnedFd;
+
+use smithay::{
+    desktop::Window,
+    input::{dnd::{GrabType, Source}, pointer::Focus},
+    utils::{Logical, Rectangle},
+    wayland::{
+        selection::{
+            SelectionTarget,
+            data_device::{
+                clear_data_device_selection, current_data_device_selection_userdata,
+                request_data_device_client_selection, set_data_device_selection,
+            },
+            primary_selection::{
+                clear_primary_selection, current_primary_selection_userdata,
+                request_primary_client_selection, set_primary_selection,
+            },
+        },
+        xwayland_shell::{XWaylandShellHandler, XWaylandShellState},
+    },
+    xwayland::{
+        X11Surface, X11Wm, XwmHandler,
+        xwm::{Reorder, ResizeEdge as X11ResizeEdge, XwmId},
+    },
+};
+
+use crate::grabs::{PointerMoveSurfaceGrab, PointerResizeSurfaceGrab, ResizeEdge, cursor_icon_for_edge};
+use crate::ipc::IpcEvent;
+use crate::state::NanaimoState;
+use crate::window::window_id;
+
+impl XWaylandShellHandler for NanaimoState {
+    fn xwayland_shell_state(&mut self) -> &mut XWaylandShellState {
+        &mut self.xwayland_shell_state
+    }
+}
+
+impl NanaimoState {
+    fn window_for_x11(&self, surface: &X11Surface) -> Option<Window> {
+        self.space
+            .elements()
+            .find(|w| w.x11_surface() == Some(surface))
+            .cloned()
+    }
+}
+
+impl XwmHandler for NanaimoState {
+    fn xwm_state(&mut self, _xwm: XwmId) -> &mut X11Wm {
+        self.xwm.as_mut().expect("X11 event without a running window manager")
+    }
+
+    fn new_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
+
+    fn new_override_redirect_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
+
+    fn map_window_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        tracing::info!("Mapping X11 window: {:?}", surface);
+        if let Err(err) = surface.set_mapped(true) {
+            tracing::warn!("Failed to map X11 window: {}", err);
+            return;
+        }
+        let location = surface.geometry().loc;
+        let window = Window::new_x11_window(surface.clone());
+        self.space.map_element(window.clone(), location, true);
+        if let Some(bbox) = self.space.element_bbox(&window) {
+            let _ = surface.configure(Some(bbox));
+        }
+        self.send_window_scale(&window);
+
+        let serial = self.serial_counter.next_serial();
+        self.focus_window(&window, serial);
+        self.animation_manager.start_fade_in(&window);
+
+        let info = self.window_info(&window, self.workspaces.active, location);
+        self.ipc_event(IpcEvent::WindowOpened { window: info });
+    }
+
+    fn mapped_override_redirect_window(&mut self, _xwm: XwmId, surface: X11Surface) {
+        // Menus, tooltips and the like: placed where the client asked. They stay in `space` so they
+        // are drawn and found by `window_for_x11`, but focus and window lists skip them.
+        let location = surface.geometry().loc;
+        let window = Window::new_x11_window(surface);
+        self.space.map_element(window, location, true);
+    }
+
+    fn unmapped_window(&mut self, _xwm: XwmId, surface: X11Surface) {
+        if let Some(window) = self.window_for_x11(&surface) {
+            let location = self.space.element_location(&window).unwrap_or_default();
+            let focused = self.focused_window().as_ref() == Some(&window);
+            self.space.unmap_elem(&window);
+            if !surface.is_override_redirect() {
+                self.animation_manager.start_fade_out(&window, location);
+                self.urgent_windows.remove(&window_id(&window).0);
+                self.ipc_event(IpcEvent::WindowClosed { id: window_id(&window).0 });
+            }
+            if focused {
+                self.restore_focus();
+            }
+        }
+        if !surface.is_override_redirect() {
+            let _ = surface.set_mapped(false);
+        }
+    }
+
+    fn destroyed_window(&mut self, _xwm: XwmId, _surface: X11Surface) {}
+
+    fn configure_request(
+        &mut self,
+        _xwm: XwmId,
+        surface: X11Surface,
+        _x: Option<i32>,
+        _y: Option<i32>,
+        w: Option<u32>,
+        h: Option<u32>,
+        _reorder: Option<Reorder>,
+    ) {
+        // Position is ours to decide once a window is managed; size requests are honoured.
+        let mut geometry = surface.geometry();
+        if let Some(w) = w {
+            geometry.size.w = w as i32;
+        }
+        if let Some(h) = h {
+            geometry.size.h = h as i32;
+        }
+        let _ = surface.configure(geometry);
+    }
+
+    fn configure_notify(
+        &mut self,
+        _xwm: XwmId,
+        surface: X11Surface,
+        geometry: Rectangle<i32, Logical>,
+        _above: Option<u32>,
+    ) {
+        if !surface.is_override_redirect() {
+            return;
+        }
+        if let Some(window) = self.window_for_x11(&surface) {
+            self.space.map_element(window, geometry.loc, false);
+        }
+    }
+
+    fn resize_request(&mut self, _xwm: XwmId, surface: X11Surface, _button: u32, edge: X11ResizeEdge) {
+        let Some(window) = self.window_for_x11(&surface) else {
+            return;
+        };
+        let Some(start_data) = self.pointer.grab_start_data() else {
+            return;
+        };
+        let edges = match edge {
+            X11ResizeEdge::Top => ResizeEdge::Top,
+            X11ResizeEdge::Bottom => ResizeEdge::Bottom,
+            X11ResizeEdge::Left => ResizeEdge::Left,
+            X11ResizeEdge::Right => ResizeEdge::Right,
+            X11ResizeEdge::TopLeft => ResizeEdge::TopLeft,
+            X11ResizeEdge::TopRight => ResizeEdge::TopRight,
+            X11ResizeEdge::BottomLeft => ResizeEdge::BottomLeft,
+            X11ResizeEdge::BottomRight => ResizeEdge::BottomRight,
+        };
+        let initial_window_location = self.space.element_location(&window).unwrap();
+        let initial_window_size = window.geometry().size;
+
+        let grab = PointerResizeSurfaceGrab {
+            start_data,
+            window,
+            edges,
+            initial_window_location,
+            initial_window_size,
+            last_window_size: initial_window_size,
+        };
+        let pointer = self.pointer.clone();
+        let serial = self.serial_counter.next_serial();
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+        self.cursor_status = smithay::input::pointer::CursorImageStatus::Named(cursor_icon_for_edge(edges));
+    }
+
+    fn allow_selection_access(&mut self, xwm: XwmId, _selection: SelectionTarget) -> bool {
+        // Only the focused X11 client may read or take a selection.
+        self.focused_window()
+            .and_then(|window| window.x11_surface().and_then(|x11| x11.xwm_id()))
+            .is_some_and(|id| id == xwm)
+    }
+
+    fn send_selection(&mut self, _xwm: XwmId, selection: SelectionTarget, mime_type: String, fd: OwnedFd) {
+        let result = match selection {
+            SelectionTarget::Clipboard => request_data_device_client_selection(&self.seat, mime_type, fd),
+            SelectionTarget::Primary => request_primary_client_selection(&self.seat, mime_type, fd),
+        };
+        if let Err(err) = result {
+            tracing::warn!("Failed to send {:?} selection to XWayland: {:?}", selection, err);
+        }
+    }
+
+    fn new_selection(&mut self, _xwm: XwmId, selection: SelectionTarget, mime_types: Vec<String>) {
+        tracing::debug!("X11 client set {:?} selection: {:?}", selection, mime_types);
+        match selection {
+            SelectionTarget::Clipboard => set_data_device_selection(&self.display_handle, &self.seat, mime_types, ()),
+            SelectionTarget::Primary => set_primary_selection(&self.display_handle, &self.seat, mime_types, ()),
+        }
+    }
+
+    fn cleared_selection(&mut self, _xwm: XwmId, selection: SelectionTarget) {
+        // Only clear selections we own on behalf of XWayland, not ones a Wayland client set since.
+        match selection {
+            SelectionTarget::Clipboard => {
+                if current_data_device_selection_userdata(&self.seat).is_some() {
+                    clear_data_device_selection(&self.display_handle, &self.seat);
+                }
+            }
+            SelectionTarget::Primary => {
+                if current_primary_selection_userdata(&self.seat).is_some() {
+                    clear_primary_selection(&self.display_handle, &self.seat);
+                }
+            }
+        }
+    }
+
+    // An X11 client started dragging: the window manager took `XdndSelection` on its behalf, and
+    // the drag now runs on the Wayland side like any other.
+    fn dnd_requested<S: Source>(&mut self, _xwm: XwmId, source: S) {
+        let seat = self.seat.clone();
+        let serial = self.serial_counter.next_serial();
+        self.start_dnd(source, seat, serial, GrabType::Pointer);
+    }
+
+    fn move_request(&mut self, _xwm: XwmId, surface: X11Surface, _button: u32) {
+        let Some(window) = self.window_for_x11(&surface) else {
+            return;
+        };
+        let Some(start_data) = self.pointer.grab_start_data() else {
+            return;
+        };
+        let initial_window_location = self.space.element_location(&window).unwrap();
+        let grab = PointerMoveSurfaceGrab {
+            start_data,
+            window,
+            initial_window_location,
+        };
+        let pointer = self.pointer.clone();
+        let serial = self.serial_counter.next_serial();
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+    }
+}
@@ -0,0 +1,147 @@
+//! `nanaimo msg`: a small command-line client for the IPC socket.
+
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use super::{EventKind, Request, Response, SOCKET_ENV};
+
+const USAGE: &str = "usage: nanaimo msg <command>
+
+commands:
+  outputs | windows | workspaces
+  focus-window <id>
+  close-window <id>
+  move-window <id> [<x> <y>] [--workspace <n>]
+  action <action...>          e.g. `action spawn foot`
+  reload-config
+  auto-settings
+  screenshot [--output <name>] [<path>]
+  screenshot-window [<id>] [<path>]
+  thumbnail [<id>] [<path>]
+  subscribe [focus] [window] [workspace] [auto]
+  raw <json>";
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let request = parse_args(args).map_err(|err| format!("{}\n\n{}", err, USAGE))?;
+    let subscribe = matches!(request, Request::Subscribe { .. });
+
+    let path = std::env::var_os(SOCKET_ENV).ok_or_else(|| format!("{} is not set, is nanaimo running?", SOCKET_ENV))?;
+    let mut stream = UnixStream::connect(&path)?;
+    let mut line = serde_json::to_vec(&request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    match serde_json::from_str::<Response>(&line)? {
+        Response::Ok(serde_json::Value::Null) => {}
+        Response::Ok(value) => println!("{}", serde_json::to_string_pretty(&value)?),
+        Response::Error(err) => return Err(err.into()),
+    }
+
+    if subscribe {
+        for line in reader.lines() {
+            println!("{}", line?);
+        }
+    }
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Request, String> {
+    let (command, rest) = args.split_first().ok_or("missing command")?;
+    let id = |rest: &[String]| -> Result<u64, String> {
+        rest.first()
+            .ok_or("missing window id")?
+            .parse()
+            .map_err(|_| "window id must be a number".to_string())
+    };
+
+    Ok(match command.as_str() {
+        "outputs" => Request::Outputs,
+        "windows" => Request::Windows,
+        "workspaces" => Request::Workspaces,
+        "reload-config" => Request::ReloadConfig,
+        "auto-settings" => Request::AutoSettings,
+        "focus-window" => Request::FocusWindow { id: id(rest)? },
+        "close-window" => Request::CloseWindow { id: id(rest)? },
+        "move-window" => {
+            let id = id(rest)?;
+            let mut position = Vec::new();
+            let mut workspace = None;
+            let mut rest = rest[1..].iter();
+            while let Some(arg) = rest.next() {
+                if arg == "--workspace" {
+                    let n = rest.next().ok_or("--workspace needs a number")?;
+                    workspace = Some(n.parse().map_err(|_| "workspace must be a number")?);
+                } else {
+                    position.push(arg.parse::<i32>().map_err(|_| format!("invalid coordinate {:?}", arg))?);
+                }
+            }
+            let (x, y) = match position.as_slice() {
+                [] => (None, None),
+                [x, y] => (Some(*x), Some(*y)),
+                _ => return Err("move-window needs both x and y".into()),
+            };
+            if x.is_none() && workspace.is_none() {
+                return Err("move-window needs a position or --workspace".into());
+            }
+            Request::MoveWindow { id, x, y, workspace }
+        }
+        "screenshot" => {
+            let (output, rest) = match rest {
+                [flag, name, rest @ ..] if flag == "--output" => (Some(name.clone()), rest),
+                [flag] if flag == "--output" => return Err("--output needs a name".into()),
+                rest => (None, rest),
+            };
+            Request::Screenshot { output, path: screenshot_path(rest)? }
+        }
+        "screenshot-window" | "thumbnail" => {
+            let (id, rest) = match rest.split_first() {
+                Some((id, rest)) if id.parse::<u64>().is_ok() => (id.parse().ok(), rest),
+                _ => (None, rest),
+            };
+            let path = screenshot_path(rest)?;
+            if command == "thumbnail" {
+                Request::Thumbnail { id, path }
+            } else {
+                Request::ScreenshotWindow { id, path }
+            }
+        }
+        "action" if rest.is_empty() => return Err("missing action".into()),
+        "action" => Request::Action { action: rest.join(" ") },
+        "subscribe" => {
+            let events = if rest.is_empty() {
+                vec![EventKind::Focus, EventKind::Window, EventKind::Workspace, EventKind::Auto]
+            } else {
+                rest.iter()
+                    .map(|kind| {
+                        serde_json::from_value(serde_json::Value::String(kind.clone()))
+                            .map_err(|_| format!("unknown event kind {:?}", kind))
+                    })
+                    .collect::<Result<_, _>>()?
+            };
+            Request::Subscribe { events }
+        }
+        "raw" => {
+            let json = rest.join(" ");
+            serde_json::from_str(&json).map_err(|err| format!("invalid request: {}", err))?
+        }
+        other => return Err(format!("unknown command {:?}", other)),
+    })
+}
+
+/// The optional path argument, made absolute since the compositor has its own working directory.
+fn screenshot_path(rest: &[String]) -> Result<Option<PathBuf>, String> {
+    match rest {
+        [] => Ok(None),
+        [path] => std::path::absolute(path)
+            .map(Some)
+            .map_err(|err| format!("invalid path {:?}: {}", path, err)),
+        _ => Err("too many arguments".into()),
+    }
+}
@@ -0,0 +1,165 @@
+//! JSON IPC for scripts and status bars.
+//!
+//! Every request and reply is a single line of JSON on the socket named by `NANAIMO_SOCKET`.
+//! A connection that sends `subscribe` additionally receives one line per matching event.
+
+pub mod client;
+pub mod server;
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+pub use server::IpcServer;
+
+pub const SOCKET_ENV: &str = "NANAIMO_SOCKET";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    Outputs,
+    Windows,
+    Workspaces,
+    FocusWindow {
+        id: u64,
+    },
+    /// Moves a window to a position on its workspace, to another workspace (1-based), or both.
+    MoveWindow {
+        id: u64,
+        #[serde(default)]
+        x: Option<i32>,
+        #[serde(default)]
+        y: Option<i32>,
+        #[serde(default)]
+        workspace: Option<usize>,
+    },
+    CloseWindow {
+        id: u64,
+    },
+    /// Runs an action using the same syntax as keybindings, e.g. `"spawn foot"`.
+    Action {
+        action: String,
+    },
+    ReloadConfig,
+    /// The `[auto]` settings the shell draws the Auto area with.
+    AutoSettings,
+    /// Saves an output (by name, or the one under the pointer) as PNG. Replies with the path.
+    Screenshot {
+        #[serde(default)]
+        output: Option<String>,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// Saves a window (by id, or the focused one) as PNG. Replies with the path.
+    ScreenshotWindow {
+        #[serde(default)]
+        id: Option<u64>,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// Saves the cached thumbnail of a window (by id, or the focused one) as PNG. Replies with
+    /// the path.
+    Thumbnail {
+        #[serde(default)]
+        id: Option<u64>,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    Subscribe {
+        events: Vec<EventKind>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Response {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    Focus,
+    Window,
+    Workspace,
+    Auto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum IpcEvent {
+    FocusChanged { id: Option<u64> },
+    WindowOpened { window: WindowInfo },
+    WindowChanged { window: WindowInfo },
+    WindowClosed { id: u64 },
+    WindowMoved { id: u64, workspace: usize },
+    WorkspaceActivated { index: usize },
+    /// The user asked for the Auto sidebar to be shown or hidden. The shell owns the sidebar.
+    AutoToggled,
+    /// The `[auto]` settings changed on a config reload.
+    AutoSettingsChanged { settings: AutoSettings },
+}
+
+impl IpcEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            IpcEvent::FocusChanged { .. } => EventKind::Focus,
+            IpcEvent::WindowOpened { .. }
+            | IpcEvent::WindowChanged { .. }
+            | IpcEvent::WindowClosed { .. }
+            | IpcEvent::WindowMoved { .. } => EventKind::Window,
+            IpcEvent::WorkspaceActivated { .. } => EventKind::Workspace,
+            IpcEvent::AutoToggled | IpcEvent::AutoSettingsChanged { .. } => EventKind::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputInfo {
+    pub name: String,
+    pub make: String,
+    pub model: String,
+    pub enabled: bool,
+    pub width: i32,
+    pub height: i32,
+    /// Refresh rate in mHz.
+    pub refresh: i32,
+    pub x: i32,
+    pub y: i32,
+    pub scale: f64,
+    pub transform: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub id: u64,
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    /// 1-based workspace index.
+    pub workspace: usize,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub focused: bool,
+    /// Set when the window asked for focus without being allowed to take it.
+    pub urgent: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoSettings {
+    /// Width of the Auto sidebar in logical pixels.
+    pub width: i32,
+    /// RGBA, each between 0 and 1.
+    pub glow_color: [f32; 4],
+    pub glow_intensity: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceInfo {
+    /// 1-based workspace index.
+    pub index: usize,
+    pub active: bool,
+    pub windows: usize,
+}
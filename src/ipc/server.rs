@@ -0,0 +1,349 @@
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+};
+
+use smithay::{
+    desktop::Window,
+    reexports::calloop::{
+        LoopHandle, PostAction,
+        generic::Generic,
+        Interest, Mode,
+    },
+    utils::{Logical, Point},
+};
+
+use super::{AutoSettings, EventKind, IpcEvent, OutputInfo, Request, Response, SOCKET_ENV, WindowInfo, WorkspaceInfo};
+use crate::actions::Action;
+use crate::output::transform_name;
+use crate::screenshot::ScreenshotTarget;
+use crate::state::NanaimoState;
+use crate::window::{close_window, window_id, window_title_and_app_id};
+use crate::workspaces::WORKSPACE_COUNT;
+
+/// Requests longer than this are treated as garbage and the connection is dropped.
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+
+struct Subscriber {
+    stream: UnixStream,
+    events: Vec<EventKind>,
+}
+
+pub struct IpcServer {
+    path: PathBuf,
+    subscribers: Vec<Subscriber>,
+}
+
+impl IpcServer {
+    /// Binds the socket, registers it with the event loop and exports `NANAIMO_SOCKET`.
+    ///
+    /// The socket lives in `XDG_RUNTIME_DIR`, which only the user can reach. Without it there is
+    /// no IPC: anyone connected could spawn programs as the user.
+    pub fn start(loop_handle: &LoopHandle<'static, NanaimoState>, wayland_display: &str) -> io::Result<Self> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+        let path = runtime_dir.join(format!("nanaimo.{}.{}.sock", wayland_display, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        let source = Generic::new(listener.try_clone()?, Interest::READ, Mode::Level);
+        let handle = loop_handle.clone();
+        loop_handle
+            .insert_source(source, move |_, _, _state| {
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(err) = insert_client(&handle, stream) {
+                                tracing::warn!("Failed to add IPC client: {}", err);
+                            }
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(err) => {
+                            tracing::warn!("IPC accept failed: {}", err);
+                            break;
+                        }
+                    }
+                }
+                Ok(PostAction::Continue)
+            })
+            .map_err(|err| io::Error::other(err.to_string()))?;
+
+        tracing::info!("IPC listening on {}={}", SOCKET_ENV, path.display());
+        unsafe { std::env::set_var(SOCKET_ENV, &path); }
+        Ok(Self {
+            path,
+            subscribers: Vec::new(),
+        })
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn insert_client(handle: &LoopHandle<'static, NanaimoState>, stream: UnixStream) -> io::Result<()> {
+    // A client that stops reading must never stall the compositor, so writes that would block
+    // simply drop the connection.
+    stream.set_nonblocking(true)?;
+    let source = Generic::new(stream.try_clone()?, Interest::READ, Mode::Level);
+    let mut buffer = Vec::new();
+
+    handle
+        .insert_source(source, move |_, _, state| {
+            let mut chunk = [0u8; 4096];
+            let read = match (&stream).read(&mut chunk) {
+                Ok(0) => return Ok(PostAction::Remove),
+                Ok(read) => read,
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {
+                    return Ok(PostAction::Continue);
+                }
+                Err(_) => return Ok(PostAction::Remove),
+            };
+            buffer.extend_from_slice(&chunk[..read]);
+
+            while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=newline).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let response = match serde_json::from_slice::<Request>(&line) {
+                    Ok(Request::Subscribe { events }) => state.add_ipc_subscriber(&stream, events),
+                    // Screenshots are answered once the file is written.
+                    Ok(Request::Screenshot { output, path }) => {
+                        match state.queue_ipc_screenshot(&stream, ScreenshotTarget::Output(output), path) {
+                            Ok(()) => continue,
+                            Err(err) => Response::Error(err),
+                        }
+                    }
+                    Ok(Request::ScreenshotWindow { id, path }) => {
+                        match state.queue_ipc_screenshot(&stream, ScreenshotTarget::Window(id), path) {
+                            Ok(()) => continue,
+                            Err(err) => Response::Error(err),
+                        }
+                    }
+                    Ok(Request::Thumbnail { id, path }) => {
+                        match state.queue_ipc_screenshot(&stream, ScreenshotTarget::Thumbnail(id), path) {
+                            Ok(()) => continue,
+                            Err(err) => Response::Error(err),
+                        }
+                    }
+                    Ok(request) => state.handle_ipc_request(request),
+                    Err(err) => Response::Error(format!("invalid request: {}", err)),
+                };
+                if write_line(&stream, &response).is_err() {
+                    return Ok(PostAction::Remove);
+                }
+            }
+
+            if buffer.len() > MAX_REQUEST_LEN {
+                tracing::warn!("Dropping IPC client that sent an oversized request");
+                return Ok(PostAction::Remove);
+            }
+            Ok(PostAction::Continue)
+        })
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    Ok(())
+}
+
+fn reply<T: serde::Serialize>(value: &T) -> Response {
+    serde_json::to_value(value)
+        .map(Response::Ok)
+        .unwrap_or_else(|err| Response::Error(err.to_string()))
+}
+
+pub(crate) fn write_line<T: serde::Serialize>(mut stream: &UnixStream, value: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+impl NanaimoState {
+    /// Sends `event` to every subscriber interested in its kind.
+    pub fn ipc_event(&mut self, event: IpcEvent) {
+        let Some(ipc) = self.ipc.as_mut() else {
+            return;
+        };
+        let kind = event.kind();
+        ipc.subscribers
+            .retain(|subscriber| !subscriber.events.contains(&kind) || write_line(&subscriber.stream, &event).is_ok());
+    }
+
+    fn add_ipc_subscriber(&mut self, stream: &UnixStream, events: Vec<EventKind>) -> Response {
+        let Some(ipc) = self.ipc.as_mut() else {
+            return Response::Error("IPC is not running".into());
+        };
+        match stream.try_clone() {
+            Ok(stream) => {
+                ipc.subscribers.push(Subscriber { stream, events });
+                Response::Ok(serde_json::Value::Null)
+            }
+            Err(err) => Response::Error(format!("failed to subscribe: {}", err)),
+        }
+    }
+
+    fn queue_ipc_screenshot(
+        &mut self,
+        stream: &UnixStream,
+        target: ScreenshotTarget,
+        path: Option<PathBuf>,
+    ) -> Result<(), String> {
+        let reply = stream.try_clone().map_err(|err| format!("failed to queue screenshot: {}", err))?;
+        self.request_screenshot(target, path, Some(reply)).map(|_| ())
+    }
+
+    pub fn window_info(&self, window: &Window, workspace: usize, location: Point<i32, Logical>) -> WindowInfo {
+        let (title, app_id) = window_title_and_app_id(window);
+        let size = window.geometry().size;
+        WindowInfo {
+            id: window_id(window).0,
+            title,
+            app_id,
+            workspace: workspace + 1,
+            x: location.x,
+            y: location.y,
+            width: size.w,
+            height: size.h,
+            focused: self.focused_window().as_ref() == Some(window),
+            urgent: self.urgent_windows.contains(&window_id(window).0),
+        }
+    }
+
+    pub fn auto_settings(&self) -> AutoSettings {
+        let auto = &self.config.auto;
+        AutoSettings {
+            width: auto.width,
+            glow_color: auto.glow_color,
+            glow_intensity: auto.glow_intensity,
+        }
+    }
+
+    /// Looks a window up by id on any workspace.
+    pub fn find_window(&self, id: u64) -> Option<(Window, usize, Point<i32, Logical>)> {
+        self.all_windows().into_iter().find(|(w, _, _)| window_id(w).0 == id)
+    }
+
+    fn handle_ipc_request(&mut self, request: Request) -> Response {
+        tracing::debug!("IPC request: {:?}", request);
+        match request {
+            Request::Outputs => {
+                let outputs: Vec<OutputInfo> = self
+                    .outputs
+                    .iter()
+                    .map(|output| {
+                        let props = output.physical_properties();
+                        let mode = output.current_mode();
+                        let location = output.current_location();
+                        OutputInfo {
+                            name: output.name(),
+                            make: props.make,
+                            model: props.model,
+                            enabled: self.space.outputs().any(|o| o == output),
+                            width: mode.map(|m| m.size.w).unwrap_or(0),
+                            height: mode.map(|m| m.size.h).unwrap_or(0),
+                            refresh: mode.map(|m| m.refresh).unwrap_or(0),
+                            x: location.x,
+                            y: location.y,
+                            scale: output.current_scale().fractional_scale(),
+                            transform: transform_name(output.current_transform()).into(),
+                        }
+                    })
+                    .collect();
+                reply(&outputs)
+            }
+            Request::Windows => {
+                let windows: Vec<WindowInfo> = self
+                    .all_windows()
+                    .iter()
+                    .map(|(window, workspace, location)| self.window_info(window, *workspace, *location))
+                    .collect();
+                reply(&windows)
+            }
+            Request::Workspaces => {
+                let workspaces: Vec<WorkspaceInfo> = (0..WORKSPACE_COUNT)
+                    .map(|index| {
+                        let active = index == self.workspaces.active;
+                        WorkspaceInfo {
+                            index: index + 1,
+                            active,
+                            windows: if active {
+                                self.managed_windows().count()
+                            } else {
+                                self.workspaces.window_count(index)
+                            },
+                        }
+                    })
+                    .collect();
+                reply(&workspaces)
+            }
+            Request::FocusWindow { id } => {
+                let Some((window, _, _)) = self.find_window(id) else {
+                    return Response::Error(format!("no window with id {}", id));
+                };
+                self.activate_window(&window);
+                Response::Ok(serde_json::Value::Null)
+            }
+            Request::MoveWindow { id, x, y, workspace } => {
+                let Some((window, current, location)) = self.find_window(id) else {
+                    return Response::Error(format!("no window with id {}", id));
+                };
+                if self.workspaces.is_minimized(&window) {
+                    return Response::Error("minimized windows cannot be moved".into());
+                }
+                // Everything is checked before anything changes, so a bad request moves nothing.
+                let position = x.is_some() || y.is_some();
+                if position && current != self.workspaces.active {
+                    return Response::Error("only windows on the active workspace can be positioned".into());
+                }
+                if let Some(workspace) = workspace {
+                    if !(1..=WORKSPACE_COUNT).contains(&workspace) {
+                        return Response::Error(format!("workspace must be between 1 and {}", WORKSPACE_COUNT));
+                    }
+                    if current != self.workspaces.active {
+                        return Response::Error("only windows on the active workspace can be moved".into());
+                    }
+                }
+
+                if position {
+                    let target: Point<i32, Logical> = (x.unwrap_or(location.x), y.unwrap_or(location.y)).into();
+                    self.space.map_element(window.clone(), target, false);
+                    self.animation_manager.start_move(&window, location, target);
+                }
+                if let Some(workspace) = workspace {
+                    self.move_window_to_workspace(&window, workspace - 1);
+                }
+                Response::Ok(serde_json::Value::Null)
+            }
+            Request::CloseWindow { id } => match self.find_window(id) {
+                Some((window, _, _)) => {
+                    close_window(&window);
+                    Response::Ok(serde_json::Value::Null)
+                }
+                None => Response::Error(format!("no window with id {}", id)),
+            },
+            Request::Action { action } => match action.parse::<Action>() {
+                Ok(action) => {
+                    self.run_action(action);
+                    Response::Ok(serde_json::Value::Null)
+                }
+                Err(err) => Response::Error(err),
+            },
+            Request::ReloadConfig => match self.reload_config() {
+                Ok(()) => Response::Ok(serde_json::Value::Null),
+                Err(err) => Response::Error(err.to_string()),
+            },
+            Request::AutoSettings => reply(&self.auto_settings()),
+            Request::Subscribe { .. }
+            | Request::Screenshot { .. }
+            | Request::ScreenshotWindow { .. }
+            | Request::Thumbnail { .. } => {
+                Response::Error("this request is handled per connection".into())
+            }
+        }
+    }
+}
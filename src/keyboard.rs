@@ -0,0 +1,69 @@
+//! Keyboard layout switching. Each configured layout is compiled into a keymap of its own when it
+//! becomes active, and with `per_window_layout` every window gets back the layout it last used.
+//! Virtual keyboards bring keymaps of their own, which stand in for the layout while they type.
+
+use crate::state::NanaimoState;
+use crate::window::window_id;
+
+impl NanaimoState {
+    /// Makes layout `index` active and, with per-window layouts, remembers it for the focused
+    /// window.
+    pub fn set_keyboard_layout(&mut self, index: usize) {
+        let config = self.config.keyboard.clone();
+        let index = index.min(config.layouts.len() - 1);
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let xkb_config = config.xkb_config(index);
+        if let Err(err) = keyboard.set_xkb_config(self, xkb_config) {
+            tracing::error!("Failed to apply keyboard layout {:?}: {:?}", config.layouts[index], err);
+            return;
+        }
+        tracing::debug!("Switched to keyboard layout {:?}", config.layouts[index]);
+        self.keyboard_layout = index;
+        self.virtual_keymap = None;
+        if config.per_window_layout {
+            if let Some(window) = self.focused_window() {
+                self.window_layouts.insert(window_id(&window).0, index);
+            }
+        }
+    }
+
+    /// Switches to the layout the focused window was last typed in, or the first layout for
+    /// windows that have none yet.
+    pub fn restore_window_layout(&mut self) {
+        if !self.config.keyboard.per_window_layout {
+            return;
+        }
+        // Windows on other workspaces and minimized ones keep theirs.
+        let live: Vec<u64> = self.all_windows().iter().map(|(w, _, _)| window_id(w).0).collect();
+        self.window_layouts.retain(|id, _| live.contains(id));
+
+        let Some(window) = self.focused_window() else {
+            return;
+        };
+        let layout = self.window_layouts.get(&window_id(&window).0).copied().unwrap_or(0);
+        if layout != self.keyboard_layout {
+            self.set_keyboard_layout(layout);
+        }
+    }
+
+    /// Puts the keymap of a virtual keyboard on the seat, unless it is there already, so its key
+    /// codes and modifiers mean what its client meant.
+    pub fn use_virtual_keymap(&mut self, keymap: &str) {
+        if self.virtual_keymap.as_deref() == Some(keymap) {
+            return;
+        }
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if let Err(err) = keyboard.set_keymap_from_string(self, keymap.to_string()) {
+            tracing::warn!("Failed to apply virtual keyboard keymap: {:?}", err);
+            return;
+        }
+        self.virtual_keymap = Some(keymap.to_string());
+    }
+
+    /// Puts the configured layout back once real keys are typed after a virtual keyboard.
+    pub fn use_configured_keymap(&mut self) {
+        if self.virtual_keymap.is_some() {
+            self.set_keyboard_layout(self.keyboard_layout);
+        }
+    }
+}
@@ -2,29 +2,55 @@ use smithay::{
     backend::{
         winit::{self, WinitEvent},
         renderer::{
-            damage::OutputDamageTracker,
             gles::GlesRenderer,
+            Color32F,
             ImportMemWl,
             ImportEgl,
         },
     },
     reexports::{
         calloop::EventLoop,
-        wayland_server::Display,
+        wayland_server::{Display, Resource},
+    },
+    backend::input::{
+        Event, GestureBeginEvent, GestureEndEvent, GesturePinchUpdateEvent, GestureSwipeUpdateEvent,
+        KeyboardKeyEvent, PointerButtonEvent, PointerMotionEvent, TouchEvent,
     },
-    backend::input::{AbsolutePositionEvent, Event, PointerButtonEvent, KeyboardKeyEvent},
     input::pointer::CursorImageStatus,
 };
 use std::time::Duration;
 
 mod state;
+mod actions;
 mod animations;
+mod capture;
+mod config;
+mod cursor;
+mod dnd;
+mod focus;
+mod gestures;
 mod render;
+mod screenshot;
+mod tablet;
+mod thumbnails;
 mod grabs;
 mod handlers;
+mod keyboard;
+mod ipc;
+mod output;
+mod protocols;
+mod window;
+mod workspaces;
+mod xwayland;
 use state::{NanaimoState, ClientState};
+use output::{event_position, winit_damage_tracker};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("msg") {
+        return ipc::client::run(&args[2..]);
+    }
+
     // 1. Logging
     if std::env::var("RUST_LOG").is_err() {
         unsafe { std::env::set_var("RUST_LOG", "info,nanaimo=info,smithay=warn"); }
@@ -42,15 +68,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let listening_socket = smithay::wayland::socket::ListeningSocketSource::new_auto()?;
     let socket_name = listening_socket.socket_name().to_string_lossy().into_owned();
     tracing::info!("Listening on WAYLAND_DISPLAY={}", socket_name);
+    // Inherited by everything started through the spawn action
+    unsafe { std::env::set_var("WAYLAND_DISPLAY", &socket_name); }
 
     loop_handle.insert_source(listening_socket, move |client_stream, _, state: &mut NanaimoState| {
-        if let Err(err) = state.display_handle.insert_client(client_stream, std::sync::Arc::new(ClientState::default())) {
-            tracing::warn!("Error adding client: {}", err);
+        match state.display_handle.insert_client(client_stream, std::sync::Arc::new(ClientState::default())) {
+            Ok(client) => state.record_client_executable(&client),
+            Err(err) => tracing::warn!("Error adding client: {}", err),
         }
     })?;
     
     // 4. State
     let mut state = NanaimoState::new(&display, loop_handle.clone());
+    match ipc::IpcServer::start(&loop_handle, &socket_name) {
+        Ok(ipc) => state.ipc = Some(ipc),
+        Err(err) => tracing::warn!("Failed to start IPC: {}", err),
+    }
+    match xwayland::XWaylandLauncher::bind(&loop_handle) {
+        Ok(launcher) => {
+            // Inherited by everything started through the spawn action
+            unsafe { std::env::set_var("DISPLAY", format!(":{}", launcher.display_number)); }
+            state.xwayland = Some(launcher);
+        }
+        Err(err) => tracing::warn!("XWayland unavailable: {}", err),
+    }
 
     // 5. Winit Backend
     let (mut backend, mut winit) = winit::init::<GlesRenderer>()?;
@@ -79,14 +120,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
     );
     let _global = output.create_global::<NanaimoState>(&display_handle);
-    output.change_current_state(Some(mode), Some(smithay::utils::Transform::Flipped180), None, Some((0, 0).into()));
+    output.change_current_state(Some(mode), None, None, Some((0, 0).into()));
     output.set_preferred(mode);
     
-    // Map output to space
-    state.space.map_output(&output, (0, 0));
+    // Apply transform/scale and map output to space
+    state.outputs.push(output.clone());
+    state.apply_output_config(&output);
+    state.outputs_changed();
 
-    // Damage Tracker
-    let mut damage_tracker = OutputDamageTracker::from_output(&output);
+    // Damage Tracker, rebuilt whenever the output's mode, transform or scale changes
+    let mut damage_tracker = winit_damage_tracker(&output);
+    let mut render_params = (output.current_mode(), output.current_transform(), output.current_scale().fractional_scale());
+    let mut render_cache = render::RenderCache::<GlesRenderer>::default();
+    let mut thumbnails = thumbnails::ThumbnailCache::<GlesRenderer>::default();
 
     // 6. Run
     tracing::info!("Starting Nanaimo Compositor...");
@@ -101,9 +147,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
     )?;
     
-    loop {
+    while state.running {
         // Dispatch calloop
-        let result = event_loop.dispatch(Some(Duration::from_millis(1)), &mut state);
+        // Don't wait for events while something is animating, every frame counts.
+        let timeout = if state.animation_manager.is_animating() { Duration::ZERO } else { Duration::from_millis(1) };
+        let result = event_loop.dispatch(Some(timeout), &mut state);
         if result.is_err() {
             tracing::error!("Event loop error: {:?}", result.err());
             break;
@@ -117,68 +165,181 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             WinitEvent::Resized { size, .. } => {
                 let mode = smithay::output::Mode {
                     size,
-                    refresh: 60_000,
+                    refresh: output.current_mode().map(|m| m.refresh).unwrap_or(60_000),
                 };
                 output.change_current_state(Some(mode), None, None, None);
-                state.space.map_output(&output, (0, 0));
+                if state.space.outputs().any(|o| o == &output) {
+                    state.space.map_output(&output, output.current_location());
+                }
+                state.outputs_changed();
             }
             WinitEvent::Input(event) => {
                 use smithay::backend::input::InputEvent;
                 match event {
                     InputEvent::PointerMotionAbsolute { event } => {
-                        let output_geo = state.space.output_geometry(&output).unwrap();
-                        let final_pos = event.position_transformed(output_geo.size) + output_geo.loc.to_f64();
+                        let Some(final_pos) = event_position(&state.space, &output, &event) else {
+                            return;
+                        };
                         
                         tracing::trace!("Pointer move: logical={:?}", final_pos);
                         state.on_pointer_move_absolute(final_pos, event.time_msec());
                     }
+                    InputEvent::PointerMotion { event } => {
+                        state.on_pointer_move_relative(event.delta(), event.delta_unaccel(), event.time());
+                    }
+                    InputEvent::TouchDown { event } => {
+                        let Some(final_pos) = event_position(&state.space, &output, &event) else {
+                            return;
+                        };
+                        state.on_touch_down(event.slot(), final_pos, event.time_msec());
+                    }
+                    InputEvent::TouchMotion { event } => {
+                        let Some(final_pos) = event_position(&state.space, &output, &event) else {
+                            return;
+                        };
+                        state.on_touch_motion(event.slot(), final_pos, event.time_msec());
+                    }
+                    InputEvent::TouchUp { event } => {
+                        state.on_touch_up(event.slot(), event.time_msec());
+                    }
+                    InputEvent::TouchFrame { .. } => state.on_touch_frame(),
+                    InputEvent::TouchCancel { .. } => state.on_touch_cancel(),
                     InputEvent::PointerButton { event } => {
                         state.on_pointer_button(event.button_code(), event.state(), event.time_msec());
                     }
                     InputEvent::Keyboard { event } => {
+                        state.use_configured_keymap();
                         state.on_keyboard_key(event.key_code(), event.state(), event.time_msec());
                     }
                     InputEvent::PointerAxis { event } => {
                         state.on_pointer_axis::<winit::WinitInput>(event);
                     }
+                    InputEvent::TabletToolProximity { event } => {
+                        let Some(final_pos) = event_position(&state.space, &output, &event) else {
+                            return;
+                        };
+                        state.on_tablet_tool_proximity::<winit::WinitInput>(event, final_pos);
+                    }
+                    InputEvent::TabletToolAxis { event } => {
+                        let Some(final_pos) = event_position(&state.space, &output, &event) else {
+                            return;
+                        };
+                        state.on_tablet_tool_axis::<winit::WinitInput>(event, final_pos);
+                    }
+                    InputEvent::TabletToolTip { event } => {
+                        state.on_tablet_tool_tip::<winit::WinitInput>(event);
+                    }
+                    InputEvent::TabletToolButton { event } => {
+                        state.on_tablet_tool_button::<winit::WinitInput>(event);
+                    }
+                    InputEvent::TabletPadButton { event } => {
+                        state.on_tablet_pad_button::<winit::WinitInput>(event);
+                    }
+                    InputEvent::TabletPadRing { event } => {
+                        state.on_tablet_pad_ring::<winit::WinitInput>(event);
+                    }
+                    InputEvent::TabletPadStrip { event } => {
+                        state.on_tablet_pad_strip::<winit::WinitInput>(event);
+                    }
+                    InputEvent::DeviceAdded { device } => state.on_tablet_pad_added(&device),
+                    InputEvent::DeviceRemoved { device } => state.on_tablet_pad_removed(&device),
+                    InputEvent::GestureSwipeBegin { event } => {
+                        state.on_gesture_swipe_begin(event.fingers(), event.time_msec());
+                    }
+                    InputEvent::GestureSwipeUpdate { event } => {
+                        state.on_gesture_swipe_update(event.delta(), event.time_msec());
+                    }
+                    InputEvent::GestureSwipeEnd { event } => {
+                        state.on_gesture_swipe_end(event.cancelled(), event.time_msec());
+                    }
+                    InputEvent::GesturePinchBegin { event } => {
+                        state.on_gesture_pinch_begin(event.fingers(), event.time_msec());
+                    }
+                    InputEvent::GesturePinchUpdate { event } => {
+                        state.on_gesture_pinch_update(event.delta(), event.scale(), event.rotation(), event.time_msec());
+                    }
+                    InputEvent::GesturePinchEnd { event } => {
+                        state.on_gesture_pinch_end(event.cancelled(), event.time_msec());
+                    }
+                    InputEvent::GestureHoldBegin { event } => {
+                        state.on_gesture_hold_begin(event.fingers(), event.time_msec());
+                    }
+                    InputEvent::GestureHoldEnd { event } => {
+                        state.on_gesture_hold_end(event.cancelled(), event.time_msec());
+                    }
                     _ => (),
                 }
             }
             WinitEvent::CloseRequested => {
                 // Shutdown
-                std::process::exit(0);
+                state.running = false;
             }
             _ => (),
         });
 
-        // Sync cursor status
-        let window = backend.window();
-        match state.cursor_status {
-            CursorImageStatus::Named(icon) => {
-                window.set_cursor(icon);
-                window.set_cursor_visible(true);
-            }
-            CursorImageStatus::Surface(_) => {
-                // For now, keep the default if a surface is requested
-                window.set_cursor(smithay::input::pointer::CursorIcon::Default);
-                window.set_cursor_visible(true);
+        // The cursor is drawn by us, not by the host window system.
+        backend.window().set_cursor_visible(false);
+        if let CursorImageStatus::Surface(surface) = &state.cursor_status {
+            if !surface.alive() {
+                state.cursor_status = CursorImageStatus::default_named();
             }
-            CursorImageStatus::Hidden => {
-                window.set_cursor_visible(false);
+        }
+        for tool in state.tablet_tools.values_mut() {
+            if let CursorImageStatus::Surface(surface) = &tool.cursor {
+                if !surface.alive() {
+                    tool.cursor = CursorImageStatus::default_named();
+                }
             }
         }
-        
+
         // Render
         state.animation_manager.tick();
+
+        let current_params = (output.current_mode(), output.current_transform(), output.current_scale().fractional_scale());
+        if current_params != render_params {
+            damage_tracker = winit_damage_tracker(&output);
+            render_params = current_params;
+        }
         
+        let [r, g, b, a] = state.config.appearance.background;
+        let clear_color = Color32F::new(r, g, b, a);
+        let output_location = output.current_location();
+        let pointer_location = state.pointer.current_location();
+        let cursor_time = state.start_time.elapsed();
+        let locked = state.is_locked();
+        let lock_surface = state.lock_surface(&output).cloned();
+        let blanked = state.blanked_outputs.contains(&output);
         let render_res = backend.bind().map(|(renderer, mut framebuffer)| {
+            let mut cursor_elements = render::cursor_elements(
+                renderer,
+                &output,
+                output_location,
+                pointer_location,
+                &state.cursor_status,
+                &mut state.cursor_theme,
+                cursor_time,
+            );
+            cursor_elements.extend(state.tablet_cursor_elements(renderer, &output));
+            if blanked {
+                let elements: [render::OutputRenderElements<GlesRenderer>; 0] = [];
+                return damage_tracker.render_output(renderer, &mut framebuffer, 0, &elements, Color32F::BLACK);
+            }
+            if locked {
+                // Without a lock surface (or with a crashed locker) the output stays plain black.
+                let elements = render::lock_elements(renderer, &output, lock_surface.as_ref(), cursor_elements);
+                return damage_tracker.render_output(renderer, &mut framebuffer, 0, &elements, Color32F::BLACK);
+            }
             render::render_output(
                 &output,
                 &state.space,
+                &state.animation_manager,
+                &mut render_cache,
                 renderer,
                 &mut framebuffer,
                 &mut damage_tracker,
                 0,
+                clear_color,
+                cursor_elements,
             )
         });
         
@@ -195,11 +356,41 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     } else {
                          let _ = backend.submit(None);
                     }
+                    // Only now is nothing but the lock screen on the output.
+                    if locked {
+                        state.confirm_lock();
+                    }
                     
                     // Send frame callbacks to clients
                     let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
-                    for window in state.space.elements() {
-                        window.send_frame(&output, Duration::from_millis(time), Some(Duration::from_millis(16)), |_, _| None);
+                    let refresh = output.current_mode().map(|m| m.refresh).unwrap_or(60_000);
+                    let frame_interval = Duration::from_micros(1_000_000_000 / refresh.max(1) as u64);
+                    // Blanked outputs don't ask clients for frames until they wake up.
+                    if let Some(surface) = lock_surface.as_ref().filter(|_| !blanked) {
+                        smithay::desktop::utils::send_frames_surface_tree(
+                            surface,
+                            &output,
+                            Duration::from_millis(time),
+                            Some(frame_interval),
+                            |_, _| Some(output.clone()),
+                        );
+                    } else if !locked && !blanked {
+                        for window in state.space.elements() {
+                            window.send_frame(&output, Duration::from_millis(time), Some(frame_interval), |_, _| None);
+                        }
+                    }
+                    let tool_cursors = state.tablet_tools.values().map(|tool| &tool.cursor);
+                    for cursor in std::iter::once(&state.cursor_status).chain(tool_cursors) {
+                        let CursorImageStatus::Surface(surface) = cursor else {
+                            continue;
+                        };
+                        smithay::desktop::utils::send_frames_surface_tree(
+                            surface,
+                            &output,
+                            Duration::from_millis(time),
+                            Some(frame_interval),
+                            |_, _| Some(output.clone()),
+                        );
                     }
                 }
             Ok(Err(err)) => {
@@ -210,7 +401,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         
+        let windows: Vec<_> = state.all_windows().into_iter().map(|(window, _, _)| window).collect();
+        thumbnails.refresh(backend.renderer(), &windows);
+        if !state.pending_captures.is_empty() {
+            state.process_captures(backend.renderer(), &mut render_cache, &thumbnails);
+        }
+
+        if !state.pending_screenshots.is_empty() {
+            state.take_screenshots(backend.renderer(), &thumbnails);
+        }
+
         state.space.refresh();
+        state.popups.cleanup();
+        state.workspaces.refresh();
+        state.refresh_foreign_toplevels();
+        state.refresh_idle();
+        state.apply_pending_raise();
+        // Window capture sessions follow the size of their window.
+        protocols::image_copy_capture::refresh_sessions(&mut state);
     }
     
     Ok(())
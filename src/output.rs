@@ -0,0 +1,309 @@
+use std::{collections::HashMap, time::Duration};
+
+use smithay::{
+    backend::{
+        input::{AbsolutePositionEvent, InputBackend},
+        renderer::damage::OutputDamageTracker,
+    },
+    desktop::{Space, Window, utils::surface_primary_scanout_output},
+    output::{Mode, Output, Scale},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Physical, Point, Transform},
+    wayland::{
+        compositor::{SurfaceData, send_surface_state},
+        fractional_scale::with_fractional_scale,
+    },
+};
+
+use crate::protocols::output_management::OutputHeadState;
+use crate::state::NanaimoState;
+
+/// User-facing settings for a single output, keyed by output name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputConfig {
+    pub enabled: bool,
+    pub transform: Transform,
+    pub scale: f64,
+    /// Position in the global logical space. `None` keeps the current one.
+    pub position: Option<Point<i32, Logical>>,
+    /// Refresh rate in mHz. `None` keeps the backend's choice.
+    pub refresh: Option<i32>,
+    /// How long without input before the output is blanked. `None` never blanks it.
+    pub blank_after: Option<Duration>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            transform: Transform::Normal,
+            scale: 1.0,
+            position: None,
+            refresh: None,
+            blank_after: None,
+        }
+    }
+}
+
+pub type OutputConfigs = HashMap<String, OutputConfig>;
+
+/// Parses the transform names used by wlr-randr and sway (`normal`, `90`, `flipped-180`, ...).
+pub fn parse_transform(name: &str) -> Option<Transform> {
+    Some(match name {
+        "normal" | "0" => Transform::Normal,
+        "90" => Transform::_90,
+        "180" => Transform::_180,
+        "270" => Transform::_270,
+        "flipped" => Transform::Flipped,
+        "flipped-90" => Transform::Flipped90,
+        "flipped-180" => Transform::Flipped180,
+        "flipped-270" => Transform::Flipped270,
+        _ => return None,
+    })
+}
+
+pub fn transform_name(transform: Transform) -> &'static str {
+    match transform {
+        Transform::Normal => "normal",
+        Transform::_90 => "90",
+        Transform::_180 => "180",
+        Transform::_270 => "270",
+        Transform::Flipped => "flipped",
+        Transform::Flipped90 => "flipped-90",
+        Transform::Flipped180 => "flipped-180",
+        Transform::Flipped270 => "flipped-270",
+    }
+}
+
+/// Splits a transform into a counter-clockwise rotation applied after an optional flip.
+fn transform_parts(transform: Transform) -> (u32, bool) {
+    match transform {
+        Transform::Normal => (0, false),
+        Transform::_90 => (90, false),
+        Transform::_180 => (180, false),
+        Transform::_270 => (270, false),
+        Transform::Flipped => (0, true),
+        Transform::Flipped90 => (90, true),
+        Transform::Flipped180 => (180, true),
+        Transform::Flipped270 => (270, true),
+    }
+}
+
+fn transform_from_parts(rotation: u32, flipped: bool) -> Transform {
+    match (rotation % 360, flipped) {
+        (0, false) => Transform::Normal,
+        (90, false) => Transform::_90,
+        (180, false) => Transform::_180,
+        (270, false) => Transform::_270,
+        (0, true) => Transform::Flipped,
+        (90, true) => Transform::Flipped90,
+        (180, true) => Transform::Flipped180,
+        _ => Transform::Flipped270,
+    }
+}
+
+/// Returns the transform equivalent to applying `first` and then `then`.
+pub fn compose_transforms(first: Transform, then: Transform) -> Transform {
+    let (a, f) = transform_parts(first);
+    let (b, g) = transform_parts(then);
+    // A flip reverses the direction of any rotation applied before it.
+    let rotation = if g { 360 + b - a } else { a + b };
+    transform_from_parts(rotation, f ^ g)
+}
+
+/// Damage tracker for the winit window.
+///
+/// The winit GL framebuffer is y-flipped relative to the output, so the backend renders with
+/// `Flipped180` on top of whatever transform the output advertises to clients.
+pub fn winit_damage_tracker(output: &Output) -> OutputDamageTracker {
+    let mode = output.current_mode().expect("output without mode?");
+    let transform = compose_transforms(output.current_transform(), Transform::Flipped180);
+    OutputDamageTracker::new(mode.size, output.current_scale().fractional_scale(), transform)
+}
+
+/// Maps a point in the output's untransformed pixel space, as reported by the backend,
+/// to the global logical coordinate space.
+pub fn output_to_global(output: &Output, location: Point<i32, Logical>, pos: Point<f64, Physical>) -> Point<f64, Logical> {
+    let mode = output.current_mode().expect("output without mode?");
+    let local = output
+        .current_transform()
+        .invert()
+        .transform_point_in(pos, &mode.size.to_f64());
+    local.to_logical(output.current_scale().fractional_scale()) + location.to_f64()
+}
+
+/// Where an absolute-position event on `output` lands in the global logical space, or `None` if
+/// the output is disabled and not mapped.
+pub fn event_position<B: InputBackend>(
+    space: &Space<Window>,
+    output: &Output,
+    event: &impl AbsolutePositionEvent<B>,
+) -> Option<Point<f64, Logical>> {
+    let output_geo = space.output_geometry(output)?;
+    let mode_size = output.current_mode()?.size;
+    let pos = event.position_transformed((mode_size.w, mode_size.h).into());
+    Some(output_to_global(output, output_geo.loc, (pos.x, pos.y).into()))
+}
+
+/// Tells a surface which buffer scale and transform would match `output` exactly.
+pub fn send_preferred_scale(surface: &WlSurface, states: &SurfaceData, output: &Output) {
+    let scale = output.current_scale();
+    send_surface_state(surface, states, scale.integer_scale(), output.current_transform());
+    with_fractional_scale(states, |fractional_scale| {
+        fractional_scale.set_preferred_scale(scale.fractional_scale());
+    });
+}
+
+impl NanaimoState {
+    /// Applies the stored configuration for `output` and updates every mapped surface.
+    ///
+    /// Callers are expected to follow up with [`NanaimoState::outputs_changed`] once all
+    /// outputs of a batch are configured.
+    pub fn apply_output_config(&mut self, output: &Output) {
+        let config = self.output_configs.get(&output.name()).cloned().unwrap_or_default();
+        if !config.enabled {
+            tracing::info!("Disabling output {}", output.name());
+            self.space.unmap_output(output);
+            self.refresh_preferred_scales();
+            return;
+        }
+        let location = config
+            .position
+            .unwrap_or_else(|| output.current_location());
+
+        tracing::info!(
+            "Configuring output {}: transform={} scale={} position={:?}",
+            output.name(),
+            transform_name(config.transform),
+            config.scale,
+            location
+        );
+        let mode = output
+            .current_mode()
+            .zip(config.refresh)
+            .map(|(mode, refresh)| Mode { size: mode.size, refresh });
+        output.change_current_state(
+            mode,
+            Some(config.transform),
+            Some(Scale::Fractional(config.scale)),
+            Some(location),
+        );
+        self.space.map_output(output, location);
+        self.refresh_preferred_scales();
+    }
+
+    /// Reports the current state of all outputs to output management clients.
+    pub fn outputs_changed(&mut self) {
+        let heads = self
+            .outputs
+            .iter()
+            .map(|output| OutputHeadState::from_output(output, self.space.outputs().any(|o| o == output)))
+            .collect::<Vec<_>>();
+        self.output_management_state.update::<Self>(&self.display_handle, heads);
+        crate::protocols::image_copy_capture::refresh_sessions(self);
+        self.configure_lock_surfaces();
+    }
+
+    /// Re-sends preferred buffer scale and fractional scale to every mapped window.
+    pub fn refresh_preferred_scales(&self) {
+        for window in self.space.elements() {
+            self.send_window_scale(window);
+        }
+    }
+
+    /// Sends preferred scale to all surfaces of `window`, based on the output it is shown on.
+    pub fn send_window_scale(&self, window: &Window) {
+        let Some(window_output) = self
+            .space
+            .outputs_for_element(window)
+            .into_iter()
+            .next()
+            .or_else(|| self.space.outputs().next().cloned())
+        else {
+            return;
+        };
+        window.with_surfaces(|surface, states| {
+            let output = surface_primary_scanout_output(surface, states).unwrap_or_else(|| window_output.clone());
+            send_preferred_scale(surface, states, &output);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Transform; 8] = [
+        Transform::Normal,
+        Transform::_90,
+        Transform::_180,
+        Transform::_270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    #[test]
+    fn transform_names_round_trip() {
+        for transform in ALL {
+            assert_eq!(parse_transform(transform_name(transform)), Some(transform));
+        }
+        assert_eq!(parse_transform("0"), Some(Transform::Normal));
+        assert_eq!(parse_transform("45"), None);
+    }
+
+    #[test]
+    fn normal_is_identity() {
+        for transform in ALL {
+            assert_eq!(compose_transforms(Transform::Normal, transform), transform);
+            assert_eq!(compose_transforms(transform, Transform::Normal), transform);
+        }
+    }
+
+    #[test]
+    fn rotations_add_up() {
+        assert_eq!(compose_transforms(Transform::_90, Transform::_90), Transform::_180);
+        assert_eq!(compose_transforms(Transform::_90, Transform::_180), Transform::_270);
+        assert_eq!(compose_transforms(Transform::_270, Transform::_180), Transform::_90);
+        assert_eq!(compose_transforms(Transform::_270, Transform::_90), Transform::Normal);
+    }
+
+    #[test]
+    fn flips_reverse_earlier_rotations() {
+        assert_eq!(compose_transforms(Transform::Flipped, Transform::Flipped), Transform::Normal);
+        assert_eq!(compose_transforms(Transform::Flipped, Transform::_90), Transform::Flipped90);
+        assert_eq!(compose_transforms(Transform::_90, Transform::Flipped), Transform::Flipped270);
+        assert_eq!(compose_transforms(Transform::Flipped90, Transform::Flipped90), Transform::Normal);
+        // The winit backend stacks Flipped180 on top of the output transform.
+        assert_eq!(compose_transforms(Transform::Normal, Transform::Flipped180), Transform::Flipped180);
+        assert_eq!(compose_transforms(Transform::_90, Transform::Flipped180), Transform::Flipped90);
+        assert_eq!(compose_transforms(Transform::Flipped180, Transform::Flipped180), Transform::Normal);
+    }
+
+    #[test]
+    fn composing_with_the_inverse_gives_normal() {
+        for transform in ALL {
+            assert_eq!(compose_transforms(transform, transform.invert()), Transform::Normal, "{:?}", transform);
+            assert_eq!(compose_transforms(transform.invert(), transform), Transform::Normal, "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn composition_is_associative() {
+        for a in ALL {
+            for b in ALL {
+                for c in ALL {
+                    assert_eq!(
+                        compose_transforms(compose_transforms(a, b), c),
+                        compose_transforms(a, compose_transforms(b, c)),
+                        "{:?} {:?} {:?}",
+                        a,
+                        b,
+                        c
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Server side of `wlr-foreign-toplevel-management-unstable-v1`.
+//!
+//! Same shape as the output management module: the compositor describes every toplevel with a
+//! [`ToplevelInfo`] keyed by its window id, [`ForeignToplevelManagementState::update`] sends
+//! whatever changed to every bound manager, and requests come back through the handler trait.
+
+use std::collections::{BTreeMap, HashMap};
+
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::foreign_toplevel::v1::server::{
+            zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+            zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+        },
+        wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, backend::ClientId},
+    },
+};
+
+const VERSION: u32 = 3;
+
+/// A toplevel as reported to taskbars.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToplevelInfo {
+    pub title: String,
+    pub app_id: String,
+    pub activated: bool,
+    pub maximized: bool,
+    pub minimized: bool,
+    pub fullscreen: bool,
+    pub outputs: Vec<Output>,
+}
+
+impl ToplevelInfo {
+    fn states(&self) -> Vec<u8> {
+        use zwlr_foreign_toplevel_handle_v1::State;
+
+        [
+            (self.maximized, State::Maximized),
+            (self.minimized, State::Minimized),
+            (self.activated, State::Activated),
+            (self.fullscreen, State::Fullscreen),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .flat_map(|(_, state)| (state as u32).to_ne_bytes())
+        .collect()
+    }
+}
+
+/// Requests from taskbars, by window id. Maximize and fullscreen requests are not forwarded since
+/// the compositor has neither.
+pub trait ForeignToplevelManagementHandler {
+    fn foreign_toplevel_management_state(&mut self) -> &mut ForeignToplevelManagementState;
+
+    fn activate_toplevel(&mut self, id: u64);
+
+    fn close_toplevel(&mut self, id: u64);
+
+    fn set_toplevel_minimized(&mut self, id: u64, minimized: bool);
+}
+
+struct ManagerInstance {
+    manager: ZwlrForeignToplevelManagerV1,
+    handles: HashMap<u64, ZwlrForeignToplevelHandleV1>,
+}
+
+pub struct ForeignToplevelManagementState {
+    /// Ordered by id, so new managers learn about toplevels in the order they were opened.
+    toplevels: BTreeMap<u64, ToplevelInfo>,
+    managers: Vec<ManagerInstance>,
+}
+
+impl ForeignToplevelManagementState {
+    pub fn new<D>(dh: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwlrForeignToplevelManagerV1, ()> + 'static,
+    {
+        dh.create_global::<D, ZwlrForeignToplevelManagerV1, _>(VERSION, ());
+        Self {
+            toplevels: BTreeMap::new(),
+            managers: Vec::new(),
+        }
+    }
+
+    /// Replaces the set of toplevels and sends whatever changed to every bound manager.
+    pub fn update<D>(&mut self, dh: &DisplayHandle, toplevels: impl IntoIterator<Item = (u64, ToplevelInfo)>)
+    where
+        D: Dispatch<ZwlrForeignToplevelHandleV1, u64> + 'static,
+    {
+        let toplevels: BTreeMap<u64, ToplevelInfo> = toplevels.into_iter().collect();
+        if toplevels == self.toplevels {
+            return;
+        }
+
+        for instance in &mut self.managers {
+            instance.handles.retain(|id, handle| {
+                if toplevels.contains_key(id) {
+                    return true;
+                }
+                handle.closed();
+                false
+            });
+
+            for (id, info) in &toplevels {
+                match instance.handles.get(id) {
+                    Some(handle) => send_info(handle, self.toplevels.get(id), info),
+                    None => {
+                        if let Some(handle) = create_handle::<D>(dh, &instance.manager, *id, info) {
+                            instance.handles.insert(*id, handle);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.toplevels = toplevels;
+    }
+}
+
+fn create_handle<D>(
+    dh: &DisplayHandle,
+    manager: &ZwlrForeignToplevelManagerV1,
+    id: u64,
+    info: &ToplevelInfo,
+) -> Option<ZwlrForeignToplevelHandleV1>
+where
+    D: Dispatch<ZwlrForeignToplevelHandleV1, u64> + 'static,
+{
+    let client = manager.client()?;
+    let handle = client
+        .create_resource::<ZwlrForeignToplevelHandleV1, _, D>(dh, manager.version(), id)
+        .ok()?;
+    manager.toplevel(&handle);
+    send_info(&handle, None, info);
+    Some(handle)
+}
+
+/// Sends the properties of `info` that differ from `old` (all of them if `old` is `None`),
+/// followed by `done` if anything was sent.
+fn send_info(handle: &ZwlrForeignToplevelHandleV1, old: Option<&ToplevelInfo>, info: &ToplevelInfo) {
+    if old == Some(info) {
+        return;
+    }
+    let Some(client) = handle.client() else {
+        return;
+    };
+
+    if old.map(|old| &old.title) != Some(&info.title) {
+        handle.title(info.title.clone());
+    }
+    if old.map(|old| &old.app_id) != Some(&info.app_id) {
+        handle.app_id(info.app_id.clone());
+    }
+    let old_outputs = old.map(|old| old.outputs.as_slice()).unwrap_or_default();
+    for output in old_outputs.iter().filter(|output| !info.outputs.contains(output)) {
+        for wl_output in output.client_outputs(&client) {
+            handle.output_leave(&wl_output);
+        }
+    }
+    for output in info.outputs.iter().filter(|output| !old_outputs.contains(output)) {
+        for wl_output in output.client_outputs(&client) {
+            handle.output_enter(&wl_output);
+        }
+    }
+    let states = info.states();
+    if old.map(ToplevelInfo::states) != Some(states.clone()) {
+        handle.state(states);
+    }
+    handle.done();
+}
+
+impl<D> GlobalDispatch<ZwlrForeignToplevelManagerV1, (), D> for ForeignToplevelManagementState
+where
+    D: GlobalDispatch<ZwlrForeignToplevelManagerV1, ()>
+        + Dispatch<ZwlrForeignToplevelManagerV1, ()>
+        + Dispatch<ZwlrForeignToplevelHandleV1, u64>
+        + ForeignToplevelManagementHandler
+        + 'static,
+{
+    fn bind(
+        state: &mut D,
+        dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrForeignToplevelManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let manager = data_init.init(resource, ());
+        let mgmt = state.foreign_toplevel_management_state();
+
+        let mut handles = HashMap::new();
+        for (id, info) in &mgmt.toplevels {
+            if let Some(handle) = create_handle::<D>(dh, &manager, *id, info) {
+                handles.insert(*id, handle);
+            }
+        }
+        mgmt.managers.push(ManagerInstance { manager, handles });
+    }
+}
+
+impl<D> Dispatch<ZwlrForeignToplevelManagerV1, (), D> for ForeignToplevelManagementState
+where
+    D: Dispatch<ZwlrForeignToplevelManagerV1, ()> + ForeignToplevelManagementHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwlrForeignToplevelManagerV1,
+        request: zwlr_foreign_toplevel_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Request::Stop = request {
+            state
+                .foreign_toplevel_management_state()
+                .managers
+                .retain(|instance| &instance.manager != resource);
+            resource.finished();
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, resource: &ZwlrForeignToplevelManagerV1, _data: &()) {
+        state
+            .foreign_toplevel_management_state()
+            .managers
+            .retain(|instance| &instance.manager != resource);
+    }
+}
+
+impl<D> Dispatch<ZwlrForeignToplevelHandleV1, u64, D> for ForeignToplevelManagementState
+where
+    D: Dispatch<ZwlrForeignToplevelHandleV1, u64> + ForeignToplevelManagementHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _resource: &ZwlrForeignToplevelHandleV1,
+        request: zwlr_foreign_toplevel_handle_v1::Request,
+        id: &u64,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        use zwlr_foreign_toplevel_handle_v1::Request;
+
+        match request {
+            Request::Activate { .. } => state.activate_toplevel(*id),
+            Request::Close => state.close_toplevel(*id),
+            Request::SetMinimized => state.set_toplevel_minimized(*id, true),
+            Request::UnsetMinimized => state.set_toplevel_minimized(*id, false),
+            // Minimize animations don't aim at taskbar buttons, and there is no maximize or
+            // fullscreen to switch to.
+            Request::SetRectangle { .. }
+            | Request::SetMaximized
+            | Request::UnsetMaximized
+            | Request::SetFullscreen { .. }
+            | Request::UnsetFullscreen => {}
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, resource: &ZwlrForeignToplevelHandleV1, _id: &u64) {
+        for instance in &mut state.foreign_toplevel_management_state().managers {
+            instance.handles.retain(|_, handle| handle != resource);
+        }
+    }
+}
+
+macro_rules! delegate_foreign_toplevel_management {
+    ($ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::foreign_toplevel::v1::server::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1: ()
+        ] => $crate::protocols::foreign_toplevel::ForeignToplevelManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::foreign_toplevel::v1::server::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1: ()
+        ] => $crate::protocols::foreign_toplevel::ForeignToplevelManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::foreign_toplevel::v1::server::zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1: u64
+        ] => $crate::protocols::foreign_toplevel::ForeignToplevelManagementState);
+    };
+}
+pub(crate) use delegate_foreign_toplevel_management;
@@ -0,0 +1,646 @@
+//! Server side of `ext-image-capture-source-v1` and `ext-image-copy-capture-v1`.
+//!
+//! Sources are created per output or per foreign toplevel handle, and through
+//! `nanaimo-thumbnail-v1` for the thumbnail of a foreign toplevel. Each session tracks the buffer
+//! constraints it last sent, so [`refresh_sessions`] can resend them when its source changes size,
+//! or stop the session once the source is gone. Frames are validated here and handed to the
+//! compositor as [`CaptureFrame`]s. Cursor sessions are accepted but stopped straight away, since
+//! the cursor can only be captured painted into the frame.
+
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use smithay::{
+    output::{Output, WeakOutput},
+    reexports::{
+        wayland_protocols::ext::{
+            foreign_toplevel_list::v1::server::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+            image_capture_source::v1::server::{
+                ext_foreign_toplevel_image_capture_source_manager_v1::{
+                    self, ExtForeignToplevelImageCaptureSourceManagerV1,
+                },
+                ext_image_capture_source_v1::{self, ExtImageCaptureSourceV1},
+                ext_output_image_capture_source_manager_v1::{self, ExtOutputImageCaptureSourceManagerV1},
+            },
+            image_copy_capture::v1::server::{
+                ext_image_copy_capture_cursor_session_v1::{self, ExtImageCopyCaptureCursorSessionV1},
+                ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1, FailureReason},
+                ext_image_copy_capture_manager_v1::{self, ExtImageCopyCaptureManagerV1},
+                ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+            },
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum, backend::ClientId,
+            protocol::wl_buffer::WlBuffer,
+        },
+    },
+    utils::{Buffer, Rectangle, Size, Transform},
+};
+
+use super::{CAPTURE_SHM_FORMATS, is_capture_buffer, next_capture_session_id};
+use super::output_management::to_wl_transform;
+use super::thumbnail::server::nanaimo_thumbnail_manager_v1::{self, NanaimoThumbnailManagerV1};
+
+const VERSION: u32 = 1;
+
+/// What an `ext_image_capture_source_v1` captures.
+#[derive(Debug, Clone)]
+pub enum ImageCaptureSource {
+    Output(WeakOutput),
+    /// A window, by id.
+    Toplevel(u64),
+    /// The cached thumbnail of a window, by id.
+    Thumbnail(u64),
+    /// The source was created for an output or toplevel that was already gone.
+    Gone,
+}
+
+pub trait ImageCopyCaptureHandler {
+    fn image_copy_capture_state(&mut self) -> &mut ImageCopyCaptureState;
+
+    /// The window id behind a foreign toplevel handle, if it still exists.
+    fn toplevel_capture_source(&self, handle: &ExtForeignToplevelHandleV1) -> Option<u64>;
+
+    /// The size frames of `source` have right now, or `None` once it can no longer be captured.
+    fn capture_source_size(&self, source: &ImageCaptureSource) -> Option<Size<i32, Buffer>>;
+
+    /// A client wants `frame` captured into its buffer. Dropping it without finishing it
+    /// reports failure to the client.
+    fn capture_frame(&mut self, frame: CaptureFrame);
+
+    /// A session was destroyed, so nothing more will be captured for it.
+    fn capture_session_ended(&mut self, session: u64);
+}
+
+pub struct ImageCopyCaptureState {
+    sessions: Vec<ExtImageCopyCaptureSessionV1>,
+}
+
+pub struct SessionData {
+    id: u64,
+    source: ImageCaptureSource,
+    paint_cursors: bool,
+    inner: Mutex<SessionInner>,
+}
+
+struct SessionInner {
+    /// The buffer size last sent to the client, `None` once the session is stopped.
+    size: Option<Size<i32, Buffer>>,
+    frame: Option<ExtImageCopyCaptureFrameV1>,
+}
+
+pub struct FrameData {
+    session: ExtImageCopyCaptureSessionV1,
+    inner: Mutex<FrameInner>,
+}
+
+#[derive(Default)]
+struct FrameInner {
+    buffer: Option<WlBuffer>,
+    captured: bool,
+}
+
+pub struct CursorSessionData {
+    source: ImageCaptureSource,
+    has_session: AtomicBool,
+}
+
+/// A frame waiting to be captured.
+pub struct CaptureFrame {
+    pub session: u64,
+    pub source: ImageCaptureSource,
+    pub paint_cursors: bool,
+    pub buffer: WlBuffer,
+    frame: ExtImageCopyCaptureFrameV1,
+    finished: bool,
+}
+
+impl CaptureFrame {
+    /// Whether the client still wants this frame.
+    pub fn is_alive(&self) -> bool {
+        self.frame.is_alive()
+    }
+
+    /// Tells the client its buffer is filled and what changed since the session's last frame.
+    pub fn success(mut self, transform: Transform, damage: impl IntoIterator<Item = Rectangle<i32, Buffer>>, time: Duration) {
+        self.frame.transform(to_wl_transform(transform));
+        for rect in damage {
+            self.frame.damage(rect.loc.x, rect.loc.y, rect.size.w, rect.size.h);
+        }
+        let secs = time.as_secs();
+        self.frame
+            .presentation_time((secs >> 32) as u32, secs as u32, time.subsec_nanos());
+        self.frame.ready();
+        self.finished = true;
+    }
+
+    pub fn fail(mut self, reason: FailureReason) {
+        self.frame.failed(reason);
+        self.finished = true;
+    }
+}
+
+impl Drop for CaptureFrame {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.frame.failed(FailureReason::Unknown);
+        }
+    }
+}
+
+impl ImageCopyCaptureState {
+    pub fn new<D>(dh: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ExtImageCopyCaptureManagerV1, ()>
+            + GlobalDispatch<ExtOutputImageCaptureSourceManagerV1, ()>
+            + GlobalDispatch<ExtForeignToplevelImageCaptureSourceManagerV1, ()>
+            + GlobalDispatch<NanaimoThumbnailManagerV1, ()>
+            + 'static,
+    {
+        dh.create_global::<D, ExtImageCopyCaptureManagerV1, _>(VERSION, ());
+        dh.create_global::<D, ExtOutputImageCaptureSourceManagerV1, _>(VERSION, ());
+        dh.create_global::<D, ExtForeignToplevelImageCaptureSourceManagerV1, _>(VERSION, ());
+        dh.create_global::<D, NanaimoThumbnailManagerV1, _>(VERSION, ());
+        Self { sessions: Vec::new() }
+    }
+}
+
+fn send_constraints(session: &ExtImageCopyCaptureSessionV1, size: Size<i32, Buffer>) {
+    session.buffer_size(size.w as u32, size.h as u32);
+    for format in CAPTURE_SHM_FORMATS {
+        session.shm_format(format);
+    }
+    session.done();
+}
+
+/// Resends buffer constraints to sessions whose source changed size and stops the ones whose
+/// source can no longer be captured.
+pub fn refresh_sessions<D: ImageCopyCaptureHandler>(state: &mut D) {
+    let mut sessions = std::mem::take(&mut state.image_copy_capture_state().sessions);
+    sessions.retain(|session| {
+        let Some(data) = session.data::<SessionData>() else {
+            return false;
+        };
+        let size = state.capture_source_size(&data.source);
+        let mut inner = data.inner.lock().unwrap();
+        match size {
+            Some(size) if inner.size != Some(size) => {
+                inner.size = Some(size);
+                send_constraints(session, size);
+                true
+            }
+            Some(_) => true,
+            None => {
+                inner.size = None;
+                session.stopped();
+                false
+            }
+        }
+    });
+    state.image_copy_capture_state().sessions.extend(sessions);
+}
+
+impl<D> GlobalDispatch<ExtOutputImageCaptureSourceManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: GlobalDispatch<ExtOutputImageCaptureSourceManagerV1, ()>
+        + Dispatch<ExtOutputImageCaptureSourceManagerV1, ()>
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtOutputImageCaptureSourceManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<ExtOutputImageCaptureSourceManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: Dispatch<ExtOutputImageCaptureSourceManagerV1, ()> + Dispatch<ExtImageCaptureSourceV1, ImageCaptureSource> + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _resource: &ExtOutputImageCaptureSourceManagerV1,
+        request: ext_output_image_capture_source_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        if let ext_output_image_capture_source_manager_v1::Request::CreateSource { source, output } = request {
+            let data = match Output::from_resource(&output) {
+                Some(output) => ImageCaptureSource::Output(output.downgrade()),
+                None => ImageCaptureSource::Gone,
+            };
+            data_init.init(source, data);
+        }
+    }
+}
+
+impl<D> GlobalDispatch<ExtForeignToplevelImageCaptureSourceManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: GlobalDispatch<ExtForeignToplevelImageCaptureSourceManagerV1, ()>
+        + Dispatch<ExtForeignToplevelImageCaptureSourceManagerV1, ()>
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtForeignToplevelImageCaptureSourceManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<ExtForeignToplevelImageCaptureSourceManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: Dispatch<ExtForeignToplevelImageCaptureSourceManagerV1, ()>
+        + Dispatch<ExtImageCaptureSourceV1, ImageCaptureSource>
+        + ImageCopyCaptureHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _resource: &ExtForeignToplevelImageCaptureSourceManagerV1,
+        request: ext_foreign_toplevel_image_capture_source_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        if let ext_foreign_toplevel_image_capture_source_manager_v1::Request::CreateSource {
+            source,
+            toplevel_handle,
+        } = request
+        {
+            let data = match state.toplevel_capture_source(&toplevel_handle) {
+                Some(id) => ImageCaptureSource::Toplevel(id),
+                None => ImageCaptureSource::Gone,
+            };
+            data_init.init(source, data);
+        }
+    }
+}
+
+impl<D> GlobalDispatch<NanaimoThumbnailManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: GlobalDispatch<NanaimoThumbnailManagerV1, ()> + Dispatch<NanaimoThumbnailManagerV1, ()> + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<NanaimoThumbnailManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<NanaimoThumbnailManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: Dispatch<NanaimoThumbnailManagerV1, ()>
+        + Dispatch<ExtImageCaptureSourceV1, ImageCaptureSource>
+        + ImageCopyCaptureHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _resource: &NanaimoThumbnailManagerV1,
+        request: nanaimo_thumbnail_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        if let nanaimo_thumbnail_manager_v1::Request::CreateSource { source, toplevel_handle } = request {
+            let data = match state.toplevel_capture_source(&toplevel_handle) {
+                Some(id) => ImageCaptureSource::Thumbnail(id),
+                None => ImageCaptureSource::Gone,
+            };
+            data_init.init(source, data);
+        }
+    }
+}
+
+impl<D> Dispatch<ExtImageCaptureSourceV1, ImageCaptureSource, D> for ImageCopyCaptureState
+where
+    D: Dispatch<ExtImageCaptureSourceV1, ImageCaptureSource> + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _resource: &ExtImageCaptureSourceV1,
+        _request: ext_image_capture_source_v1::Request,
+        _data: &ImageCaptureSource,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+    }
+}
+
+impl<D> GlobalDispatch<ExtImageCopyCaptureManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: GlobalDispatch<ExtImageCopyCaptureManagerV1, ()> + Dispatch<ExtImageCopyCaptureManagerV1, ()> + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtImageCopyCaptureManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<ExtImageCopyCaptureManagerV1, (), D> for ImageCopyCaptureState
+where
+    D: Dispatch<ExtImageCopyCaptureManagerV1, ()>
+        + Dispatch<ExtImageCopyCaptureSessionV1, SessionData>
+        + Dispatch<ExtImageCopyCaptureCursorSessionV1, CursorSessionData>
+        + ImageCopyCaptureHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ExtImageCopyCaptureManagerV1,
+        request: ext_image_copy_capture_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            ext_image_copy_capture_manager_v1::Request::CreateSession { session, source, options } => {
+                let paint_cursors = match options {
+                    WEnum::Value(options) => options.contains(ext_image_copy_capture_manager_v1::Options::PaintCursors),
+                    WEnum::Unknown(bits) => {
+                        resource.post_error(
+                            ext_image_copy_capture_manager_v1::Error::InvalidOption,
+                            format!("unknown options {:#x}", bits),
+                        );
+                        false
+                    }
+                };
+                let source = source
+                    .data::<ImageCaptureSource>()
+                    .cloned()
+                    .unwrap_or(ImageCaptureSource::Gone);
+                let size = state.capture_source_size(&source);
+                let session = data_init.init(
+                    session,
+                    SessionData {
+                        id: next_capture_session_id(),
+                        source,
+                        paint_cursors,
+                        inner: Mutex::new(SessionInner { size, frame: None }),
+                    },
+                );
+                match size {
+                    Some(size) => {
+                        send_constraints(&session, size);
+                        state.image_copy_capture_state().sessions.push(session);
+                    }
+                    None => session.stopped(),
+                }
+            }
+            ext_image_copy_capture_manager_v1::Request::CreatePointerCursorSession { session, source, .. } => {
+                let source = source
+                    .data::<ImageCaptureSource>()
+                    .cloned()
+                    .unwrap_or(ImageCaptureSource::Gone);
+                data_init.init(
+                    session,
+                    CursorSessionData {
+                        source,
+                        has_session: AtomicBool::new(false),
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<D> Dispatch<ExtImageCopyCaptureCursorSessionV1, CursorSessionData, D> for ImageCopyCaptureState
+where
+    D: Dispatch<ExtImageCopyCaptureCursorSessionV1, CursorSessionData>
+        + Dispatch<ExtImageCopyCaptureSessionV1, SessionData>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        resource: &ExtImageCopyCaptureCursorSessionV1,
+        request: ext_image_copy_capture_cursor_session_v1::Request,
+        data: &CursorSessionData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        if let ext_image_copy_capture_cursor_session_v1::Request::GetCaptureSession { session } = request {
+            let session = data_init.init(
+                session,
+                SessionData {
+                    id: next_capture_session_id(),
+                    source: data.source.clone(),
+                    paint_cursors: false,
+                    inner: Mutex::new(SessionInner { size: None, frame: None }),
+                },
+            );
+            if data.has_session.swap(true, Ordering::Relaxed) {
+                resource.post_error(
+                    ext_image_copy_capture_cursor_session_v1::Error::DuplicateSession,
+                    "cursor session already has a capture session",
+                );
+                return;
+            }
+            session.stopped();
+        }
+    }
+}
+
+impl<D> Dispatch<ExtImageCopyCaptureSessionV1, SessionData, D> for ImageCopyCaptureState
+where
+    D: Dispatch<ExtImageCopyCaptureSessionV1, SessionData>
+        + Dispatch<ExtImageCopyCaptureFrameV1, FrameData>
+        + ImageCopyCaptureHandler
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        resource: &ExtImageCopyCaptureSessionV1,
+        request: ext_image_copy_capture_session_v1::Request,
+        data: &SessionData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        if let ext_image_copy_capture_session_v1::Request::CreateFrame { frame } = request {
+            let frame = data_init.init(
+                frame,
+                FrameData {
+                    session: resource.clone(),
+                    inner: Mutex::new(FrameInner::default()),
+                },
+            );
+            let mut inner = data.inner.lock().unwrap();
+            if inner.frame.is_some() {
+                resource.post_error(
+                    ext_image_copy_capture_session_v1::Error::DuplicateFrame,
+                    "session already has a frame",
+                );
+                return;
+            }
+            inner.frame = Some(frame);
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, resource: &ExtImageCopyCaptureSessionV1, data: &SessionData) {
+        state
+            .image_copy_capture_state()
+            .sessions
+            .retain(|session| session != resource);
+        state.capture_session_ended(data.id);
+    }
+}
+
+impl<D> Dispatch<ExtImageCopyCaptureFrameV1, FrameData, D> for ImageCopyCaptureState
+where
+    D: Dispatch<ExtImageCopyCaptureFrameV1, FrameData> + ImageCopyCaptureHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ExtImageCopyCaptureFrameV1,
+        request: ext_image_copy_capture_frame_v1::Request,
+        data: &FrameData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        use ext_image_copy_capture_frame_v1::{Error, Request};
+
+        let mut inner = data.inner.lock().unwrap();
+        let captured = inner.captured;
+        let already_captured = |resource: &ExtImageCopyCaptureFrameV1| {
+            resource.post_error(Error::AlreadyCaptured, "frame was already captured");
+        };
+
+        match request {
+            Request::AttachBuffer { buffer } => {
+                if captured {
+                    return already_captured(resource);
+                }
+                inner.buffer = Some(buffer);
+            }
+            // Every frame is written in full, so the client's own damage needs no tracking.
+            Request::DamageBuffer { x, y, width, height } => {
+                if captured {
+                    return already_captured(resource);
+                }
+                if x < 0 || y < 0 || width <= 0 || height <= 0 {
+                    resource.post_error(Error::InvalidBufferDamage, "invalid buffer damage");
+                }
+            }
+            Request::Capture => {
+                if captured {
+                    return already_captured(resource);
+                }
+                let Some(buffer) = inner.buffer.clone() else {
+                    resource.post_error(Error::NoBuffer, "no buffer attached");
+                    return;
+                };
+                inner.captured = true;
+                drop(inner);
+
+                let Some(session) = data.session.data::<SessionData>() else {
+                    resource.failed(FailureReason::Unknown);
+                    return;
+                };
+                let Some(size) = session.inner.lock().unwrap().size else {
+                    resource.failed(FailureReason::Stopped);
+                    return;
+                };
+                if !is_capture_buffer(&buffer, size) {
+                    resource.failed(FailureReason::BufferConstraints);
+                    return;
+                }
+
+                state.capture_frame(CaptureFrame {
+                    session: session.id,
+                    source: session.source.clone(),
+                    paint_cursors: session.paint_cursors,
+                    buffer,
+                    frame: resource.clone(),
+                    finished: false,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(_state: &mut D, _client: ClientId, resource: &ExtImageCopyCaptureFrameV1, data: &FrameData) {
+        if let Some(session) = data.session.data::<SessionData>() {
+            let mut inner = session.inner.lock().unwrap();
+            if inner.frame.as_ref() == Some(resource) {
+                inner.frame = None;
+            }
+        }
+    }
+}
+
+macro_rules! delegate_image_copy_capture {
+    ($ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_capture_source::v1::server::ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_capture_source::v1::server::ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_capture_source::v1::server::ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_capture_source::v1::server::ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            $crate::protocols::thumbnail::server::nanaimo_thumbnail_manager_v1::NanaimoThumbnailManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            $crate::protocols::thumbnail::server::nanaimo_thumbnail_manager_v1::NanaimoThumbnailManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_capture_source::v1::server::ext_image_capture_source_v1::ExtImageCaptureSourceV1: $crate::protocols::image_copy_capture::ImageCaptureSource
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_copy_capture::v1::server::ext_image_copy_capture_manager_v1::ExtImageCopyCaptureManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_copy_capture::v1::server::ext_image_copy_capture_manager_v1::ExtImageCopyCaptureManagerV1: ()
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_copy_capture::v1::server::ext_image_copy_capture_session_v1::ExtImageCopyCaptureSessionV1: $crate::protocols::image_copy_capture::SessionData
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_copy_capture::v1::server::ext_image_copy_capture_cursor_session_v1::ExtImageCopyCaptureCursorSessionV1: $crate::protocols::image_copy_capture::CursorSessionData
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols::ext::image_copy_capture::v1::server::ext_image_copy_capture_frame_v1::ExtImageCopyCaptureFrameV1: $crate::protocols::image_copy_capture::FrameData
+        ] => $crate::protocols::image_copy_capture::ImageCopyCaptureState);
+    };
+}
+pub(crate) use delegate_image_copy_capture;
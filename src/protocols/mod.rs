@@ -0,0 +1,36 @@
+pub mod foreign_toplevel;
+pub mod image_copy_capture;
+pub mod output_management;
+pub mod screencopy;
+pub mod thumbnail;
+pub mod virtual_keyboard;
+pub mod virtual_pointer;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use smithay::{
+    reexports::wayland_server::protocol::{wl_buffer::WlBuffer, wl_shm},
+    utils::{Buffer, Size},
+    wayland::shm::with_buffer_contents,
+};
+
+/// Formats screen capture clients may hand us buffers in. Both are written the same way.
+pub const CAPTURE_SHM_FORMATS: [wl_shm::Format; 2] = [wl_shm::Format::Argb8888, wl_shm::Format::Xrgb8888];
+
+/// Hands out ids for capture sessions of either screencopy protocol, so per-session state such as
+/// damage tracking can be kept in one map.
+pub fn next_capture_session_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Whether `buffer` is a tightly packed shm buffer of `size` in one of [`CAPTURE_SHM_FORMATS`].
+pub fn is_capture_buffer(buffer: &WlBuffer, size: Size<i32, Buffer>) -> bool {
+    with_buffer_contents(buffer, |_, _, data| {
+        data.width == size.w
+            && data.height == size.h
+            && data.stride == size.w * 4
+            && CAPTURE_SHM_FORMATS.contains(&data.format)
+    })
+    .unwrap_or(false)
+}
@@ -0,0 +1,640 @@
+//! Server side of `wlr-output-management-unstable-v1`.
+//!
+//! Smithay has no helper for this protocol, so the globals are implemented here in the same
+//! shape as smithay's own: an `OutputManagementState` owned by the compositor, a handler trait
+//! for applying configurations and a `delegate_output_management!` macro.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use smithay::{
+    output::{Mode, Output},
+    reexports::{
+        wayland_protocols_wlr::output_management::v1::server::{
+            zwlr_output_configuration_head_v1::{self, ZwlrOutputConfigurationHeadV1},
+            zwlr_output_configuration_v1::{self, ZwlrOutputConfigurationV1},
+            zwlr_output_head_v1::{self, ZwlrOutputHeadV1},
+            zwlr_output_manager_v1::{self, ZwlrOutputManagerV1},
+            zwlr_output_mode_v1::{self, ZwlrOutputModeV1},
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+            backend::ClientId, protocol::wl_output::Transform as WlTransform,
+        },
+    },
+    utils::{Logical, Physical, Point, Size, Transform},
+};
+
+const VERSION: u32 = 4;
+
+/// Snapshot of an output as reported to output management clients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputHeadState {
+    pub name: String,
+    pub description: String,
+    pub make: String,
+    pub model: String,
+    pub serial_number: String,
+    pub physical_size: (i32, i32),
+    pub modes: Vec<Mode>,
+    pub current_mode: Option<Mode>,
+    pub preferred_mode: Option<Mode>,
+    pub enabled: bool,
+    pub position: Point<i32, Logical>,
+    pub transform: Transform,
+    pub scale: f64,
+}
+
+impl OutputHeadState {
+    pub fn from_output(output: &Output, enabled: bool) -> Self {
+        let props = output.physical_properties();
+        Self {
+            name: output.name(),
+            description: output.description(),
+            make: props.make,
+            model: props.model,
+            serial_number: props.serial_number,
+            physical_size: (props.size.w, props.size.h),
+            modes: output.modes(),
+            current_mode: output.current_mode(),
+            preferred_mode: output.preferred_mode(),
+            enabled,
+            position: output.current_location(),
+            transform: output.current_transform(),
+            scale: output.current_scale().fractional_scale(),
+        }
+    }
+}
+
+/// A mode requested by a client, either one of the advertised modes or a custom one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestedMode {
+    Existing(Mode),
+    Custom { size: Size<i32, Physical>, refresh: i32 },
+}
+
+/// The requested state of a single head. Properties left as `None` keep their current value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeadConfiguration {
+    pub enabled: bool,
+    pub mode: Option<RequestedMode>,
+    pub position: Option<Point<i32, Logical>>,
+    pub transform: Option<Transform>,
+    pub scale: Option<f64>,
+}
+
+pub trait OutputManagementHandler {
+    fn output_management_state(&mut self) -> &mut OutputManagementState;
+
+    /// Checks a configuration covering every head and, unless `test_only` is set, applies it.
+    ///
+    /// Either all heads are changed or none are; returning `false` reports `failed` to the client.
+    fn apply_output_configuration(&mut self, heads: HashMap<String, HeadConfiguration>, test_only: bool) -> bool;
+}
+
+struct ClientHead {
+    head: ZwlrOutputHeadV1,
+    modes: Vec<(Mode, ZwlrOutputModeV1)>,
+}
+
+struct ManagerInstance {
+    manager: ZwlrOutputManagerV1,
+    heads: HashMap<String, ClientHead>,
+}
+
+pub struct OutputManagementState {
+    serial: u32,
+    heads: HashMap<String, OutputHeadState>,
+    managers: Vec<ManagerInstance>,
+}
+
+pub struct ModeData {
+    output: String,
+    mode: Mode,
+}
+
+enum ConfiguredHead {
+    Enabled(ZwlrOutputConfigurationHeadV1),
+    Disabled,
+}
+
+pub struct ConfigurationData {
+    serial: u32,
+    inner: Mutex<ConfigurationInner>,
+}
+
+struct ConfigurationInner {
+    used: bool,
+    heads: HashMap<String, ConfiguredHead>,
+}
+
+pub struct ConfigurationHeadData {
+    name: String,
+    config: Mutex<HeadConfiguration>,
+}
+
+impl OutputManagementState {
+    pub fn new<D>(dh: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwlrOutputManagerV1, ()> + 'static,
+    {
+        dh.create_global::<D, ZwlrOutputManagerV1, _>(VERSION, ());
+        Self {
+            serial: 0,
+            heads: HashMap::new(),
+            managers: Vec::new(),
+        }
+    }
+
+    /// Replaces the set of heads and sends whatever changed to every bound manager.
+    pub fn update<D>(&mut self, dh: &DisplayHandle, heads: impl IntoIterator<Item = OutputHeadState>)
+    where
+        D: Dispatch<ZwlrOutputHeadV1, String> + Dispatch<ZwlrOutputModeV1, ModeData> + 'static,
+    {
+        let heads: HashMap<String, OutputHeadState> = heads.into_iter().map(|head| (head.name.clone(), head)).collect();
+        if heads == self.heads {
+            return;
+        }
+        self.serial = self.serial.wrapping_add(1);
+
+        for instance in &mut self.managers {
+            instance.heads.retain(|name, client_head| {
+                if heads.contains_key(name) {
+                    return true;
+                }
+                for (_, mode) in &client_head.modes {
+                    mode.finished();
+                }
+                client_head.head.finished();
+                false
+            });
+
+            for (name, state) in &heads {
+                match instance.heads.get_mut(name) {
+                    Some(client_head) => send_head_state::<D>(dh, client_head, self.heads.get(name), state),
+                    None => {
+                        if let Some(client_head) = create_head::<D>(dh, &instance.manager, state) {
+                            instance.heads.insert(name.clone(), client_head);
+                        }
+                    }
+                }
+            }
+            instance.manager.done(self.serial);
+        }
+
+        self.heads = heads;
+    }
+}
+
+fn create_head<D>(dh: &DisplayHandle, manager: &ZwlrOutputManagerV1, state: &OutputHeadState) -> Option<ClientHead>
+where
+    D: Dispatch<ZwlrOutputHeadV1, String> + Dispatch<ZwlrOutputModeV1, ModeData> + 'static,
+{
+    let client = manager.client()?;
+    let head = client
+        .create_resource::<ZwlrOutputHeadV1, _, D>(dh, manager.version(), state.name.clone())
+        .ok()?;
+    manager.head(&head);
+
+    head.name(state.name.clone());
+    head.description(state.description.clone());
+    let (width, height) = state.physical_size;
+    if width > 0 && height > 0 {
+        head.physical_size(width, height);
+    }
+    if head.version() >= 2 {
+        head.make(state.make.clone());
+        head.model(state.model.clone());
+        head.serial_number(state.serial_number.clone());
+    }
+
+    let mut client_head = ClientHead { head, modes: Vec::new() };
+    send_head_state::<D>(dh, &mut client_head, None, state);
+    Some(client_head)
+}
+
+/// Sends the properties of `state` that differ from `old` (all of them if `old` is `None`).
+fn send_head_state<D>(dh: &DisplayHandle, client_head: &mut ClientHead, old: Option<&OutputHeadState>, state: &OutputHeadState)
+where
+    D: Dispatch<ZwlrOutputModeV1, ModeData> + 'static,
+{
+    let head = &client_head.head;
+    let Some(client) = head.client() else {
+        return;
+    };
+
+    client_head.modes.retain(|(mode, object)| {
+        if state.modes.contains(mode) {
+            return true;
+        }
+        object.finished();
+        false
+    });
+    for mode in &state.modes {
+        if client_head.modes.iter().any(|(m, _)| m == mode) {
+            continue;
+        }
+        let data = ModeData {
+            output: state.name.clone(),
+            mode: *mode,
+        };
+        let Ok(object) = client.create_resource::<ZwlrOutputModeV1, _, D>(dh, head.version(), data) else {
+            continue;
+        };
+        head.mode(&object);
+        object.size(mode.size.w, mode.size.h);
+        object.refresh(mode.refresh);
+        if state.preferred_mode == Some(*mode) {
+            object.preferred();
+        }
+        client_head.modes.push((*mode, object));
+    }
+
+    if old.map(|old| old.enabled) != Some(state.enabled) {
+        head.enabled(state.enabled as i32);
+    }
+    if !state.enabled {
+        return;
+    }
+
+    // Everything below has to be re-sent when a head gets enabled.
+    let old = old.filter(|old| old.enabled);
+    if old.map(|old| old.current_mode) != Some(state.current_mode) {
+        let current = state
+            .current_mode
+            .and_then(|current| client_head.modes.iter().find(|(mode, _)| *mode == current));
+        if let Some((_, object)) = current {
+            head.current_mode(object);
+        }
+    }
+    if old.map(|old| old.position) != Some(state.position) {
+        head.position(state.position.x, state.position.y);
+    }
+    if old.map(|old| old.transform) != Some(state.transform) {
+        head.transform(to_wl_transform(state.transform));
+    }
+    if old.map(|old| old.scale) != Some(state.scale) {
+        head.scale(state.scale);
+    }
+}
+
+pub(crate) fn to_wl_transform(transform: Transform) -> WlTransform {
+    match transform {
+        Transform::Normal => WlTransform::Normal,
+        Transform::_90 => WlTransform::_90,
+        Transform::_180 => WlTransform::_180,
+        Transform::_270 => WlTransform::_270,
+        Transform::Flipped => WlTransform::Flipped,
+        Transform::Flipped90 => WlTransform::Flipped90,
+        Transform::Flipped180 => WlTransform::Flipped180,
+        Transform::Flipped270 => WlTransform::Flipped270,
+    }
+}
+
+fn from_wl_transform(transform: WlTransform) -> Option<Transform> {
+    Some(match transform {
+        WlTransform::Normal => Transform::Normal,
+        WlTransform::_90 => Transform::_90,
+        WlTransform::_180 => Transform::_180,
+        WlTransform::_270 => Transform::_270,
+        WlTransform::Flipped => Transform::Flipped,
+        WlTransform::Flipped90 => Transform::Flipped90,
+        WlTransform::Flipped180 => Transform::Flipped180,
+        WlTransform::Flipped270 => Transform::Flipped270,
+        _ => return None,
+    })
+}
+
+impl<D> GlobalDispatch<ZwlrOutputManagerV1, (), D> for OutputManagementState
+where
+    D: GlobalDispatch<ZwlrOutputManagerV1, ()>
+        + Dispatch<ZwlrOutputManagerV1, ()>
+        + Dispatch<ZwlrOutputHeadV1, String>
+        + Dispatch<ZwlrOutputModeV1, ModeData>
+        + OutputManagementHandler
+        + 'static,
+{
+    fn bind(
+        state: &mut D,
+        dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrOutputManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let manager = data_init.init(resource, ());
+        let mgmt = state.output_management_state();
+
+        let mut heads = HashMap::new();
+        for (name, head_state) in &mgmt.heads {
+            if let Some(client_head) = create_head::<D>(dh, &manager, head_state) {
+                heads.insert(name.clone(), client_head);
+            }
+        }
+        manager.done(mgmt.serial);
+        mgmt.managers.push(ManagerInstance { manager, heads });
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputManagerV1, (), D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputManagerV1, ()>
+        + Dispatch<ZwlrOutputConfigurationV1, ConfigurationData>
+        + OutputManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwlrOutputManagerV1,
+        request: zwlr_output_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_output_manager_v1::Request::CreateConfiguration { id, serial } => {
+                data_init.init(
+                    id,
+                    ConfigurationData {
+                        serial,
+                        inner: Mutex::new(ConfigurationInner {
+                            used: false,
+                            heads: HashMap::new(),
+                        }),
+                    },
+                );
+            }
+            zwlr_output_manager_v1::Request::Stop => {
+                state
+                    .output_management_state()
+                    .managers
+                    .retain(|instance| &instance.manager != resource);
+                resource.finished();
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, resource: &ZwlrOutputManagerV1, _data: &()) {
+        state
+            .output_management_state()
+            .managers
+            .retain(|instance| &instance.manager != resource);
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputHeadV1, String, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputHeadV1, String> + OutputManagementHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwlrOutputHeadV1,
+        request: zwlr_output_head_v1::Request,
+        _data: &String,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        if let zwlr_output_head_v1::Request::Release = request {
+            for instance in &mut state.output_management_state().managers {
+                instance.heads.retain(|_, client_head| &client_head.head != resource);
+            }
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputModeV1, ModeData, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputModeV1, ModeData> + OutputManagementHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwlrOutputModeV1,
+        request: zwlr_output_mode_v1::Request,
+        _data: &ModeData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        if let zwlr_output_mode_v1::Request::Release = request {
+            for instance in &mut state.output_management_state().managers {
+                for client_head in instance.heads.values_mut() {
+                    client_head.modes.retain(|(_, mode)| mode != resource);
+                }
+            }
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputConfigurationV1, ConfigurationData, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputConfigurationV1, ConfigurationData>
+        + Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData>
+        + OutputManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwlrOutputConfigurationV1,
+        request: zwlr_output_configuration_v1::Request,
+        data: &ConfigurationData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_output_configuration_v1::Request::EnableHead { id, head } => {
+                let name = head.data::<String>().cloned().unwrap_or_default();
+                let mut inner = data.inner.lock().unwrap();
+                if inner.heads.contains_key(&name) {
+                    resource.post_error(
+                        zwlr_output_configuration_v1::Error::AlreadyConfiguredHead,
+                        format!("head {} configured twice", name),
+                    );
+                    return;
+                }
+                let config_head = data_init.init(
+                    id,
+                    ConfigurationHeadData {
+                        name: name.clone(),
+                        config: Mutex::new(HeadConfiguration {
+                            enabled: true,
+                            ..Default::default()
+                        }),
+                    },
+                );
+                inner.heads.insert(name, ConfiguredHead::Enabled(config_head));
+            }
+            zwlr_output_configuration_v1::Request::DisableHead { head } => {
+                let name = head.data::<String>().cloned().unwrap_or_default();
+                let mut inner = data.inner.lock().unwrap();
+                if inner.heads.contains_key(&name) {
+                    resource.post_error(
+                        zwlr_output_configuration_v1::Error::AlreadyConfiguredHead,
+                        format!("head {} configured twice", name),
+                    );
+                    return;
+                }
+                inner.heads.insert(name, ConfiguredHead::Disabled);
+            }
+            zwlr_output_configuration_v1::Request::Apply => apply_configuration(state, resource, data, false),
+            zwlr_output_configuration_v1::Request::Test => apply_configuration(state, resource, data, true),
+            _ => {}
+        }
+    }
+}
+
+fn apply_configuration<D: OutputManagementHandler>(
+    state: &mut D,
+    resource: &ZwlrOutputConfigurationV1,
+    data: &ConfigurationData,
+    test_only: bool,
+) {
+    let heads = {
+        let mut inner = data.inner.lock().unwrap();
+        if inner.used {
+            resource.post_error(zwlr_output_configuration_v1::Error::AlreadyUsed, "configuration already used");
+            return;
+        }
+        inner.used = true;
+
+        let mgmt = state.output_management_state();
+        if data.serial != mgmt.serial {
+            resource.cancelled();
+            return;
+        }
+        if let Some(missing) = mgmt.heads.keys().find(|name| !inner.heads.contains_key(*name)) {
+            resource.post_error(
+                zwlr_output_configuration_v1::Error::UnconfiguredHead,
+                format!("head {} was neither enabled nor disabled", missing),
+            );
+            return;
+        }
+
+        inner
+            .heads
+            .iter()
+            .map(|(name, configured)| {
+                let config = match configured {
+                    ConfiguredHead::Enabled(head) => head
+                        .data::<ConfigurationHeadData>()
+                        .map(|data| data.config.lock().unwrap().clone())
+                        .unwrap_or_default(),
+                    ConfiguredHead::Disabled => HeadConfiguration::default(),
+                };
+                (name.clone(), config)
+            })
+            .collect::<HashMap<_, _>>()
+    };
+
+    if state.apply_output_configuration(heads, test_only) {
+        resource.succeeded();
+    } else {
+        resource.failed();
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData> + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        resource: &ZwlrOutputConfigurationHeadV1,
+        request: zwlr_output_configuration_head_v1::Request,
+        data: &ConfigurationHeadData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        use zwlr_output_configuration_head_v1::{Error, Request};
+
+        let mut config = data.config.lock().unwrap();
+        let already_set = |resource: &ZwlrOutputConfigurationHeadV1, what: &str| {
+            resource.post_error(Error::AlreadySet, format!("{} already set", what));
+        };
+
+        match request {
+            Request::SetMode { mode } => {
+                if config.mode.is_some() {
+                    return already_set(resource, "mode");
+                }
+                match mode.data::<ModeData>() {
+                    Some(mode_data) if mode_data.output == data.name => {
+                        config.mode = Some(RequestedMode::Existing(mode_data.mode));
+                    }
+                    _ => resource.post_error(Error::InvalidMode, "mode does not belong to this head"),
+                }
+            }
+            Request::SetCustomMode { width, height, refresh } => {
+                if config.mode.is_some() {
+                    return already_set(resource, "mode");
+                }
+                if width <= 0 || height <= 0 || refresh < 0 {
+                    resource.post_error(Error::InvalidCustomMode, "invalid custom mode");
+                    return;
+                }
+                config.mode = Some(RequestedMode::Custom {
+                    size: (width, height).into(),
+                    refresh,
+                });
+            }
+            Request::SetPosition { x, y } => {
+                if config.position.is_some() {
+                    return already_set(resource, "position");
+                }
+                config.position = Some((x, y).into());
+            }
+            Request::SetTransform { transform } => {
+                if config.transform.is_some() {
+                    return already_set(resource, "transform");
+                }
+                match transform {
+                    WEnum::Value(transform) if from_wl_transform(transform).is_some() => {
+                        config.transform = from_wl_transform(transform);
+                    }
+                    _ => resource.post_error(Error::InvalidTransform, "invalid transform"),
+                }
+            }
+            Request::SetScale { scale } => {
+                if config.scale.is_some() {
+                    return already_set(resource, "scale");
+                }
+                if scale <= 0.0 || !scale.is_finite() {
+                    resource.post_error(Error::InvalidScale, "invalid scale");
+                    return;
+                }
+                config.scale = Some(scale);
+            }
+            // Adaptive sync is not supported by any of our backends, so the request is accepted
+            // and ignored; the head never advertises it as enabled.
+            Request::SetAdaptiveSync { .. } => {}
+            _ => {}
+        }
+    }
+}
+
+macro_rules! delegate_output_management {
+    ($ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_manager_v1::ZwlrOutputManagerV1: ()
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_manager_v1::ZwlrOutputManagerV1: ()
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_head_v1::ZwlrOutputHeadV1: String
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_mode_v1::ZwlrOutputModeV1: $crate::protocols::output_management::ModeData
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_configuration_v1::ZwlrOutputConfigurationV1: $crate::protocols::output_management::ConfigurationData
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1: $crate::protocols::output_management::ConfigurationHeadData
+        ] => $crate::protocols::output_management::OutputManagementState);
+    };
+}
+pub(crate) use delegate_output_management;
@@ -0,0 +1,295 @@
+//! Server side of `wlr-screencopy-unstable-v1`.
+//!
+//! Frames are validated here and handed to the compositor as [`Screencopy`] requests, which it
+//! fills in once it has rendered the output. Only shm buffers are offered. Every bound manager is
+//! its own capture session, since `copy_with_damage` reports damage since that manager's last copy.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::screencopy::v1::server::{
+            zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+            zwlr_screencopy_manager_v1::{self, ZwlrScreencopyManagerV1},
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+            backend::ClientId,
+            protocol::{wl_buffer::WlBuffer, wl_output::WlOutput},
+        },
+    },
+    utils::{Buffer, Logical, Rectangle, Transform},
+};
+
+use super::{CAPTURE_SHM_FORMATS, is_capture_buffer, next_capture_session_id};
+
+const VERSION: u32 = 3;
+
+pub trait ScreencopyHandler {
+    /// A client wants `frame` copied into its buffer. Dropping it without submitting reports
+    /// failure to the client.
+    fn screencopy_frame(&mut self, frame: Screencopy);
+
+    /// A manager went away, so nothing more will be captured for `session`.
+    fn screencopy_session_ended(&mut self, session: u64);
+}
+
+pub struct ScreencopyState;
+
+impl ScreencopyState {
+    pub fn new<D>(dh: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwlrScreencopyManagerV1, ()> + 'static,
+    {
+        dh.create_global::<D, ZwlrScreencopyManagerV1, _>(VERSION, ());
+        Self
+    }
+}
+
+pub struct ManagerData {
+    session: u64,
+}
+
+pub struct FrameData {
+    /// `None` if the output was already gone when the frame was created.
+    target: Option<(Output, Rectangle<i32, Buffer>)>,
+    overlay_cursor: bool,
+    session: u64,
+    used: AtomicBool,
+}
+
+/// A frame waiting to be copied.
+pub struct Screencopy {
+    pub output: Output,
+    /// The part of the output to copy, in buffer coordinates of its current mode.
+    pub region: Rectangle<i32, Buffer>,
+    pub overlay_cursor: bool,
+    /// Whether the copy should wait for damage and report it.
+    pub with_damage: bool,
+    pub session: u64,
+    pub buffer: WlBuffer,
+    frame: ZwlrScreencopyFrameV1,
+    submitted: bool,
+}
+
+impl Screencopy {
+    /// Whether the client still wants this frame.
+    pub fn is_alive(&self) -> bool {
+        self.frame.is_alive()
+    }
+
+    /// Reports what changed since the last copy, relative to `region`. Only sent for copies
+    /// that asked for damage.
+    pub fn damage(&self, damage: impl IntoIterator<Item = Rectangle<i32, Buffer>>) {
+        if !self.with_damage {
+            return;
+        }
+        for rect in damage {
+            self.frame
+                .damage(rect.loc.x as u32, rect.loc.y as u32, rect.size.w as u32, rect.size.h as u32);
+        }
+    }
+
+    /// Tells the client its buffer is filled, with rows top to bottom.
+    pub fn submit(mut self, time: Duration) {
+        self.frame.flags(zwlr_screencopy_frame_v1::Flags::empty());
+        let secs = time.as_secs();
+        self.frame.ready((secs >> 32) as u32, secs as u32, time.subsec_nanos());
+        self.submitted = true;
+    }
+}
+
+impl Drop for Screencopy {
+    fn drop(&mut self) {
+        if !self.submitted {
+            self.frame.failed();
+        }
+    }
+}
+
+/// The whole of `output`, or `region` of it, in buffer coordinates of its current mode.
+fn buffer_region(output: &Output, region: Option<Rectangle<i32, Logical>>) -> Option<Rectangle<i32, Buffer>> {
+    let mode = output.current_mode()?;
+    let full = Rectangle::from_size(mode.size.to_logical(1).to_buffer(1, Transform::Normal));
+    let Some(region) = region else {
+        return Some(full);
+    };
+
+    let transform = output.current_transform();
+    let scale = output.current_scale().fractional_scale();
+    let logical_size = transform.transform_size(mode.size).to_f64().to_logical(scale);
+    region
+        .to_f64()
+        .to_buffer(scale, transform, &logical_size)
+        .to_i32_round()
+        .intersection(full)
+        .filter(|region| !region.is_empty())
+}
+
+fn init_frame<D>(
+    data_init: &mut DataInit<'_, D>,
+    frame: New<ZwlrScreencopyFrameV1>,
+    output: &WlOutput,
+    region: Option<Rectangle<i32, Logical>>,
+    overlay_cursor: i32,
+    session: u64,
+) where
+    D: Dispatch<ZwlrScreencopyFrameV1, FrameData> + 'static,
+{
+    let target = Output::from_resource(output).and_then(|output| {
+        let region = buffer_region(&output, region)?;
+        Some((output, region))
+    });
+    let frame = data_init.init(
+        frame,
+        FrameData {
+            target: target.clone(),
+            overlay_cursor: overlay_cursor != 0,
+            session,
+            used: AtomicBool::new(false),
+        },
+    );
+
+    let Some((_, region)) = target else {
+        frame.failed();
+        return;
+    };
+    let (width, height) = (region.size.w as u32, region.size.h as u32);
+    for format in CAPTURE_SHM_FORMATS {
+        frame.buffer(format, width, height, width * 4);
+    }
+    if frame.version() >= 3 {
+        frame.buffer_done();
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrScreencopyManagerV1, (), D> for ScreencopyState
+where
+    D: GlobalDispatch<ZwlrScreencopyManagerV1, ()> + Dispatch<ZwlrScreencopyManagerV1, ManagerData> + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrScreencopyManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(
+            resource,
+            ManagerData {
+                session: next_capture_session_id(),
+            },
+        );
+    }
+}
+
+impl<D> Dispatch<ZwlrScreencopyManagerV1, ManagerData, D> for ScreencopyState
+where
+    D: Dispatch<ZwlrScreencopyManagerV1, ManagerData>
+        + Dispatch<ZwlrScreencopyFrameV1, FrameData>
+        + ScreencopyHandler
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _resource: &ZwlrScreencopyManagerV1,
+        request: zwlr_screencopy_manager_v1::Request,
+        data: &ManagerData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_screencopy_manager_v1::Request::CaptureOutput {
+                frame,
+                overlay_cursor,
+                output,
+            } => init_frame(data_init, frame, &output, None, overlay_cursor, data.session),
+            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion {
+                frame,
+                overlay_cursor,
+                output,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let region = Rectangle::new((x, y).into(), (width, height).into());
+                init_frame(data_init, frame, &output, Some(region), overlay_cursor, data.session);
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, _resource: &ZwlrScreencopyManagerV1, data: &ManagerData) {
+        state.screencopy_session_ended(data.session);
+    }
+}
+
+impl<D> Dispatch<ZwlrScreencopyFrameV1, FrameData, D> for ScreencopyState
+where
+    D: Dispatch<ZwlrScreencopyFrameV1, FrameData> + ScreencopyHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwlrScreencopyFrameV1,
+        request: zwlr_screencopy_frame_v1::Request,
+        data: &FrameData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        let (buffer, with_damage) = match request {
+            zwlr_screencopy_frame_v1::Request::Copy { buffer } => (buffer, false),
+            zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => (buffer, true),
+            _ => return,
+        };
+
+        if data.used.swap(true, Ordering::Relaxed) {
+            resource.post_error(zwlr_screencopy_frame_v1::Error::AlreadyUsed, "frame was already copied");
+            return;
+        }
+        let Some((output, region)) = data.target.clone() else {
+            resource.failed();
+            return;
+        };
+        if !is_capture_buffer(&buffer, region.size) {
+            resource.post_error(
+                zwlr_screencopy_frame_v1::Error::InvalidBuffer,
+                "buffer does not match the advertised size, stride or formats",
+            );
+            return;
+        }
+
+        state.screencopy_frame(Screencopy {
+            output,
+            region,
+            overlay_cursor: data.overlay_cursor,
+            with_damage,
+            session: data.session,
+            buffer,
+            frame: resource.clone(),
+            submitted: false,
+        });
+    }
+}
+
+macro_rules! delegate_screencopy {
+    ($ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::screencopy::v1::server::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1: ()
+        ] => $crate::protocols::screencopy::ScreencopyState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::screencopy::v1::server::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1: $crate::protocols::screencopy::ManagerData
+        ] => $crate::protocols::screencopy::ScreencopyState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::screencopy::v1::server::zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1: $crate::protocols::screencopy::FrameData
+        ] => $crate::protocols::screencopy::ScreencopyState);
+    };
+}
+pub(crate) use delegate_screencopy;
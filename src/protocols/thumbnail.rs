@@ -0,0 +1,25 @@
+//! Bindings for `nanaimo-thumbnail-v1`, our protocol for capturing window thumbnails. It only
+//! adds a source manager, the sources themselves are `ext_image_capture_source_v1` objects and
+//! are dispatched by the image copy capture module.
+
+#![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#![allow(missing_docs, clippy::all)]
+
+pub mod server {
+    use smithay::reexports::wayland_protocols::ext::foreign_toplevel_list::v1::server::*;
+    use smithay::reexports::wayland_protocols::ext::image_capture_source::v1::server::*;
+    use wayland_server;
+    use wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_protocols::ext::foreign_toplevel_list::v1::server::__interfaces::*;
+        use smithay::reexports::wayland_protocols::ext::image_capture_source::v1::server::__interfaces::*;
+        use wayland_server::backend as wayland_backend;
+        use wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/nanaimo-thumbnail-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/nanaimo-thumbnail-v1.xml");
+}
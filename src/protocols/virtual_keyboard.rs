@@ -0,0 +1,227 @@
+//! Server side of `virtual-keyboard-unstable-v1`.
+//!
+//! Keys are handed to the compositor as if they came from a physical keyboard, so they go through
+//! keybindings like any other key. Each keyboard's keys and modifiers are interpreted with the
+//! keymap its client uploaded, which the handler puts on the seat while that keyboard types. Only
+//! clients the handler allows may create keyboards.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    os::{fd::OwnedFd, unix::fs::FileExt},
+    sync::{Arc, Mutex},
+};
+
+use smithay::{
+    backend::input::KeyState,
+    input::keyboard::{Keycode, xkb},
+    reexports::{
+        wayland_protocols_misc::zwp_virtual_keyboard_v1::server::{
+            zwp_virtual_keyboard_manager_v1::{self, ZwpVirtualKeyboardManagerV1},
+            zwp_virtual_keyboard_v1::{self, ZwpVirtualKeyboardV1},
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, backend::ClientId,
+            protocol::wl_keyboard,
+        },
+    },
+};
+
+const VERSION: u32 = 1;
+
+pub trait VirtualKeyboardHandler {
+    /// Whether `client` may synthesize key presses.
+    fn virtual_keyboard_allowed(&self, client: &Client) -> bool;
+
+    /// Presses or releases `keycode`, as `keymap` (xkb text format) maps it.
+    fn virtual_keyboard_key(&mut self, keymap: &str, keycode: Keycode, state: KeyState, time: u32);
+
+    /// Sets the serialized modifier state, as `keymap` defines the modifiers.
+    fn virtual_keyboard_modifiers(&mut self, keymap: &str, depressed: u32, latched: u32, locked: u32, group: u32);
+}
+
+pub struct VirtualKeyboardManagerState;
+
+impl VirtualKeyboardManagerState {
+    pub fn new<D>(dh: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwpVirtualKeyboardManagerV1, ()> + 'static,
+    {
+        dh.create_global::<D, ZwpVirtualKeyboardManagerV1, _>(VERSION, ());
+        Self
+    }
+}
+
+#[derive(Default)]
+pub struct VirtualKeyboardData {
+    inner: Mutex<KeyboardInner>,
+}
+
+#[derive(Default)]
+struct KeyboardInner {
+    /// The last valid keymap the client uploaded, in xkb text format.
+    keymap: Option<Arc<str>>,
+    /// Pressed keys, as evdev codes, released again when the keyboard goes away.
+    pressed: HashSet<u32>,
+    last_time: u32,
+}
+
+/// Largest keymap a client may upload. Real ones are well below 100 KiB.
+const MAX_KEYMAP_SIZE: u32 = 1024 * 1024;
+
+/// Reads an uploaded keymap and makes sure xkb can compile it.
+fn read_keymap(fd: OwnedFd, size: u32) -> Result<String, String> {
+    // The size comes straight from the client, so check it before allocating for it.
+    if size > MAX_KEYMAP_SIZE {
+        return Err(format!("keymap of {} bytes is larger than {} bytes", size, MAX_KEYMAP_SIZE));
+    }
+    let mut keymap = vec![0; size as usize];
+    File::from(fd)
+        .read_exact_at(&mut keymap, 0)
+        .map_err(|err| format!("failed to read keymap: {}", err))?;
+    // The keymap is NUL-terminated.
+    let len = keymap.iter().position(|&byte| byte == 0).unwrap_or(keymap.len());
+    keymap.truncate(len);
+    let keymap = String::from_utf8(keymap).map_err(|_| "keymap is not UTF-8".to_string())?;
+
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    xkb::Keymap::new_from_string(&context, keymap.clone(), xkb::KEYMAP_FORMAT_TEXT_V1, xkb::KEYMAP_COMPILE_NO_FLAGS)
+        .ok_or("keymap does not compile")?;
+    Ok(keymap)
+}
+
+impl<D> GlobalDispatch<ZwpVirtualKeyboardManagerV1, (), D> for VirtualKeyboardManagerState
+where
+    D: GlobalDispatch<ZwpVirtualKeyboardManagerV1, ()> + Dispatch<ZwpVirtualKeyboardManagerV1, ()> + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpVirtualKeyboardManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<ZwpVirtualKeyboardManagerV1, (), D> for VirtualKeyboardManagerState
+where
+    D: Dispatch<ZwpVirtualKeyboardManagerV1, ()>
+        + Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData>
+        + VirtualKeyboardHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        client: &Client,
+        resource: &ZwpVirtualKeyboardManagerV1,
+        request: zwp_virtual_keyboard_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let zwp_virtual_keyboard_manager_v1::Request::CreateVirtualKeyboard { id, .. } = request else {
+            return;
+        };
+        if !state.virtual_keyboard_allowed(client) {
+            resource.post_error(
+                zwp_virtual_keyboard_manager_v1::Error::Unauthorized,
+                "client is not allowed to create virtual keyboards",
+            );
+            return;
+        }
+        data_init.init(id, VirtualKeyboardData::default());
+    }
+}
+
+impl<D> Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData, D> for VirtualKeyboardManagerState
+where
+    D: Dispatch<ZwpVirtualKeyboardV1, VirtualKeyboardData> + VirtualKeyboardHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwpVirtualKeyboardV1,
+        request: zwp_virtual_keyboard_v1::Request,
+        data: &VirtualKeyboardData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwp_virtual_keyboard_v1::Request::Keymap { format, fd, size } => {
+                if format != wl_keyboard::KeymapFormat::XkbV1 as u32 {
+                    tracing::warn!("Ignoring virtual keyboard keymap in format {}", format);
+                    return;
+                }
+                match read_keymap(fd, size) {
+                    Ok(keymap) => data.inner.lock().unwrap().keymap = Some(keymap.into()),
+                    Err(err) => tracing::warn!("Ignoring virtual keyboard keymap: {}", err),
+                }
+            }
+            zwp_virtual_keyboard_v1::Request::Key { time, key, state: key_state } => {
+                let key_state = if key_state == 1 { KeyState::Pressed } else { KeyState::Released };
+                let keymap = {
+                    let mut inner = data.inner.lock().unwrap();
+                    let Some(keymap) = inner.keymap.clone() else {
+                        resource.post_error(zwp_virtual_keyboard_v1::Error::NoKeymap, "no keymap was set");
+                        return;
+                    };
+                    inner.last_time = time;
+                    let changed = match key_state {
+                        KeyState::Pressed => inner.pressed.insert(key),
+                        KeyState::Released => inner.pressed.remove(&key),
+                    };
+                    if !changed {
+                        return;
+                    }
+                    keymap
+                };
+                // Protocol key codes are evdev codes; xkb ones are offset by 8.
+                state.virtual_keyboard_key(&keymap, Keycode::new(key + 8), key_state, time);
+            }
+            zwp_virtual_keyboard_v1::Request::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+            } => {
+                let Some(keymap) = data.inner.lock().unwrap().keymap.clone() else {
+                    resource.post_error(zwp_virtual_keyboard_v1::Error::NoKeymap, "no keymap was set");
+                    return;
+                };
+                state.virtual_keyboard_modifiers(&keymap, mods_depressed, mods_latched, mods_locked, group);
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, _resource: &ZwpVirtualKeyboardV1, data: &VirtualKeyboardData) {
+        let (keymap, pressed, time) = {
+            let mut inner = data.inner.lock().unwrap();
+            (inner.keymap.take(), std::mem::take(&mut inner.pressed), inner.last_time)
+        };
+        let Some(keymap) = keymap else {
+            return;
+        };
+        for key in pressed {
+            state.virtual_keyboard_key(&keymap, Keycode::new(key + 8), KeyState::Released, time);
+        }
+    }
+}
+
+macro_rules! delegate_virtual_keyboard {
+    ($ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_misc::zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1: ()
+        ] => $crate::protocols::virtual_keyboard::VirtualKeyboardManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_misc::zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1: ()
+        ] => $crate::protocols::virtual_keyboard::VirtualKeyboardManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_misc::zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1: $crate::protocols::virtual_keyboard::VirtualKeyboardData
+        ] => $crate::protocols::virtual_keyboard::VirtualKeyboardManagerState);
+    };
+}
+pub(crate) use delegate_virtual_keyboard;
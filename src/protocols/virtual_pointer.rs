@@ -0,0 +1,240 @@
+//! Server side of `wlr-virtual-pointer-unstable-v1`.
+//!
+//! Events are handed to the compositor as if they came from a physical pointer. Axis events are
+//! collected into one [`AxisFrame`] and delivered on `frame`. Pointers created by clients the
+//! handler does not allow stay inert, since the protocol has no error to refuse them with.
+
+use std::sync::Mutex;
+
+use smithay::{
+    backend::input::{Axis, AxisSource, ButtonState},
+    input::pointer::AxisFrame,
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::virtual_pointer::v1::server::{
+            zwlr_virtual_pointer_manager_v1::{self, ZwlrVirtualPointerManagerV1},
+            zwlr_virtual_pointer_v1::{self, ZwlrVirtualPointerV1},
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+            protocol::wl_pointer,
+        },
+    },
+    utils::{Logical, Point},
+};
+
+const VERSION: u32 = 2;
+
+pub trait VirtualPointerHandler {
+    /// Whether `client` may synthesize pointer input.
+    fn virtual_pointer_allowed(&self, client: &Client) -> bool;
+
+    fn virtual_pointer_motion(&mut self, delta: Point<f64, Logical>, time: u32);
+
+    /// Moves the pointer to `position`, given as a fraction of `output`, or of the whole layout
+    /// if the client did not pick an output.
+    fn virtual_pointer_motion_absolute(&mut self, output: Option<&Output>, position: Point<f64, Logical>, time: u32);
+
+    fn virtual_pointer_button(&mut self, button: u32, state: ButtonState, time: u32);
+
+    fn virtual_pointer_axis(&mut self, frame: AxisFrame);
+}
+
+pub struct VirtualPointerManagerState;
+
+impl VirtualPointerManagerState {
+    pub fn new<D>(dh: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<ZwlrVirtualPointerManagerV1, ()> + 'static,
+    {
+        dh.create_global::<D, ZwlrVirtualPointerManagerV1, _>(VERSION, ());
+        Self
+    }
+}
+
+pub struct VirtualPointerData {
+    allowed: bool,
+    output: Option<Output>,
+    /// Axis events since the last frame.
+    axis: Mutex<Option<AxisFrame>>,
+}
+
+impl VirtualPointerData {
+    fn with_axis_frame(&self, time: u32, f: impl FnOnce(AxisFrame) -> AxisFrame) {
+        let mut axis = self.axis.lock().unwrap();
+        let frame = axis.take().unwrap_or_else(|| AxisFrame::new(time));
+        *axis = Some(f(frame));
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrVirtualPointerManagerV1, (), D> for VirtualPointerManagerState
+where
+    D: GlobalDispatch<ZwlrVirtualPointerManagerV1, ()> + Dispatch<ZwlrVirtualPointerManagerV1, ()> + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrVirtualPointerManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<ZwlrVirtualPointerManagerV1, (), D> for VirtualPointerManagerState
+where
+    D: Dispatch<ZwlrVirtualPointerManagerV1, ()>
+        + Dispatch<ZwlrVirtualPointerV1, VirtualPointerData>
+        + VirtualPointerHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        client: &Client,
+        _resource: &ZwlrVirtualPointerManagerV1,
+        request: zwlr_virtual_pointer_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let (id, output) = match request {
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointer { id, .. } => (id, None),
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointerWithOutput { id, output, .. } => {
+                (id, output.as_ref().and_then(Output::from_resource))
+            }
+            _ => return,
+        };
+        data_init.init(
+            id,
+            VirtualPointerData {
+                allowed: state.virtual_pointer_allowed(client),
+                output,
+                axis: Mutex::new(None),
+            },
+        );
+    }
+}
+
+fn axis(axis: WEnum<wl_pointer::Axis>) -> Option<Axis> {
+    match axis.into_result().ok()? {
+        wl_pointer::Axis::VerticalScroll => Some(Axis::Vertical),
+        wl_pointer::Axis::HorizontalScroll => Some(Axis::Horizontal),
+        _ => None,
+    }
+}
+
+fn axis_source(source: WEnum<wl_pointer::AxisSource>) -> Option<AxisSource> {
+    match source.into_result().ok()? {
+        wl_pointer::AxisSource::Wheel => Some(AxisSource::Wheel),
+        wl_pointer::AxisSource::Finger => Some(AxisSource::Finger),
+        wl_pointer::AxisSource::Continuous => Some(AxisSource::Continuous),
+        wl_pointer::AxisSource::WheelTilt => Some(AxisSource::WheelTilt),
+        _ => None,
+    }
+}
+
+impl<D> Dispatch<ZwlrVirtualPointerV1, VirtualPointerData, D> for VirtualPointerManagerState
+where
+    D: Dispatch<ZwlrVirtualPointerV1, VirtualPointerData> + VirtualPointerHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        resource: &ZwlrVirtualPointerV1,
+        request: zwlr_virtual_pointer_v1::Request,
+        data: &VirtualPointerData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        if !data.allowed {
+            return;
+        }
+        let invalid_axis = || resource.post_error(zwlr_virtual_pointer_v1::Error::InvalidAxis, "invalid axis");
+        match request {
+            zwlr_virtual_pointer_v1::Request::Motion { time, dx, dy } => {
+                state.virtual_pointer_motion((dx, dy).into(), time);
+            }
+            zwlr_virtual_pointer_v1::Request::MotionAbsolute {
+                time,
+                x,
+                y,
+                x_extent,
+                y_extent,
+            } => {
+                if x_extent == 0 || y_extent == 0 {
+                    return;
+                }
+                let position = (x as f64 / x_extent as f64, y as f64 / y_extent as f64).into();
+                state.virtual_pointer_motion_absolute(data.output.as_ref(), position, time);
+            }
+            zwlr_virtual_pointer_v1::Request::Button {
+                time,
+                button,
+                state: button_state,
+            } => {
+                let button_state = match button_state.into_result() {
+                    Ok(wl_pointer::ButtonState::Pressed) => ButtonState::Pressed,
+                    _ => ButtonState::Released,
+                };
+                state.virtual_pointer_button(button, button_state, time);
+            }
+            zwlr_virtual_pointer_v1::Request::Axis { time, axis: a, value } => {
+                let Some(a) = axis(a) else {
+                    invalid_axis();
+                    return;
+                };
+                data.with_axis_frame(time, |frame| frame.value(a, value));
+            }
+            zwlr_virtual_pointer_v1::Request::AxisSource { axis_source: source } => {
+                let Some(source) = axis_source(source) else {
+                    resource.post_error(zwlr_virtual_pointer_v1::Error::InvalidAxisSource, "invalid axis source");
+                    return;
+                };
+                data.with_axis_frame(0, |frame| frame.source(source));
+            }
+            zwlr_virtual_pointer_v1::Request::AxisStop { time, axis: a } => {
+                let Some(a) = axis(a) else {
+                    invalid_axis();
+                    return;
+                };
+                data.with_axis_frame(time, |frame| frame.stop(a));
+            }
+            zwlr_virtual_pointer_v1::Request::AxisDiscrete {
+                time,
+                axis: a,
+                value,
+                discrete,
+            } => {
+                let Some(a) = axis(a) else {
+                    invalid_axis();
+                    return;
+                };
+                data.with_axis_frame(time, |frame| frame.value(a, value).v120(a, discrete * 120));
+            }
+            zwlr_virtual_pointer_v1::Request::Frame => {
+                let frame = data.axis.lock().unwrap().take();
+                if let Some(frame) = frame {
+                    state.virtual_pointer_axis(frame);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+macro_rules! delegate_virtual_pointer {
+    ($ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1: ()
+        ] => $crate::protocols::virtual_pointer::VirtualPointerManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1: ()
+        ] => $crate::protocols::virtual_pointer::VirtualPointerManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1: $crate::protocols::virtual_pointer::VirtualPointerData
+        ] => $crate::protocols::virtual_pointer::VirtualPointerManagerState);
+    };
+}
+pub(crate) use delegate_virtual_pointer;
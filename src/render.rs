@@ -1,57 +1,413 @@
+use std::collections::HashMap;
+
 use smithay::{
-    backend::renderer::{
-        damage::{Error as OutputDamageTrackerError, OutputDamageTracker, RenderOutputResult},
-        element::{
-            surface::WaylandSurfaceRenderElement,
-            Wrap,
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::{Error as OutputDamageTrackerError, OutputDamageTracker, RenderOutputResult},
+            element::{
+                AsRenderElements, Element, Kind,
+                memory::MemoryRenderBufferRenderElement,
+                surface::{WaylandSurfaceRenderElement, render_elements_from_surface_tree},
+                texture::{TextureBuffer, TextureRenderElement},
+                utils::{Relocate, RelocateRenderElement, RescaleRenderElement},
+                RenderElement,
+            },
+            Bind, Color32F, ExportMem, ImportAll, ImportMem, Offscreen, Renderer, TextureMapping,
         },
-        Color32F, ImportAll, ImportMem, Renderer,
-    },
-    desktop::space::{
-        Space, SpaceRenderElements,
     },
+    desktop::{Space, Window},
     output::Output,
-    desktop::Window,
+    input::pointer::{CursorImageStatus, CursorImageSurfaceData},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Size, Transform},
+    wayland::compositor::with_states,
 };
 
-// Removed unused import: use crate::state::NanaimoState;
+use crate::animations::AnimationManager;
+use crate::cursor::CursorTheme;
 
 smithay::backend::renderer::element::render_elements! {
     pub CustomRenderElements<R> where
         R: ImportAll + ImportMem;
     Surface=WaylandSurfaceRenderElement<R>,
+    Memory=MemoryRenderBufferRenderElement<R>,
 }
 
+/// A window surface with its animated zoom and displacement applied.
+pub type AnimatedRenderElement<R> = RelocateRenderElement<RescaleRenderElement<WaylandSurfaceRenderElement<R>>>;
+
+/// The last contents of a closed window, shrinking and fading out.
+pub type ClosingRenderElement<R> =
+    RelocateRenderElement<RescaleRenderElement<TextureRenderElement<<R as Renderer>::TextureId>>>;
+
 smithay::backend::renderer::element::render_elements! {
-    pub OutputRenderElements<R, E> where R: ImportAll + ImportMem;
-    Space=SpaceRenderElements<R, E>,
-    Window=Wrap<E>,
+    pub OutputRenderElements<R> where R: ImportAll + ImportMem;
+    Window=WaylandSurfaceRenderElement<R>,
+    Animated=AnimatedRenderElement<R>,
+    Closing=ClosingRenderElement<R>,
     Custom=CustomRenderElements<R>,
 }
 
+/// Render state that outlives a frame: what each window last looked like, so it can still be
+/// drawn while it animates out after the client destroyed it.
+pub struct RenderCache<R: Renderer> {
+    /// Surface elements of every window from the last frame it was drawn without animation.
+    last_frame: HashMap<Window, Vec<WaylandSurfaceRenderElement<R>>>,
+    snapshots: HashMap<Window, Snapshot<R::TextureId>>,
+}
+
+/// A closed window rendered into a texture, placed relative to the window location.
+struct Snapshot<T> {
+    buffer: TextureBuffer<T>,
+    offset: Point<i32, Physical>,
+    size: Size<i32, Physical>,
+}
+
+impl<R: Renderer> Default for RenderCache<R> {
+    fn default() -> Self {
+        Self {
+            last_frame: HashMap::new(),
+            snapshots: HashMap::new(),
+        }
+    }
+}
+
+/// Everything drawn on an output in one frame, front to back.
+pub struct FrameElements<R: ImportAll + ImportMem> {
+    pub elements: Vec<OutputRenderElements<R>>,
+    /// The window each element belongs to, if it was drawn without animation.
+    owners: Vec<Option<Window>>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn render_output<'a, 'd, R>(
     output: &'a Output,
     space: &'a Space<Window>,
+    animations: &'a AnimationManager,
+    cache: &'a mut RenderCache<R>,
     renderer: &'a mut R,
     framebuffer: &'a mut R::Framebuffer<'_>,
     damage_tracker: &'d mut OutputDamageTracker,
     age: usize,
+    clear_color: Color32F,
+    custom_elements: Vec<CustomRenderElements<R>>,
 ) -> Result<RenderOutputResult<'d>, OutputDamageTrackerError<R::Error>>
+where
+    R: Renderer + ImportAll + ImportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+    R::TextureId: Clone + Send + 'static,
+{
+    let frame = output_elements(output, space, animations, cache, renderer, custom_elements);
+    let result = damage_tracker.render_output(renderer, framebuffer, age, &frame.elements, clear_color);
+
+    // Keep what each live window looked like, in case it closes before the next frame.
+    cache
+        .last_frame
+        .retain(|window, _| space.elements().any(|w| w == window) && animations.get(window).is_none());
+    for (element, owner) in frame.elements.into_iter().zip(frame.owners) {
+        if let (OutputRenderElements::Window(surface), Some(window)) = (element, owner) {
+            cache.last_frame.entry(window).or_default().push(surface);
+        }
+    }
+
+    result
+}
+
+/// Builds the elements for `output` as they appear on screen, animations included.
+pub fn output_elements<R>(
+    output: &Output,
+    space: &Space<Window>,
+    animations: &AnimationManager,
+    cache: &mut RenderCache<R>,
+    renderer: &mut R,
+    custom_elements: Vec<CustomRenderElements<R>>,
+) -> FrameElements<R>
+where
+    R: Renderer + ImportAll + ImportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+    R::TextureId: Clone + Send + 'static,
+{
+    let scale = output.current_scale().fractional_scale();
+    let output_location = space.output_geometry(output).map(|geo| geo.loc).unwrap_or_default();
+
+    // Custom elements (the cursor) are drawn on top of everything, closing windows above the
+    // live ones. Elements come front to back.
+    let mut elements: Vec<OutputRenderElements<R>> = custom_elements
+        .into_iter()
+        .map(OutputRenderElements::Custom)
+        .collect();
+    let mut owners: Vec<Option<Window>> = vec![None; elements.len()];
+
+    cache.snapshots.retain(|window, _| animations.closing.iter().any(|c| &c.window == window));
+    for closing in &animations.closing {
+        if !cache.snapshots.contains_key(&closing.window) {
+            let Some(surfaces) = cache.last_frame.remove(&closing.window) else {
+                continue;
+            };
+            // The cached elements sit where the window was on screen in their frame.
+            let origin = (closing.location - output_location).to_physical_precise_round(scale);
+            match snapshot(renderer, &surfaces, scale) {
+                Ok(Some(mut snapshot)) => {
+                    snapshot.offset -= origin;
+                    cache.snapshots.insert(closing.window.clone(), snapshot);
+                }
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::warn!("Failed to snapshot closing window: {}", err);
+                    continue;
+                }
+            }
+        }
+        let snapshot = &cache.snapshots[&closing.window];
+        let Some(state) = animations.get(&closing.window) else {
+            continue;
+        };
+        let origin = (closing.location - output_location).to_physical_precise_round(scale) + snapshot.offset;
+        let element = TextureRenderElement::from_texture_buffer(
+            origin.to_f64(),
+            &snapshot.buffer,
+            Some(state.alpha as f32),
+            None,
+            Some(snapshot.size.to_f64().to_logical(scale).to_i32_round()),
+            Kind::Unspecified,
+        );
+        let size = snapshot.size.to_f64();
+        let shift = Point::<f64, Physical>::from((size.w * (1.0 - state.scale) / 2.0, size.h * (1.0 - state.scale) / 2.0));
+        let element = RescaleRenderElement::from_element(element, origin, state.scale);
+        let element = RelocateRenderElement::from_element(element, shift.to_i32_round(), Relocate::Relative);
+        elements.push(OutputRenderElements::Closing(element));
+        owners.push(None);
+    }
+
+    for window in space.elements().rev() {
+        let Some(location) = space.element_location(window) else {
+            continue;
+        };
+        let geometry = window.geometry();
+        let origin = (location - output_location).to_physical_precise_round(scale);
+        let render_location = (location - geometry.loc - output_location).to_physical_precise_round(scale);
+        let state = animations.get(window);
+        let alpha = state.map_or(1.0, |state| state.alpha as f32);
+        let surfaces = window.render_elements::<WaylandSurfaceRenderElement<R>>(
+            renderer,
+            render_location,
+            Scale::from(scale),
+            alpha,
+        );
+
+        let Some(state) = state else {
+            owners.extend(std::iter::repeat_n(Some(window.clone()), surfaces.len()));
+            elements.extend(surfaces.into_iter().map(OutputRenderElements::Window));
+            continue;
+        };
+
+        // Zoom around the centre for open/close, stretch from the top-left for resizes.
+        let (resize_w, resize_h) = state.resize;
+        let size = geometry.size.to_f64().to_physical(scale);
+        let shift = Point::<f64, Physical>::from((
+            size.w * resize_w * (1.0 - state.scale) / 2.0,
+            size.h * resize_h * (1.0 - state.scale) / 2.0,
+        ));
+        let relocate = (state.offset.to_physical(scale) + shift).to_i32_round();
+        let zoom = Scale::from((state.scale * resize_w, state.scale * resize_h));
+        for surface in surfaces {
+            let element = RescaleRenderElement::from_element(surface, origin, zoom);
+            let element = RelocateRenderElement::from_element(element, relocate, Relocate::Relative);
+            elements.push(OutputRenderElements::Animated(element));
+            owners.push(None);
+        }
+    }
+
+    FrameElements { elements, owners }
+}
+
+/// What a locked output shows instead of the desktop: its lock surface, if the locker made one,
+/// and the custom elements on top.
+pub fn lock_elements<R>(
+    renderer: &mut R,
+    output: &Output,
+    lock_surface: Option<&WlSurface>,
+    custom_elements: Vec<CustomRenderElements<R>>,
+) -> Vec<OutputRenderElements<R>>
 where
     R: Renderer + ImportAll + ImportMem,
     R::TextureId: Clone + 'static,
 {
-    let elements = smithay::desktop::space::space_render_elements::<_, Window, _>(
-        renderer,
-        [space],
-        output,
-        1.0,
-    )
-    .expect("output without mode?");
-    
-    let output_render_elements = elements.into_iter().map(OutputRenderElements::Space).collect::<Vec<_>>();
+    let scale = output.current_scale().fractional_scale();
+    let mut elements: Vec<OutputRenderElements<R>> = custom_elements
+        .into_iter()
+        .map(OutputRenderElements::Custom)
+        .collect();
+    if let Some(surface) = lock_surface {
+        elements.extend(render_elements_from_surface_tree::<_, OutputRenderElements<R>>(
+            renderer,
+            surface,
+            (0, 0),
+            scale,
+            1.0,
+            Kind::Unspecified,
+        ));
+    }
+    elements
+}
+
+/// Renders `surfaces` into a texture sized to their bounding box, offset by its position.
+fn snapshot<R>(
+    renderer: &mut R,
+    surfaces: &[WaylandSurfaceRenderElement<R>],
+    scale: f64,
+) -> Result<Option<Snapshot<R::TextureId>>, R::Error>
+where
+    R: Renderer + ImportAll + Offscreen<R::TextureId> + Bind<R::TextureId>,
+    R::TextureId: Clone + 'static,
+{
+    let scale = Scale::from(scale);
+    let Some(bbox) = surfaces
+        .iter()
+        .map(|surface| surface.geometry(scale))
+        .reduce(|bbox: Rectangle<i32, Physical>, geometry| bbox.merge(geometry))
+    else {
+        return Ok(None);
+    };
+    if bbox.is_empty() {
+        return Ok(None);
+    }
+
+    let buffer_size = bbox.size.to_logical(1).to_buffer(1, Transform::Normal);
+    let mut texture = renderer.create_buffer(Fourcc::Abgr8888, buffer_size)?;
+    {
+        let mut framebuffer = renderer.bind(&mut texture)?;
+        let elements: Vec<_> = surfaces
+            .iter()
+            .map(|surface| RelocateRenderElement::from_element(surface, -bbox.loc, Relocate::Relative))
+            .collect();
+        let mut damage_tracker = OutputDamageTracker::new(bbox.size, scale, Transform::Normal);
+        if let Err(err) = damage_tracker.render_output(renderer, &mut framebuffer, 0, &elements, Color32F::TRANSPARENT) {
+            tracing::warn!("Failed to render window snapshot: {:?}", err);
+            return Ok(None);
+        }
+    }
+
+    let buffer = TextureBuffer::from_texture(renderer, texture, 1, Transform::Normal, None);
+    Ok(Some(Snapshot {
+        buffer,
+        offset: bbox.loc,
+        size: bbox.size,
+    }))
+}
+
+/// Builds the cursor for `output` with the pointer at `location`, in global coordinates.
+///
+/// Client cursor surfaces are drawn at their hotspot; named cursors come from the xcursor theme.
+pub fn cursor_elements<R>(
+    renderer: &mut R,
+    output: &Output,
+    output_location: Point<i32, Logical>,
+    location: Point<f64, Logical>,
+    status: &CursorImageStatus,
+    theme: &mut CursorTheme,
+    time: std::time::Duration,
+) -> Vec<CustomRenderElements<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Clone + Send + 'static,
+{
+    let scale = output.current_scale().fractional_scale();
+    let location = (location - output_location.to_f64()).to_physical(scale);
+
+    match status {
+        CursorImageStatus::Hidden => Vec::new(),
+        CursorImageStatus::Surface(surface) => {
+            let hotspot = with_states(surface, |states| {
+                states
+                    .data_map
+                    .get::<CursorImageSurfaceData>()
+                    .map(|data| data.lock().unwrap().hotspot)
+                    .unwrap_or_default()
+            });
+            let position = (location - hotspot.to_f64().to_physical(scale)).to_i32_round();
+            render_elements_from_surface_tree(renderer, surface, position, scale, 1.0, Kind::Cursor)
+                .into_iter()
+                .map(CustomRenderElements::Surface)
+                .collect()
+        }
+        CursorImageStatus::Named(icon) => {
+            let (buffer, hotspot) = theme.image(*icon, scale.ceil() as u32, time);
+            let position = location - hotspot.to_physical(scale);
+            match MemoryRenderBufferRenderElement::from_buffer(renderer, position, &buffer, None, None, None, Kind::Cursor) {
+                Ok(element) => vec![CustomRenderElements::Memory(element)],
+                Err(err) => {
+                    tracing::warn!("Failed to upload cursor image: {}", err);
+                    Vec::new()
+                }
+            }
+        }
+    }
+}
 
-    let clear_color = Color32F::new(0.1, 0.1, 0.1, 1.0);
+/// Pixels read back from the GPU, as tightly packed RGBA rows.
+pub struct Pixels {
+    pub size: Size<i32, Buffer>,
+    pub rgba: Vec<u8>,
+}
+
+/// Renders `elements` offscreen into a buffer of `size`, as an output with `scale` and
+/// `transform` would, and reads back `region` of it.
+pub fn render_to_memory<R, E>(
+    renderer: &mut R,
+    size: Size<i32, Physical>,
+    scale: f64,
+    transform: Transform,
+    elements: &[E],
+    region: Rectangle<i32, Buffer>,
+) -> Result<Pixels, String>
+where
+    R: Renderer + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+    R::TextureId: 'static,
+    E: RenderElement<R>,
+{
+    let buffer_size = size.to_logical(1).to_buffer(1, Transform::Normal);
+    let mut texture = renderer
+        .create_buffer(Fourcc::Abgr8888, buffer_size)
+        .map_err(|err| format!("failed to create texture: {}", err))?;
+    let mut framebuffer = renderer
+        .bind(&mut texture)
+        .map_err(|err| format!("failed to bind texture: {}", err))?;
+
+    let mut damage_tracker = OutputDamageTracker::new(size, scale, transform);
+    damage_tracker
+        .render_output(renderer, &mut framebuffer, 0, elements, Color32F::TRANSPARENT)
+        .map_err(|err| format!("failed to render: {:?}", err))?;
+
+    let mapping = renderer
+        .copy_framebuffer(&framebuffer, region, Fourcc::Abgr8888)
+        .map_err(|err| format!("failed to read back pixels: {}", err))?;
+    read_mapping(renderer, mapping, region.size)
+}
+
+/// Reads back all of `texture`, which is `size` large.
+pub fn read_texture<R>(renderer: &mut R, texture: &R::TextureId, size: Size<i32, Buffer>) -> Result<Pixels, String>
+where
+    R: Renderer + ExportMem,
+{
+    let mapping = renderer
+        .copy_texture(texture, Rectangle::from_size(size), Fourcc::Abgr8888)
+        .map_err(|err| format!("failed to read back pixels: {}", err))?;
+    read_mapping(renderer, mapping, size)
+}
+
+fn read_mapping<R>(renderer: &mut R, mapping: R::TextureMapping, size: Size<i32, Buffer>) -> Result<Pixels, String>
+where
+    R: Renderer + ExportMem,
+{
+    let flipped = mapping.flipped();
+    let mut rgba = renderer
+        .map_texture(&mapping)
+        .map_err(|err| format!("failed to map pixels: {}", err))?
+        .to_vec();
 
-    damage_tracker.render_output(renderer, framebuffer, age, &output_render_elements, clear_color)
+    if flipped {
+        let stride = size.w as usize * 4;
+        rgba = rgba.chunks_exact(stride).rev().flatten().copied().collect();
+    }
+    Ok(Pixels { size, rgba })
 }
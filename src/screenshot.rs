@@ -0,0 +1,220 @@
+//! Screenshots of a single window, its thumbnail or a whole output, saved as PNG.
+//!
+//! Requests are queued on the state and taken by the backend right after it renders, since that
+//! is where the renderer lives. Encoding happens on a worker thread; IPC callers get their reply
+//! once the file is written.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use smithay::{
+    backend::renderer::{
+        Bind, ExportMem, ImportAll, ImportMem, Offscreen, Renderer, Texture,
+        element::{AsRenderElements, surface::WaylandSurfaceRenderElement},
+    },
+    desktop::{Window, space::space_render_elements},
+    output::Output,
+    utils::{Buffer, Physical, Point, Rectangle, Scale, Size, Transform},
+};
+
+use crate::ipc::Response;
+use crate::render::{Pixels, read_texture, render_to_memory};
+use crate::state::NanaimoState;
+use crate::thumbnails::ThumbnailCache;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScreenshotTarget {
+    /// An output by name, or the one under the pointer.
+    Output(Option<String>),
+    /// A window by IPC id, or the focused one.
+    Window(Option<u64>),
+    /// The cached thumbnail of a window by IPC id, or of the focused one.
+    Thumbnail(Option<u64>),
+}
+
+pub struct ScreenshotRequest {
+    pub target: ScreenshotTarget,
+    pub path: PathBuf,
+    /// IPC connection waiting for the result.
+    pub reply: Option<UnixStream>,
+}
+
+/// Where screenshots go unless a path is given: `$XDG_PICTURES_DIR`, else `~/Pictures`.
+fn default_path() -> PathBuf {
+    let dir = std::env::var_os("XDG_PICTURES_DIR")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Pictures")))
+        .unwrap_or_else(std::env::temp_dir);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    dir.join(format!("nanaimo-{}.png", millis))
+}
+
+impl NanaimoState {
+    /// Queues a screenshot for the next frame and returns where it will be saved.
+    pub fn request_screenshot(
+        &mut self,
+        target: ScreenshotTarget,
+        path: Option<PathBuf>,
+        reply: Option<UnixStream>,
+    ) -> Result<PathBuf, String> {
+        if self.is_locked() {
+            return Err("the session is locked".into());
+        }
+        match &target {
+            ScreenshotTarget::Output(Some(name)) if !self.outputs.iter().any(|o| &o.name() == name) => {
+                return Err(format!("no output named {:?}", name));
+            }
+            ScreenshotTarget::Window(Some(id)) | ScreenshotTarget::Thumbnail(Some(id))
+                if self.find_window(*id).is_none() =>
+            {
+                return Err(format!("no window with id {}", id));
+            }
+            ScreenshotTarget::Window(None) | ScreenshotTarget::Thumbnail(None) if self.focused_window().is_none() => {
+                return Err("no window is focused".into());
+            }
+            _ => {}
+        }
+
+        let path = path.unwrap_or_else(default_path);
+        self.pending_screenshots.push(ScreenshotRequest {
+            target,
+            path: path.clone(),
+            reply,
+        });
+        Ok(path)
+    }
+
+    /// Takes every queued screenshot with `renderer` and hands the pixels off to be saved.
+    pub fn take_screenshots<R>(&mut self, renderer: &mut R, thumbnails: &ThumbnailCache<R>)
+    where
+        R: Renderer + ImportAll + ImportMem + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+        R::TextureId: Texture + Clone + Send + 'static,
+    {
+        for request in std::mem::take(&mut self.pending_screenshots) {
+            let capture = match &request.target {
+                ScreenshotTarget::Output(name) => {
+                    let output = match name {
+                        Some(name) => self.outputs.iter().find(|o| &o.name() == name).cloned(),
+                        None => self.output_under_pointer(),
+                    };
+                    match output {
+                        Some(output) => self.capture_output(renderer, &output),
+                        None => Err("output is gone".into()),
+                    }
+                }
+                ScreenshotTarget::Window(id) => {
+                    let window = match id {
+                        Some(id) => self.find_window(*id).map(|(window, _, _)| window),
+                        None => self.focused_window(),
+                    };
+                    match window {
+                        Some(window) => self.capture_window(renderer, &window),
+                        None => Err("window is gone".into()),
+                    }
+                }
+                ScreenshotTarget::Thumbnail(id) => {
+                    let window = match id {
+                        Some(id) => self.find_window(*id).map(|(window, _, _)| window),
+                        None => self.focused_window(),
+                    };
+                    match window.as_ref().and_then(|window| thumbnails.get(window)) {
+                        Some(thumbnail) => read_texture(renderer, &thumbnail.texture, thumbnail.size),
+                        None => Err("window has no thumbnail yet".into()),
+                    }
+                }
+            };
+
+            let ScreenshotRequest { path, reply, .. } = request;
+            std::thread::spawn(move || {
+                let result = capture.and_then(|pixels| save_png(&pixels, &path));
+                let response = match result {
+                    Ok(()) => {
+                        tracing::info!("Saved screenshot to {}", path.display());
+                        Response::Ok(serde_json::json!({ "path": path }))
+                    }
+                    Err(err) => {
+                        tracing::warn!("Screenshot failed: {}", err);
+                        Response::Error(err)
+                    }
+                };
+                if let Some(stream) = reply {
+                    let _ = crate::ipc::server::write_line(&stream, &response);
+                }
+            });
+        }
+    }
+
+    fn output_under_pointer(&self) -> Option<Output> {
+        let location = self.pointer.current_location();
+        self.space
+            .output_under(location)
+            .next()
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+    }
+
+    fn capture_output<R>(&self, renderer: &mut R, output: &Output) -> Result<Pixels, String>
+    where
+        R: Renderer + ImportAll + ImportMem + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+        R::TextureId: Texture + Clone + Send + 'static,
+    {
+        let geometry = self.space.output_geometry(output).ok_or("output is disabled")?;
+        let scale = output.current_scale().fractional_scale();
+        let elements = space_render_elements::<_, Window, _>(renderer, [&self.space], output, scale)
+            .map_err(|err| format!("{:?}", err))?;
+        let size = geometry.size.to_physical_precise_round(scale);
+        render_to_memory(renderer, size, scale, Transform::Normal, &elements, full_region(size))
+    }
+
+    /// The scale of the output `window` is on, or 1 for windows that are not shown.
+    pub fn window_scale(&self, window: &Window) -> f64 {
+        self.space
+            .outputs_for_element(window)
+            .first()
+            .map(|output| output.current_scale().fractional_scale())
+            .unwrap_or(1.0)
+    }
+
+    /// Renders a window with its subsurfaces and popups, cropped to their bounding box.
+    fn capture_window<R>(&self, renderer: &mut R, window: &Window) -> Result<Pixels, String>
+    where
+        R: Renderer + ImportAll + ImportMem + ExportMem + Offscreen<R::TextureId> + Bind<R::TextureId>,
+        R::TextureId: Texture + Clone + Send + 'static,
+    {
+        let scale = self.window_scale(window);
+        let bbox = window.bbox_with_popups();
+        if bbox.is_empty() {
+            return Err("window has no contents".into());
+        }
+        let location: Point<i32, Physical> = (-bbox.loc).to_physical_precise_round(scale);
+        let elements = window.render_elements::<WaylandSurfaceRenderElement<R>>(renderer, location, Scale::from(scale), 1.0);
+        let size = bbox.size.to_physical_precise_round(scale);
+        render_to_memory(renderer, size, scale, Transform::Normal, &elements, full_region(size))
+    }
+}
+
+fn full_region(size: Size<i32, Physical>) -> Rectangle<i32, Buffer> {
+    Rectangle::from_size(size.to_logical(1).to_buffer(1, Transform::Normal))
+}
+
+fn save_png(pixels: &Pixels, path: &PathBuf) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+    }
+    let file = File::create(path).map_err(|err| format!("failed to create {}: {}", path.display(), err))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), pixels.size.w as u32, pixels.size.h as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer.write_image_data(&pixels.rgba).map_err(|err| err.to_string())?;
+    writer.finish().map_err(|err| err.to_string())
+}
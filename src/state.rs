@@ -1,19 +1,27 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
 use smithay::{
     delegate_compositor, delegate_output, delegate_seat,
     delegate_shm, delegate_viewporter, delegate_xdg_shell,
     delegate_layer_shell,
-    desktop::{Space, Window, WindowSurfaceType},
+    desktop::{PopupManager, Space, Window, WindowSurfaceType},
     input::{
         Seat, SeatState,
-        pointer::{PointerHandle, MotionEvent, ButtonEvent, AxisFrame}, 
+        pointer::{PointerHandle, MotionEvent, RelativeMotionEvent, ButtonEvent, AxisFrame}, 
         keyboard::{FilterResult, Keycode},
+        touch::{DownEvent, MotionEvent as TouchMotionEvent, TouchHandle, UpEvent},
     },
     reexports::{
         calloop::LoopHandle,
         wayland_server::{Display, DisplayHandle, backend::{ClientData, ClientId, DisconnectReason}, protocol::wl_surface::WlSurface},
     },
-    utils::{Point, Logical, Serial},
-    backend::input::{KeyState, Axis, AxisSource},
+    utils::{Point, Logical, Physical, Serial, Size},
+    backend::input::{KeyState, Axis, AxisSource, TouchSlot},
+    backend::renderer::damage::OutputDamageTracker,
     wayland::{
         compositor::{CompositorState, CompositorClientState},
         viewporter::ViewporterState,
@@ -30,15 +38,52 @@ use smithay::{
         },
         shm::{ShmState},
         seat::WaylandFocus,
+        foreign_toplevel_list::{ForeignToplevelHandle, ForeignToplevelListState},
+        session_lock::{LockSurface, SessionLockManagerState},
+        idle_notify::IdleNotifierState,
+        idle_inhibit::IdleInhibitManagerState,
+        pointer_constraints::PointerConstraintsState,
+        relative_pointer::RelativePointerManagerState,
+        pointer_gestures::PointerGesturesState,
+        tablet_manager::TabletManagerState,
+        text_input::TextInputManagerState,
+        input_method::InputMethodManagerState,
+        keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitState,
     },
 };
-use smithay::backend::input::{Event, PointerAxisEvent};
+use smithay::backend::input::{Event, PointerAxisEvent, TabletToolDescriptor};
 
+use crate::actions::Action;
 use crate::animations::AnimationManager;
+use crate::config::Config;
+use crate::cursor::CursorTheme;
+use crate::ipc::IpcServer;
+use crate::screenshot::ScreenshotRequest;
+use crate::workspaces::Workspaces;
+use crate::xwayland::XWaylandLauncher;
+use crate::output::OutputConfigs;
+use crate::protocols::output_management::{OutputManagementState, delegate_output_management};
+use crate::protocols::image_copy_capture::{ImageCopyCaptureState, delegate_image_copy_capture};
+use crate::protocols::screencopy::{ScreencopyState, delegate_screencopy};
+use crate::protocols::virtual_keyboard::{VirtualKeyboardManagerState, delegate_virtual_keyboard};
+use crate::protocols::virtual_pointer::{VirtualPointerManagerState, delegate_virtual_pointer};
+use crate::protocols::foreign_toplevel::{
+    ForeignToplevelManagementState, ToplevelInfo, delegate_foreign_toplevel_management,
+};
+use crate::capture::PendingCapture;
+use crate::gestures::Swipe;
+use crate::handlers::pointer_constraints::ActiveConstraint;
+use crate::handlers::session_lock::LockState;
+use crate::handlers::virtual_input::InputClientAllowlist;
+use crate::handlers::shortcuts_inhibit::{is_inhibit_escape, toggle_shortcuts_inhibitor};
+use crate::tablet::TabletTool;
+use crate::window::is_override_redirect;
 
 #[derive(Default)]
 pub struct ClientState {
     pub compositor_state: CompositorClientState,
+    /// The executable the client ran when it connected, with symlinks resolved.
+    pub executable: OnceLock<PathBuf>,
 }
 
 impl ClientData for ClientState {
@@ -52,6 +97,7 @@ impl ClientData for ClientState {
 
 pub struct NanaimoState {
     pub space: Space<Window>,
+    pub workspaces: Workspaces,
     pub compositor_state: CompositorState,
     pub viewporter_state: ViewporterState,
     pub xdg_activation_state: XdgActivationState,
@@ -65,18 +111,96 @@ pub struct NanaimoState {
     pub data_device_state: DataDeviceState,
     pub primary_selection_state: PrimarySelectionState,
     pub cursor_status: smithay::input::pointer::CursorImageStatus,
+    pub cursor_theme: CursorTheme,
+    pub start_time: std::time::Instant,
     pub seat: Seat<NanaimoState>,
     pub pointer: PointerHandle<NanaimoState>,
+    pub touch: TouchHandle<NanaimoState>,
+    /// The surface each finger went down on. It gets all of that finger's events.
+    pub touch_focus: HashMap<TouchSlot, (WlSurface, Point<f64, Logical>)>,
     pub animation_manager: AnimationManager,
+    pub output_configs: OutputConfigs,
+    pub outputs: Vec<smithay::output::Output>,
+    pub output_management_state: OutputManagementState,
+    pub screencopy_state: ScreencopyState,
+    pub image_copy_capture_state: ImageCopyCaptureState,
+    pub pending_captures: Vec<PendingCapture>,
+    /// Damage trackers of capture sessions, per session id and output name.
+    pub capture_damage: HashMap<(u64, String), OutputDamageTracker>,
+    /// Damage trackers of window capture sessions, per session id, with the size and scale they
+    /// were created for.
+    pub window_capture_damage: HashMap<u64, ((Size<i32, Physical>, f64), OutputDamageTracker)>,
+    /// When the thumbnail last sent to each thumbnail capture session was rendered.
+    pub capture_thumbnails: HashMap<u64, std::time::Instant>,
+    pub foreign_toplevel_list_state: ForeignToplevelListState,
+    pub foreign_toplevel_management_state: ForeignToplevelManagementState,
+    /// Each window's ext-foreign-toplevel-list handle, with what was last sent on it.
+    pub foreign_toplevels: HashMap<Window, (ForeignToplevelHandle, ToplevelInfo)>,
+    pub session_lock_state: SessionLockManagerState,
+    pub lock_state: LockState,
+    pub lock_surfaces: HashMap<smithay::output::Output, LockSurface>,
+    pub idle_notifier_state: IdleNotifierState<NanaimoState>,
+    pub idle_inhibit_manager_state: IdleInhibitManagerState,
+    /// Surfaces with an idle inhibitor. They only inhibit while visible.
+    pub idle_inhibitors: Vec<WlSurface>,
+    pub idle_inhibited: bool,
+    pub last_activity: std::time::Instant,
+    pub blanked_outputs: HashSet<smithay::output::Output>,
+    pub pointer_constraints_state: PointerConstraintsState,
+    pub relative_pointer_manager_state: RelativePointerManagerState,
+    /// Where the pointer goes once the surface's pointer lock ends, in global coordinates.
+    pub pointer_lock_hint: Option<(WlSurface, Point<f64, Logical>)>,
+    pub pointer_gestures_state: PointerGesturesState,
+    /// A swipe in progress that is bound to an action rather than sent to clients.
+    pub swipe: Option<Swipe>,
+    pub tablet_manager_state: TabletManagerState,
+    /// Tablet tools in proximity, each with its own cursor.
+    pub tablet_tools: HashMap<TabletToolDescriptor, TabletTool>,
+    pub text_input_manager_state: TextInputManagerState,
+    pub input_method_manager_state: InputMethodManagerState,
+    /// `input.virtual_input_clients`, shared with the input method global's filter.
+    pub input_client_allowlist: InputClientAllowlist,
+    pub popups: PopupManager,
+    pub virtual_keyboard_manager_state: VirtualKeyboardManagerState,
+    pub virtual_pointer_manager_state: VirtualPointerManagerState,
+    pub keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
+    /// Windows in the order they last had focus, most recent last.
+    pub focus_history: Vec<Window>,
+    /// Ids of windows that asked to be activated without a valid token.
+    pub urgent_windows: HashSet<u64>,
+    /// A hover-focused window waiting out the raise delay.
+    pub pending_raise: Option<(Window, std::time::Instant)>,
+    /// Index into the configured keyboard layouts.
+    pub keyboard_layout: usize,
+    /// Layouts windows were last typed in, by window id, when layouts are kept per window.
+    pub window_layouts: HashMap<u64, usize>,
+    /// The keymap of the virtual keyboard that typed last, while it is on the seat instead of the
+    /// configured layout.
+    pub virtual_keymap: Option<String>,
+    pub config: Config,
+    pub config_reload_pending: bool,
+    pub config_watcher: Option<notify::RecommendedWatcher>,
+    /// Keys whose press triggered a keybinding, so their release is not sent to clients either.
+    pub suppressed_keys: Vec<Keycode>,
+    pub running: bool,
+    pub ipc: Option<IpcServer>,
+    pub pending_screenshots: Vec<ScreenshotRequest>,
+    pub xwayland: Option<XWaylandLauncher>,
+    pub xwm: Option<smithay::xwayland::X11Wm>,
+    pub xwayland_shell_state: smithay::wayland::xwayland_shell::XWaylandShellState,
+    
+    pub loop_handle: LoopHandle<'static, NanaimoState>,
     
     pub display_handle: DisplayHandle,
     pub serial_counter: smithay::utils::SerialCounter,
 }
 
 impl NanaimoState {
-    pub fn new(display: &Display<NanaimoState>, _loop_handle: LoopHandle<'static, NanaimoState>) -> Self {
+    pub fn new(display: &Display<NanaimoState>, loop_handle: LoopHandle<'static, NanaimoState>) -> Self {
         tracing::info!("Initializing NanaimoState...");
         let dh = display.handle();
+        let config = Config::load_or_default();
+        let config_watcher = crate::config::watch_config(&loop_handle);
         
         let compositor_state = CompositorState::new::<Self>(&dh);
         let viewporter_state = ViewporterState::new::<Self>(&dh);
@@ -88,21 +212,51 @@ impl NanaimoState {
         let layer_shell_state = WlrLayerShellState::new::<Self>(&dh);
         let shm_state = ShmState::new::<Self>(&dh, vec![]);
         let _output_manager_state = smithay::wayland::output::OutputManagerState::new_with_xdg_output::<Self>(&dh);
+        let output_management_state = OutputManagementState::new::<Self>(&dh);
+        let screencopy_state = ScreencopyState::new::<Self>(&dh);
+        let image_copy_capture_state = ImageCopyCaptureState::new::<Self>(&dh);
+        let foreign_toplevel_list_state = ForeignToplevelListState::new::<Self>(&dh);
+        let foreign_toplevel_management_state = ForeignToplevelManagementState::new::<Self>(&dh);
+        let session_lock_state = SessionLockManagerState::new::<Self, _>(&dh, |_| true);
+        let idle_notifier_state = IdleNotifierState::new(&dh, loop_handle.clone());
+        let idle_inhibit_manager_state = IdleInhibitManagerState::new::<Self>(&dh);
+        let pointer_constraints_state = PointerConstraintsState::new::<Self>(&dh);
+        let relative_pointer_manager_state = RelativePointerManagerState::new::<Self>(&dh);
+        let pointer_gestures_state = PointerGesturesState::new::<Self>(&dh);
+        let tablet_manager_state = TabletManagerState::new::<Self>(&dh);
+        let text_input_manager_state = TextInputManagerState::new::<Self>(&dh);
+        // Input methods see every key typed into text fields, so only allowlisted ones get the global.
+        let input_client_allowlist = InputClientAllowlist::new(&config.virtual_input_clients);
+        let input_method_manager_state = {
+            let allowlist = input_client_allowlist.clone();
+            InputMethodManagerState::new::<Self, _>(&dh, move |client| allowlist.allows(client))
+        };
+        let virtual_keyboard_manager_state = VirtualKeyboardManagerState::new::<Self>(&dh);
+        let virtual_pointer_manager_state = VirtualPointerManagerState::new::<Self>(&dh);
+        let keyboard_shortcuts_inhibit_state = KeyboardShortcutsInhibitState::new::<Self>(&dh);
+        let xwayland_shell_state = smithay::wayland::xwayland_shell::XWaylandShellState::new::<Self>(&dh);
         let mut seat_state = SeatState::new();
         let mut seat = seat_state.new_wl_seat(&dh, "nanaimo");
         
-        let xkb_config = smithay::input::keyboard::XkbConfig {
-            layout: "us",
-            ..Default::default()
-        };
-        seat.add_keyboard(xkb_config, 200, 25).expect("Failed to add keyboard");
+        let keyboard_config = &config.keyboard;
+        seat.add_keyboard(keyboard_config.xkb_config(0), keyboard_config.repeat_delay, keyboard_config.repeat_rate)
+            .or_else(|err| {
+                tracing::error!("Invalid keyboard layout {:?}: {:?}, falling back to the default", keyboard_config.layouts[0], err);
+                seat.add_keyboard(Default::default(), keyboard_config.repeat_delay, keyboard_config.repeat_rate)
+            })
+            .expect("Failed to add keyboard");
         let pointer = seat.add_pointer();
+        let touch = seat.add_touch();
         
         let data_device_state = DataDeviceState::new::<Self>(&dh);
         let primary_selection_state = PrimarySelectionState::new::<Self>(&dh);
 
+        let mut animation_manager = AnimationManager::new();
+        animation_manager.set_durations(&config.animations);
+
         Self {
             space: Space::default(),
+            workspaces: Workspaces::default(),
             compositor_state,
             viewporter_state,
             xdg_activation_state,
@@ -116,15 +270,74 @@ impl NanaimoState {
             data_device_state,
             primary_selection_state,
             cursor_status: smithay::input::pointer::CursorImageStatus::default_named(),
+            cursor_theme: CursorTheme::load(),
+            start_time: std::time::Instant::now(),
             seat,
             pointer,
-            animation_manager: AnimationManager::new(),
+            touch,
+            touch_focus: HashMap::new(),
+            animation_manager,
+            output_configs: config.outputs.clone(),
+            outputs: Vec::new(),
+            output_management_state,
+            screencopy_state,
+            image_copy_capture_state,
+            pending_captures: Vec::new(),
+            capture_damage: HashMap::new(),
+            window_capture_damage: HashMap::new(),
+            capture_thumbnails: HashMap::new(),
+            foreign_toplevel_list_state,
+            foreign_toplevel_management_state,
+            foreign_toplevels: HashMap::new(),
+            session_lock_state,
+            lock_state: LockState::Unlocked,
+            lock_surfaces: HashMap::new(),
+            idle_notifier_state,
+            idle_inhibit_manager_state,
+            idle_inhibitors: Vec::new(),
+            idle_inhibited: false,
+            last_activity: std::time::Instant::now(),
+            blanked_outputs: HashSet::new(),
+            pointer_constraints_state,
+            relative_pointer_manager_state,
+            pointer_lock_hint: None,
+            pointer_gestures_state,
+            swipe: None,
+            tablet_manager_state,
+            tablet_tools: HashMap::new(),
+            text_input_manager_state,
+            input_method_manager_state,
+            input_client_allowlist,
+            popups: PopupManager::default(),
+            virtual_keyboard_manager_state,
+            virtual_pointer_manager_state,
+            keyboard_shortcuts_inhibit_state,
+            focus_history: Vec::new(),
+            pending_raise: None,
+            urgent_windows: HashSet::new(),
+            keyboard_layout: 0,
+            window_layouts: HashMap::new(),
+            virtual_keymap: None,
+            config,
+            config_reload_pending: false,
+            config_watcher,
+            suppressed_keys: Vec::new(),
+            running: true,
+            ipc: None,
+            pending_screenshots: Vec::new(),
+            xwayland: None,
+            xwm: None,
+            xwayland_shell_state,
+            loop_handle,
             display_handle: dh,
             serial_counter: smithay::utils::SerialCounter::default(),
         }
     }
     
     pub fn surface_under(&self, pos: Point<f64, Logical>) -> Option<(WlSurface, Point<f64, Logical>)> {
+        if self.is_locked() {
+            return self.lock_surface_under(pos);
+        }
         self.space.element_under(pos).and_then(|(window, loc)| {
             window.surface_under(pos - loc.to_f64(), WindowSurfaceType::ALL)
                 .map(|(surface, surf_loc)| (surface, surf_loc.to_f64() + loc.to_f64()))
@@ -132,14 +345,80 @@ impl NanaimoState {
     }
     
     pub fn on_pointer_move_absolute(&mut self, pos: Point<f64, Logical>, time: u32) {
+        let delta = pos - self.pointer.current_location();
+        self.on_pointer_motion(pos, delta, delta, u64::from(time) * 1000);
+    }
+
+    /// Relative motion as reported by mice. The pointer stays within the outputs.
+    pub fn on_pointer_move_relative(
+        &mut self,
+        delta: Point<f64, Logical>,
+        delta_unaccel: Point<f64, Logical>,
+        utime: u64,
+    ) {
+        let current = self.pointer.current_location();
+        let mut pos = current + delta;
+        if self.space.output_under(pos).next().is_none() {
+            let output = self.space.output_under(current).next();
+            if let Some(geometry) = output.and_then(|output| self.space.output_geometry(output)) {
+                let max = geometry.loc.to_f64() + geometry.size.to_f64().to_point() - Point::from((1.0, 1.0));
+                pos.x = pos.x.clamp(geometry.loc.x as f64, max.x);
+                pos.y = pos.y.clamp(geometry.loc.y as f64, max.y);
+            }
+        }
+        self.on_pointer_motion(pos, delta, delta_unaccel, utime);
+    }
+
+    /// Moves the pointer to `pos`, unless a pointer constraint holds it back, and sends the
+    /// relative motion to relative-pointer clients either way.
+    fn on_pointer_motion(
+        &mut self,
+        pos: Point<f64, Logical>,
+        delta: Point<f64, Logical>,
+        delta_unaccel: Point<f64, Logical>,
+        utime: u64,
+    ) {
+        self.notify_activity();
+        self.apply_pointer_lock_hint();
         let serial = self.serial_counter.next_serial();
+        let current = self.pointer.current_location();
+        let pointer = self.pointer.clone();
+
+        let constraint = self.active_pointer_constraint();
+        pointer.relative_motion(
+            self,
+            self.surface_under(current),
+            &RelativeMotionEvent {
+                delta,
+                delta_unaccel,
+                utime,
+            },
+        );
+        let pos = match constraint {
+            Some(ActiveConstraint::Locked) => None,
+            // Slide along the edge of the region where only one axis is blocked.
+            Some(ActiveConstraint::Confined(surface, origin, region)) => {
+                let candidates: [Point<f64, Logical>; 3] = [pos, (pos.x, current.y).into(), (current.x, pos.y).into()];
+                candidates.into_iter().find(|candidate| {
+                    let inside = region
+                        .as_ref()
+                        .is_none_or(|region| region.contains((*candidate - origin).to_i32_floor()));
+                    inside && self.surface_under(*candidate).is_some_and(|(s, _)| s == surface)
+                })
+            }
+            None => Some(pos),
+        };
+        let Some(pos) = pos else {
+            pointer.frame(self);
+            return;
+        };
+
+        let time = (utime / 1000) as u32;
         let under = self.surface_under(pos);
-        
         if let Some((ref surface, _)) = under {
              tracing::trace!("Pointer over surface: {:?}", surface);
         }
 
-        let pointer = self.pointer.clone();
         pointer.motion(
             self,
             under,
@@ -150,14 +429,17 @@ impl NanaimoState {
             },
         );
         pointer.frame(self);
+        self.maybe_activate_pointer_constraint();
+        self.focus_follows_pointer(pos);
     }
     
     pub fn on_pointer_button(&mut self, button: u32, state: smithay::backend::input::ButtonState, time: u32) {
+        self.notify_activity();
         let serial = self.serial_counter.next_serial();
         tracing::trace!("Pointer button: {:?} state: {:?} at {:?}", button, state, self.pointer.current_location());
         
         if state == smithay::backend::input::ButtonState::Pressed {
-            self.update_keyboard_focus(serial);
+            self.update_keyboard_focus(self.pointer.current_location(), serial);
         }
         
         let pointer = self.pointer.clone();
@@ -174,6 +456,7 @@ impl NanaimoState {
     }
 
     pub fn on_pointer_axis<B: smithay::backend::input::InputBackend>(&mut self, event: B::PointerAxisEvent) {
+        self.notify_activity();
         let mut frame = AxisFrame::new(event.time_msec()).source(event.source());
         
         let process_axis = |axis: Axis, frame: &mut AxisFrame| {
@@ -206,69 +489,146 @@ impl NanaimoState {
 
         process_axis(Axis::Vertical, &mut frame);
         process_axis(Axis::Horizontal, &mut frame);
-        
+        self.on_pointer_axis_frame(frame);
+    }
+
+    /// Sends a finished axis frame to the client under the pointer.
+    pub fn on_pointer_axis_frame(&mut self, frame: AxisFrame) {
         let pointer = self.pointer.clone();
         pointer.axis(self, frame);
         pointer.frame(self);
     }
     
-    pub fn on_keyboard_key(&mut self, keycode: Keycode, state: KeyState, time: u32) {
+    pub fn on_keyboard_key(&mut self, keycode: Keycode, key_state: KeyState, time: u32) {
+        self.notify_activity();
         let serial = self.serial_counter.next_serial();
         let keyboard = self.seat.get_keyboard().unwrap();
         let focus = keyboard.current_focus();
-        tracing::trace!("Keyboard key: {:?} state: {:?} current_focus: {:?}", keycode, state, focus.as_ref().map(|f| f.wl_surface()));
+        tracing::trace!("Keyboard key: {:?} state: {:?} current_focus: {:?}", keycode, key_state, focus.as_ref().map(|f| f.wl_surface()));
         
-        keyboard.input::<(), _>(
+        let action = keyboard.input::<Option<Action>, _>(
             self,
             keycode,
-            state,
+            key_state,
             serial,
             time,
-            |_, _, _| {
+            |state, modifiers, handle| {
+                let keysym = handle.raw_latin_sym_or_raw_current_sym();
+                // Nothing but the lock screen may react to keys while locked.
+                if key_state == KeyState::Pressed && !state.is_locked() {
+                    let escape = keysym.is_some_and(|keysym| is_inhibit_escape(modifiers, keysym));
+                    let inhibitor = focus
+                        .as_ref()
+                        .filter(|_| escape)
+                        .and_then(|surface| state.seat.keyboard_shortcuts_inhibitor_for_surface(surface));
+                    if let Some(inhibitor) = inhibitor {
+                        toggle_shortcuts_inhibitor(&inhibitor);
+                        state.suppressed_keys.push(keycode);
+                        return FilterResult::Intercept(None);
+                    }
+                    // Remote desktops and VM viewers holding an inhibitor get every other key.
+                    if focus.as_ref().is_some_and(|surface| state.shortcuts_inhibited(surface)) {
+                        return FilterResult::Forward;
+                    }
+                    let binding = keysym.and_then(|keysym| {
+                        state.config.keybindings.iter().find(|b| b.matches(modifiers, keysym))
+                    });
+                    if let Some(binding) = binding {
+                        state.suppressed_keys.push(keycode);
+                        return FilterResult::Intercept(Some(binding.action.clone()));
+                    }
+                } else if let Some(pos) = state.suppressed_keys.iter().position(|k| *k == keycode) {
+                    state.suppressed_keys.remove(pos);
+                    return FilterResult::Intercept(None);
+                }
                 tracing::trace!("Forwarding key event to client");
                 FilterResult::Forward
             },
         );
+
+        if let Some(action) = action.flatten() {
+            self.run_action(action);
+        }
     }
     
-    fn update_keyboard_focus(&mut self, serial: Serial) {
-        let pos = self.pointer.current_location();
-        let under = self.space.element_under(pos).map(|(w, p)| (w.clone(), p));
-        tracing::trace!("Updating keyboard focus, pointer at {:?}, found window: {:?}", pos, under.as_ref().map(|(w, _)| w));
+    pub fn on_touch_down(&mut self, slot: TouchSlot, pos: Point<f64, Logical>, time: u32) {
+        self.notify_activity();
+        let serial = self.serial_counter.next_serial();
+        self.update_keyboard_focus(pos, serial);
 
-        if let Some((window, _)) = under {
-            self.space.raise_element(&window, true);
-            let keyboard = self.seat.get_keyboard().unwrap();
-            
-            if keyboard.current_focus().as_ref().map(|f| f.wl_surface().as_deref() == window.wl_surface().as_deref()).unwrap_or(false) {
-                tracing::trace!("Window already focused");
-                return;
-            }
+        let under = self.surface_under(pos);
+        match &under {
+            Some(focus) => self.touch_focus.insert(slot, focus.clone()),
+            None => self.touch_focus.remove(&slot),
+        };
+        let touch = self.touch.clone();
+        touch.down(
+            self,
+            under,
+            &DownEvent {
+                slot,
+                location: pos,
+                serial,
+                time,
+            },
+        );
+    }
 
-            // Deactivate other windows
-            for other in self.space.elements() {
-                if other != &window {
-                    if let Some(toplevel) = other.toplevel() {
-                        toplevel.with_pending_state(|state| {
-                            state.states.unset(smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::State::Activated);
-                        });
-                        toplevel.send_configure();
-                    }
-                }
-            }
+    pub fn on_touch_motion(&mut self, slot: TouchSlot, pos: Point<f64, Logical>, time: u32) {
+        self.notify_activity();
+        let focus = self.touch_focus.get(&slot).cloned();
+        let touch = self.touch.clone();
+        touch.motion(
+            self,
+            focus,
+            &TouchMotionEvent {
+                slot,
+                location: pos,
+                time,
+            },
+        );
+    }
 
-            if let Some(toplevel) = window.toplevel() {
-                toplevel.with_pending_state(|state| {
-                    state.states.set(smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::State::Activated);
-                });
-                toplevel.send_configure();
-            }
+    pub fn on_touch_up(&mut self, slot: TouchSlot, time: u32) {
+        self.notify_activity();
+        let serial = self.serial_counter.next_serial();
+        self.touch_focus.remove(&slot);
+        let touch = self.touch.clone();
+        touch.up(self, &UpEvent { slot, serial, time });
+    }
 
-            if let Some(surface) = window.wl_surface() {
-                tracing::info!("Setting keyboard focus to window: {:?}", window);
-                keyboard.set_focus(self, Some(surface.into_owned()), serial);
-            }
+    pub fn on_touch_frame(&mut self) {
+        let touch = self.touch.clone();
+        touch.frame(self);
+    }
+
+    pub fn on_touch_cancel(&mut self) {
+        self.touch_focus.clear();
+        let touch = self.touch.clone();
+        touch.cancel(self);
+    }
+
+    /// Focuses the window at `pos`, where a button was pressed or a finger went down.
+    fn update_keyboard_focus(&mut self, pos: Point<f64, Logical>, serial: Serial) {
+        if self.is_locked() {
+            self.focus_lock_surface();
+            return;
+        }
+        let under = self.space.element_under(pos).map(|(w, p)| (w.clone(), p));
+        tracing::trace!("Updating keyboard focus, pointer at {:?}, found window: {:?}", pos, under.as_ref().map(|(w, _)| w));
+
+        if let Some((window, _)) = under.filter(|(w, _)| !is_override_redirect(w)) {
+            self.focus_window(&window, serial);
+        }
+    }
+
+    /// Raises `window`, marks it activated and gives it keyboard focus.
+    pub fn focus_window(&mut self, window: &Window, serial: Serial) {
+        if self.is_locked() || is_override_redirect(window) {
+            return;
         }
+        self.raise_window(window);
+        self.set_focused_window(window, serial);
     }
 }
 
@@ -286,3 +646,21 @@ smithay::delegate_xdg_decoration!(NanaimoState);
 smithay::delegate_data_device!(NanaimoState);
 smithay::delegate_primary_selection!(NanaimoState);
 smithay::delegate_cursor_shape!(NanaimoState);
+smithay::delegate_xwayland_shell!(NanaimoState);
+delegate_output_management!(NanaimoState);
+delegate_screencopy!(NanaimoState);
+delegate_image_copy_capture!(NanaimoState);
+smithay::delegate_foreign_toplevel_list!(NanaimoState);
+delegate_foreign_toplevel_management!(NanaimoState);
+smithay::delegate_session_lock!(NanaimoState);
+smithay::delegate_idle_notify!(NanaimoState);
+smithay::delegate_idle_inhibit!(NanaimoState);
+smithay::delegate_pointer_constraints!(NanaimoState);
+smithay::delegate_relative_pointer!(NanaimoState);
+smithay::delegate_pointer_gestures!(NanaimoState);
+smithay::delegate_tablet_manager!(NanaimoState);
+smithay::delegate_text_input_manager!(NanaimoState);
+smithay::delegate_input_method_manager!(NanaimoState);
+smithay::delegate_keyboard_shortcuts_inhibit!(NanaimoState);
+delegate_virtual_keyboard!(NanaimoState);
+delegate_virtual_pointer!(NanaimoState);
@@ -0,0 +1,200 @@
+//! Drawing tablets: tools are routed to the surface under them through tablet-v2 and get their
+//! own cursor, drawn like the pointer's. Pad buttons, rings and strips go to the surface with
+//! keyboard focus, which is where the shortcuts they are usually bound to apply.
+
+use smithay::{
+    backend::{
+        input::{
+            Device, DeviceCapability, Event, InputBackend, ProximityState, TabletPadButtonEvent, TabletPadRingEvent,
+            TabletPadStripEvent, TabletToolAxisEvent, TabletToolButtonEvent, TabletToolDescriptor, TabletToolEvent,
+            TabletToolProximityEvent, TabletToolTipEvent, TabletToolTipState,
+        },
+        renderer::{ImportAll, ImportMem, Renderer},
+    },
+    input::pointer::CursorImageStatus,
+    output::Output,
+    utils::{Logical, Point},
+    wayland::tablet_manager::{TabletDescriptor, TabletPadDescriptor, TabletPadHandle, TabletSeatTrait},
+};
+
+use crate::render::{CustomRenderElements, cursor_elements};
+use crate::state::NanaimoState;
+
+/// A tool in proximity of its tablet.
+pub struct TabletTool {
+    pub location: Point<f64, Logical>,
+    pub cursor: CursorImageStatus,
+}
+
+impl NanaimoState {
+    pub fn on_tablet_tool_proximity<B: InputBackend>(
+        &mut self,
+        event: B::TabletToolProximityEvent,
+        pos: Point<f64, Logical>,
+    ) {
+        self.notify_activity();
+        let tablet_seat = self.seat.tablet_seat();
+        let tablet = tablet_seat.add_tablet::<Self>(&self.display_handle, &TabletDescriptor::from(&event.device()));
+        let tool = tablet_seat.add_tool::<Self>(&self.display_handle, &event.tool());
+
+        match event.state() {
+            ProximityState::In => {
+                self.tablet_tools.insert(
+                    event.tool(),
+                    TabletTool {
+                        location: pos,
+                        cursor: CursorImageStatus::default_named(),
+                    },
+                );
+                if let Some(under) = self.surface_under(pos) {
+                    let serial = self.serial_counter.next_serial();
+                    tool.proximity_in(pos, under, &tablet, serial, event.time_msec());
+                }
+            }
+            ProximityState::Out => {
+                self.tablet_tools.remove(&event.tool());
+                tool.proximity_out(event.time_msec());
+            }
+        }
+    }
+
+    pub fn on_tablet_tool_axis<B: InputBackend>(&mut self, event: B::TabletToolAxisEvent, pos: Point<f64, Logical>) {
+        self.notify_activity();
+        if let Some(state) = self.tablet_tools.get_mut(&event.tool()) {
+            state.location = pos;
+        }
+        let tablet_seat = self.seat.tablet_seat();
+        let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
+        let Some((tablet, tool)) = tablet.zip(tablet_seat.get_tool(&event.tool())) else {
+            return;
+        };
+
+        if event.pressure_has_changed() {
+            tool.pressure(event.pressure());
+        }
+        if event.distance_has_changed() {
+            tool.distance(event.distance());
+        }
+        if event.tilt_has_changed() {
+            tool.tilt(event.tilt());
+        }
+        if event.slider_has_changed() {
+            tool.slider_position(event.slider_position());
+        }
+        if event.rotation_has_changed() {
+            tool.rotation(event.rotation());
+        }
+        if event.wheel_has_changed() {
+            tool.wheel(event.wheel_delta(), event.wheel_delta_discrete());
+        }
+        let under = self.surface_under(pos);
+        let serial = self.serial_counter.next_serial();
+        tool.motion(pos, under, &tablet, serial, event.time_msec());
+    }
+
+    pub fn on_tablet_tool_tip<B: InputBackend>(&mut self, event: B::TabletToolTipEvent) {
+        self.notify_activity();
+        let Some(tool) = self.seat.tablet_seat().get_tool(&event.tool()) else {
+            return;
+        };
+        match event.tip_state() {
+            TabletToolTipState::Down => {
+                let serial = self.serial_counter.next_serial();
+                tool.tip_down(serial, event.time_msec());
+                // Touching the tablet focuses like a click does.
+                if let Some(location) = self.tablet_tools.get(&event.tool()).map(|state| state.location) {
+                    self.update_keyboard_focus(location, serial);
+                }
+            }
+            TabletToolTipState::Up => tool.tip_up(event.time_msec()),
+        }
+    }
+
+    pub fn on_tablet_tool_button<B: InputBackend>(&mut self, event: B::TabletToolButtonEvent) {
+        self.notify_activity();
+        if let Some(tool) = self.seat.tablet_seat().get_tool(&event.tool()) {
+            let serial = self.serial_counter.next_serial();
+            tool.button(event.button(), event.button_state(), serial, event.time_msec());
+        }
+    }
+
+    /// Announces the pad of a newly plugged in tablet, along with the tablet it belongs to.
+    pub fn on_tablet_pad_added<D: Device>(&mut self, device: &D) {
+        if !device.has_capability(DeviceCapability::TabletPad) {
+            return;
+        }
+        let tablet_seat = self.seat.tablet_seat();
+        tablet_seat.add_tablet::<Self>(&self.display_handle, &TabletDescriptor::from(device));
+        tablet_seat.add_pad::<Self>(&self.display_handle, &TabletPadDescriptor::from(device));
+    }
+
+    pub fn on_tablet_pad_removed<D: Device>(&mut self, device: &D) {
+        if device.has_capability(DeviceCapability::TabletPad) {
+            self.seat.tablet_seat().remove_pad(&TabletPadDescriptor::from(device));
+        }
+    }
+
+    pub fn on_tablet_pad_button<B: InputBackend>(&mut self, event: B::TabletPadButtonEvent) {
+        self.notify_activity();
+        if let Some(pad) = self.focused_tablet_pad(&event.device()) {
+            pad.button(event.button(), event.button_state(), event.time_msec());
+        }
+    }
+
+    pub fn on_tablet_pad_ring<B: InputBackend>(&mut self, event: B::TabletPadRingEvent) {
+        self.notify_activity();
+        if let Some(pad) = self.focused_tablet_pad(&event.device()) {
+            pad.ring(event.number(), event.position(), event.source(), event.time_msec());
+        }
+    }
+
+    pub fn on_tablet_pad_strip<B: InputBackend>(&mut self, event: B::TabletPadStripEvent) {
+        self.notify_activity();
+        if let Some(pad) = self.focused_tablet_pad(&event.device()) {
+            pad.strip(event.number(), event.position(), event.source(), event.time_msec());
+        }
+    }
+
+    /// The pad of `device`, moved over to the surface with keyboard focus if that changed since
+    /// its last event.
+    fn focused_tablet_pad<D: Device>(&mut self, device: &D) -> Option<TabletPadHandle> {
+        let tablet_seat = self.seat.tablet_seat();
+        let pad = tablet_seat.get_pad(&TabletPadDescriptor::from(device))?;
+        let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(device))?;
+        let focus = self.seat.get_keyboard().and_then(|keyboard| keyboard.current_focus());
+        let serial = self.serial_counter.next_serial();
+        pad.set_focus(focus.as_ref(), &tablet, serial);
+        Some(pad)
+    }
+
+    /// Sets the cursor a client asked for while `tool` is over its surface.
+    pub fn set_tablet_tool_cursor(&mut self, tool: &TabletToolDescriptor, image: CursorImageStatus) {
+        if let Some(state) = self.tablet_tools.get_mut(tool) {
+            state.cursor = image;
+        }
+    }
+
+    /// Cursors of the tools in proximity, for drawing on `output` next to the pointer's.
+    pub fn tablet_cursor_elements<R>(&mut self, renderer: &mut R, output: &Output) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        R::TextureId: Clone + Send + 'static,
+    {
+        let output_location = output.current_location();
+        let time = self.start_time.elapsed();
+        self.tablet_tools
+            .values()
+            .flat_map(|tool| {
+                cursor_elements(
+                    renderer,
+                    output,
+                    output_location,
+                    tool.location,
+                    &tool.cursor,
+                    &mut self.cursor_theme,
+                    time,
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,161 @@
+//! Small copies of every window, for the shell's sidebar and window switchers.
+//!
+//! A thumbnail is only re-rendered after its window committed new contents, no more than once
+//! per [`MIN_INTERVAL`] and only a few per frame, so idle windows cost nothing and a video
+//! playing in one can't eat into the frame budget. Windows on other workspaces keep the
+//! thumbnail they had when they were hidden.
+//!
+//! Shells get at thumbnails through `nanaimo-thumbnail-v1` capture sources or the `thumbnail` IPC
+//! request.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            Bind, Color32F, ImportAll, Offscreen, Renderer,
+            damage::OutputDamageTracker,
+            element::{AsRenderElements, Element, Id, surface::WaylandSurfaceRenderElement},
+            utils::CommitCounter,
+        },
+    },
+    desktop::Window,
+    utils::{Buffer, Logical, Physical, Point, Scale, Size, Transform},
+};
+
+/// Longest edge of a thumbnail, in pixels.
+const MAX_SIZE: i32 = 256;
+const MIN_INTERVAL: Duration = Duration::from_millis(500);
+/// How many thumbnails are re-rendered per frame at most.
+const PER_FRAME: usize = 2;
+
+pub struct Thumbnail<T> {
+    pub texture: T,
+    pub size: Size<i32, Buffer>,
+    /// What the window's surfaces had committed when this was rendered.
+    commits: Vec<(Id, CommitCounter)>,
+    updated: Instant,
+}
+
+pub struct ThumbnailCache<R: Renderer> {
+    thumbnails: HashMap<Window, Thumbnail<R::TextureId>>,
+}
+
+impl<R: Renderer> Default for ThumbnailCache<R> {
+    fn default() -> Self {
+        Self {
+            thumbnails: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Thumbnail<T> {
+    /// When the thumbnail was last rendered, to tell whether it changed since it was last sent.
+    pub fn updated(&self) -> Instant {
+        self.updated
+    }
+}
+
+/// The scale a window of `size` is drawn at so its longest edge fits [`MAX_SIZE`]. Never upscales.
+fn thumbnail_scale(size: Size<i32, Logical>) -> f64 {
+    (MAX_SIZE as f64 / size.w.max(size.h) as f64).min(1.0)
+}
+
+/// The size the thumbnail of `window` has once it is up to date.
+pub fn thumbnail_size(window: &Window) -> Option<Size<i32, Buffer>> {
+    let bbox = window.bbox_with_popups();
+    if bbox.is_empty() {
+        return None;
+    }
+    let size: Size<i32, Physical> = bbox.size.to_physical_precise_round(thumbnail_scale(bbox.size));
+    Some(size.to_logical(1).to_buffer(1, Transform::Normal))
+}
+
+impl<R> ThumbnailCache<R>
+where
+    R: Renderer + ImportAll + Offscreen<R::TextureId> + Bind<R::TextureId>,
+    R::TextureId: Clone + 'static,
+{
+    pub fn get(&self, window: &Window) -> Option<&Thumbnail<R::TextureId>> {
+        self.thumbnails.get(window)
+    }
+
+    /// Forgets windows that are gone and re-renders outdated thumbnails, missing ones first,
+    /// then the ones that have been outdated the longest.
+    pub fn refresh(&mut self, renderer: &mut R, windows: &[Window]) {
+        self.thumbnails.retain(|window, _| windows.contains(window));
+
+        let now = Instant::now();
+        let mut outdated = Vec::new();
+        for window in windows {
+            let last_update = self.thumbnails.get(window).map(|thumbnail| thumbnail.updated);
+            if last_update.is_some_and(|updated| now - updated < MIN_INTERVAL) {
+                continue;
+            }
+            let bbox = window.bbox_with_popups();
+            if bbox.is_empty() {
+                continue;
+            }
+
+            let scale = thumbnail_scale(bbox.size);
+            let location: Point<i32, Physical> = (-bbox.loc).to_physical_precise_round(scale);
+            let elements =
+                window.render_elements::<WaylandSurfaceRenderElement<R>>(renderer, location, Scale::from(scale), 1.0);
+            let commits: Vec<_> = elements
+                .iter()
+                .map(|element| (element.id().clone(), element.current_commit()))
+                .collect();
+            if self.thumbnails.get(window).is_some_and(|thumbnail| thumbnail.commits == commits) {
+                continue;
+            }
+            let size = bbox.size.to_physical_precise_round(scale);
+            outdated.push((last_update, window, size, scale, elements, commits));
+        }
+
+        outdated.sort_by_key(|(last_update, ..)| *last_update);
+        for (_, window, size, scale, elements, commits) in outdated.into_iter().take(PER_FRAME) {
+            match render_thumbnail(renderer, size, scale, &elements) {
+                Ok((texture, size)) => {
+                    let thumbnail = Thumbnail {
+                        texture,
+                        size,
+                        commits,
+                        updated: now,
+                    };
+                    self.thumbnails.insert(window.clone(), thumbnail);
+                }
+                Err(err) => tracing::warn!("Failed to render window thumbnail: {}", err),
+            }
+        }
+    }
+}
+
+fn render_thumbnail<R>(
+    renderer: &mut R,
+    size: Size<i32, Physical>,
+    scale: f64,
+    elements: &[WaylandSurfaceRenderElement<R>],
+) -> Result<(R::TextureId, Size<i32, Buffer>), String>
+where
+    R: Renderer + ImportAll + Offscreen<R::TextureId> + Bind<R::TextureId>,
+    R::TextureId: 'static,
+{
+    let buffer_size = size.to_logical(1).to_buffer(1, Transform::Normal);
+    let mut texture = renderer
+        .create_buffer(Fourcc::Abgr8888, buffer_size)
+        .map_err(|err| format!("failed to create texture: {}", err))?;
+    {
+        let mut framebuffer = renderer
+            .bind(&mut texture)
+            .map_err(|err| format!("failed to bind texture: {}", err))?;
+        let mut damage_tracker = OutputDamageTracker::new(size, scale, Transform::Normal);
+        damage_tracker
+            .render_output(renderer, &mut framebuffer, 0, elements, Color32F::TRANSPARENT)
+            .map_err(|err| format!("failed to render: {:?}", err))?;
+    }
+    Ok((texture, buffer_size))
+}
@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use smithay::{
+    desktop::Window,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+
+static NEXT_WINDOW_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Stable identifier handed out to IPC clients and shells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WindowId(pub u64);
+
+pub fn window_id(window: &Window) -> WindowId {
+    *window
+        .user_data()
+        .get_or_insert(|| WindowId(NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed)))
+}
+
+/// Current title and app id of a window. For X11 windows the WM_CLASS stands in for the app id.
+pub fn window_title_and_app_id(window: &Window) -> (Option<String>, Option<String>) {
+    if let Some(x11) = window.x11_surface() {
+        return (Some(x11.title()), Some(x11.class()));
+    }
+    let Some(toplevel) = window.toplevel() else {
+        return (None, None);
+    };
+    with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .map(|data| {
+                let data = data.lock().unwrap();
+                (data.title.clone(), data.app_id.clone())
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Whether `window` is an X11 override-redirect window: a menu, tooltip or the like that places
+/// itself. These are drawn and get pointer input, but are never focused or listed as windows.
+pub fn is_override_redirect(window: &Window) -> bool {
+    window.x11_surface().is_some_and(|x11| x11.is_override_redirect())
+}
+
+/// Asks the client to close `window`, whichever shell it uses.
+pub fn close_window(window: &Window) {
+    if let Some(toplevel) = window.toplevel() {
+        toplevel.send_close();
+    } else if let Some(x11) = window.x11_surface() {
+        if let Err(err) = x11.close() {
+            tracing::warn!("Failed to close X11 window: {}", err);
+        }
+    }
+}
+
+/// Sets or clears the activated state that clients use to draw focused decorations.
+pub fn set_window_activated(window: &Window, activated: bool) {
+    if let Some(toplevel) = window.toplevel() {
+        toplevel.with_pending_state(|state| {
+            if activated {
+                state.states.set(xdg_toplevel::State::Activated);
+            } else {
+                state.states.unset(xdg_toplevel::State::Activated);
+            }
+        });
+        toplevel.send_configure();
+    } else if let Some(x11) = window.x11_surface() {
+        let _ = x11.set_activated(activated);
+    }
+}
+
+/// Whether `window` is currently maximized and whether it is fullscreen.
+pub fn window_maximized_and_fullscreen(window: &Window) -> (bool, bool) {
+    if let Some(x11) = window.x11_surface() {
+        return (x11.is_maximized(), x11.is_fullscreen());
+    }
+    let Some(toplevel) = window.toplevel() else {
+        return (false, false);
+    };
+    let state = toplevel.current_state();
+    (
+        state.states.contains(xdg_toplevel::State::Maximized),
+        state.states.contains(xdg_toplevel::State::Fullscreen),
+    )
+}
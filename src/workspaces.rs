@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use smithay::{
+    desktop::Window,
+    utils::{Logical, Point},
+};
+
+use crate::ipc::IpcEvent;
+use crate::state::NanaimoState;
+use crate::window::{is_override_redirect, set_window_activated, window_id};
+
+pub const WORKSPACE_COUNT: usize = 9;
+
+/// Windows on the active workspace live in `space`; the others are unmapped and parked here
+/// together with the location they had.
+#[derive(Default)]
+pub struct Workspaces {
+    pub active: usize,
+    hidden: HashMap<usize, Vec<(Window, Point<i32, Logical>)>>,
+    /// Minimized windows keep their workspace and location, but stay unmapped until activated.
+    minimized: Vec<(Window, usize, Point<i32, Logical>)>,
+}
+
+impl Workspaces {
+    pub fn hidden_windows(&self) -> impl Iterator<Item = (usize, &Window, Point<i32, Logical>)> {
+        self.hidden
+            .iter()
+            .flat_map(|(index, windows)| windows.iter().map(move |(window, loc)| (*index, window, *loc)))
+            .chain(self.minimized.iter().map(|(window, index, loc)| (*index, window, *loc)))
+    }
+
+    pub fn window_count(&self, index: usize) -> usize {
+        let minimized = self.minimized.iter().filter(|(_, i, _)| *i == index).count();
+        self.hidden.get(&index).map(Vec::len).unwrap_or(0) + minimized
+    }
+
+    pub fn is_minimized(&self, window: &Window) -> bool {
+        self.minimized.iter().any(|(w, _, _)| w == window)
+    }
+
+    /// Drops windows whose client has gone away.
+    pub fn refresh(&mut self) {
+        for windows in self.hidden.values_mut() {
+            windows.retain(|(window, _)| window.alive());
+        }
+        self.minimized.retain(|(window, _, _)| window.alive());
+    }
+}
+
+impl NanaimoState {
+    /// The windows on the active workspace, in stacking order, without override-redirect ones.
+    pub fn managed_windows(&self) -> impl DoubleEndedIterator<Item = &Window> {
+        self.space.elements().filter(|w| !is_override_redirect(w))
+    }
+
+    /// Every window on every workspace, with its workspace index and location.
+    pub fn all_windows(&self) -> Vec<(Window, usize, Point<i32, Logical>)> {
+        let active = self.workspaces.active;
+        self.managed_windows()
+            .map(|w| (w.clone(), active, self.space.element_location(w).unwrap_or_default()))
+            .chain(self.workspaces.hidden_windows().map(|(index, w, loc)| (w.clone(), index, loc)))
+            .collect()
+    }
+
+    pub fn switch_workspace(&mut self, index: usize) {
+        if index >= WORKSPACE_COUNT || index == self.workspaces.active {
+            return;
+        }
+        tracing::info!("Switching to workspace {}", index + 1);
+
+        // Override-redirect windows belong to no workspace; their clients unmap them.
+        let outgoing: Vec<_> = self
+            .managed_windows()
+            .filter_map(|w| self.space.element_location(w).map(|loc| (w.clone(), loc)))
+            .collect();
+        for (window, _) in &outgoing {
+            self.space.unmap_elem(window);
+        }
+        self.workspaces.hidden.insert(self.workspaces.active, outgoing);
+
+        self.workspaces.active = index;
+        let incoming = self.workspaces.hidden.remove(&index).unwrap_or_default();
+        for (window, location) in incoming {
+            self.space.map_element(window, location, false);
+        }
+
+        self.restore_focus();
+        self.ipc_event(IpcEvent::WorkspaceActivated { index: index + 1 });
+    }
+
+    pub fn move_window_to_workspace(&mut self, window: &Window, index: usize) {
+        if index >= WORKSPACE_COUNT || index == self.workspaces.active {
+            return;
+        }
+        let Some(location) = self.space.element_location(window) else {
+            return;
+        };
+        self.space.unmap_elem(window);
+        self.workspaces
+            .hidden
+            .entry(index)
+            .or_default()
+            .push((window.clone(), location));
+
+        self.restore_focus();
+        self.ipc_event(IpcEvent::WindowMoved {
+            id: window_id(window).0,
+            workspace: index + 1,
+        });
+    }
+
+    /// Hides a window on the active workspace until it is activated again.
+    pub fn minimize_window(&mut self, window: &Window) {
+        let Some(location) = self.space.element_location(window) else {
+            return;
+        };
+        self.space.unmap_elem(window);
+        set_window_activated(window, false);
+        self.workspaces
+            .minimized
+            .push((window.clone(), self.workspaces.active, location));
+        self.restore_focus();
+    }
+
+    /// Restores `window` if it is minimized, switches to its workspace and focuses it.
+    pub fn activate_window(&mut self, window: &Window) {
+        if let Some(pos) = self.workspaces.minimized.iter().position(|(w, _, _)| w == window) {
+            let (window, index, location) = self.workspaces.minimized.remove(pos);
+            if index == self.workspaces.active {
+                self.space.map_element(window, location, false);
+            } else {
+                self.workspaces.hidden.entry(index).or_default().push((window, location));
+            }
+        }
+        let Some((_, index, _)) = self.all_windows().into_iter().find(|(w, _, _)| w == window) else {
+            return;
+        };
+        self.switch_workspace(index);
+        let serial = self.serial_counter.next_serial();
+        self.focus_window(window, serial);
+    }
+}
@@ -0,0 +1,267 @@
+//! Lazily started XWayland.
+//!
+//! Nanaimo claims an X11 display and binds its sockets itself, exports `DISPLAY`, and only
+//! launches Xwayland once an X11 client connects. The listening sockets are handed to Xwayland
+//! with `-listenfd`, so the client that triggered the launch is accepted by Xwayland as soon as
+//! it is up. If Xwayland exits, the sockets are watched again and the next client restarts it.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, BorrowedFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::{
+            fs::OpenOptionsExt,
+            net::{SocketAddr, UnixListener, UnixStream},
+            process::CommandExt,
+        },
+    },
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use smithay::{
+    reexports::{
+        calloop::{
+            Interest, LoopHandle, Mode, PostAction, RegistrationToken,
+            generic::Generic,
+            timer::{TimeoutAction, Timer},
+        },
+        rustix,
+    },
+    xwayland::X11Wm,
+};
+
+use crate::state::{ClientState, NanaimoState};
+
+/// Highest display number we try before giving up.
+const MAX_DISPLAY: u32 = 32;
+
+pub struct XWaylandLauncher {
+    pub display_number: u32,
+    lock_path: PathBuf,
+    socket_path: PathBuf,
+    listeners: Vec<UnixListener>,
+    tokens: Vec<RegistrationToken>,
+    child: Option<Child>,
+}
+
+impl XWaylandLauncher {
+    /// Claims a free X11 display, binds its sockets and starts watching them.
+    pub fn bind(loop_handle: &LoopHandle<'static, NanaimoState>) -> io::Result<Self> {
+        std::fs::create_dir_all("/tmp/.X11-unix")?;
+
+        for display_number in 0..=MAX_DISPLAY {
+            let Some(lock_path) = grab_lock(display_number)? else {
+                continue;
+            };
+            match bind_sockets(display_number) {
+                Ok((socket_path, listeners)) => {
+                    let mut launcher = Self {
+                        display_number,
+                        lock_path,
+                        socket_path,
+                        listeners,
+                        tokens: Vec::new(),
+                        child: None,
+                    };
+                    launcher.watch(loop_handle)?;
+                    tracing::info!("XWayland will start on demand on DISPLAY=:{}", display_number);
+                    return Ok(launcher);
+                }
+                Err(err) => {
+                    tracing::debug!("Could not bind X11 display :{}: {}", display_number, err);
+                    let _ = std::fs::remove_file(&lock_path);
+                }
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::AddrInUse, "no free X11 display"))
+    }
+
+    fn watch(&mut self, loop_handle: &LoopHandle<'static, NanaimoState>) -> io::Result<()> {
+        for listener in &self.listeners {
+            let source = Generic::new(listener.try_clone()?, Interest::READ, Mode::Level);
+            let token = loop_handle
+                .insert_source(source, |_, _, state| {
+                    if let Err(err) = state.start_xwayland() {
+                        tracing::error!("Failed to start XWayland: {}", err);
+                    }
+                    // The socket stays readable until Xwayland accepts the pending client.
+                    Ok(PostAction::Disable)
+                })
+                .map_err(|err| io::Error::other(err.to_string()))?;
+            self.tokens.push(token);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for XWaylandLauncher {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+        }
+        let _ = std::fs::remove_file(&self.socket_path);
+        let _ = std::fs::remove_file(&self.lock_path);
+    }
+}
+
+/// Creates `/tmp/.X<n>-lock`, clearing it first if the process that owned it is gone.
+fn grab_lock(display_number: u32) -> io::Result<Option<PathBuf>> {
+    let lock_path = PathBuf::from(format!("/tmp/.X{}-lock", display_number));
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).mode(0o444).open(&lock_path) {
+            Ok(mut file) => {
+                file.write_all(format!("{:>10}\n", std::process::id()).as_bytes())?;
+                return Ok(Some(lock_path));
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let mut contents = String::new();
+                let owner = OpenOptions::new()
+                    .read(true)
+                    .open(&lock_path)
+                    .and_then(|mut file| file.read_to_string(&mut contents))
+                    .ok()
+                    .and_then(|_| contents.trim().parse::<u32>().ok());
+                match owner {
+                    Some(pid) if !PathBuf::from(format!("/proc/{}", pid)).exists() => {
+                        tracing::debug!("Removing stale X11 lock {}", lock_path.display());
+                        let _ = std::fs::remove_file(&lock_path);
+                    }
+                    _ => return Ok(None),
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+fn bind_sockets(display_number: u32) -> io::Result<(PathBuf, Vec<UnixListener>)> {
+    let socket_path = PathBuf::from(format!("/tmp/.X11-unix/X{}", display_number));
+    // We hold the lock, so anything left at this path is stale.
+    let _ = std::fs::remove_file(&socket_path);
+    let filesystem = UnixListener::bind(&socket_path)?;
+
+    let abstract_addr = SocketAddr::from_abstract_name(socket_path.as_os_str().as_encoded_bytes())?;
+    let abstract_socket = UnixListener::bind_addr(&abstract_addr)?;
+
+    Ok((socket_path, vec![filesystem, abstract_socket]))
+}
+
+fn clear_cloexec(fd: RawFd) -> io::Result<()> {
+    // Safety: only called from `pre_exec` on fds that stay open until the child has spawned.
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    rustix::io::fcntl_setfd(fd, rustix::io::FdFlags::empty())?;
+    Ok(())
+}
+
+impl NanaimoState {
+    /// Launches Xwayland on the display we already own, if it is not running yet.
+    pub fn start_xwayland(&mut self) -> io::Result<()> {
+        let Some(launcher) = self.xwayland.as_mut() else {
+            return Ok(());
+        };
+        if launcher.child.is_some() {
+            return Ok(());
+        }
+
+        let (wayland_ours, wayland_theirs) = UnixStream::pair()?;
+        let (wm_ours, wm_theirs) = UnixStream::pair()?;
+        let (ready_ours, ready_theirs) = UnixStream::pair()?;
+
+        let mut command = Command::new("Xwayland");
+        command
+            .arg(format!(":{}", launcher.display_number))
+            .arg("-rootless")
+            .arg("-wm")
+            .arg(wm_theirs.as_raw_fd().to_string())
+            .arg("-displayfd")
+            .arg(ready_theirs.as_raw_fd().to_string())
+            .env("WAYLAND_SOCKET", wayland_theirs.as_raw_fd().to_string())
+            .stdin(Stdio::null());
+        let mut inherited = vec![
+            wayland_theirs.as_raw_fd(),
+            wm_theirs.as_raw_fd(),
+            ready_theirs.as_raw_fd(),
+        ];
+        for listener in &launcher.listeners {
+            command.arg("-listenfd").arg(listener.as_raw_fd().to_string());
+            inherited.push(listener.as_raw_fd());
+        }
+        unsafe {
+            command.pre_exec(move || {
+                for fd in &inherited {
+                    clear_cloexec(*fd)?;
+                }
+                Ok(())
+            });
+        }
+
+        tracing::info!("X11 client connected, starting XWayland on :{}", launcher.display_number);
+        launcher.child = Some(command.spawn()?);
+        drop((wayland_theirs, wm_theirs, ready_theirs));
+
+        let client = self
+            .display_handle
+            .insert_client(wayland_ours, Arc::new(ClientState::default()))?;
+
+        // Xwayland writes the display number to `-displayfd` once it is ready for the WM.
+        let mut wm_socket = Some(wm_ours);
+        let ready = Generic::new(ready_ours, Interest::READ, Mode::Level);
+        self.loop_handle
+            .insert_source(ready, move |_, _, state| {
+                let Some(wm_socket) = wm_socket.take() else {
+                    return Ok(PostAction::Remove);
+                };
+                match X11Wm::start_wm(state.loop_handle.clone(), wm_socket, client.clone()) {
+                    Ok(wm) => {
+                        tracing::info!("XWayland ready");
+                        state.xwm = Some(wm);
+                    }
+                    Err(err) => tracing::error!("Failed to start the X11 window manager: {}", err),
+                }
+                Ok(PostAction::Remove)
+            })
+            .map_err(|err| io::Error::other(err.to_string()))?;
+
+        self.loop_handle
+            .insert_source(Timer::from_duration(Duration::from_secs(1)), |_, _, state| {
+                state.check_xwayland_exit()
+            })
+            .map_err(|err| io::Error::other(err.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Notices when Xwayland exits and re-arms the sockets so the next client restarts it.
+    fn check_xwayland_exit(&mut self) -> TimeoutAction {
+        let Some(launcher) = self.xwayland.as_mut() else {
+            return TimeoutAction::Drop;
+        };
+        let Some(child) = launcher.child.as_mut() else {
+            return TimeoutAction::Drop;
+        };
+        match child.try_wait() {
+            Ok(None) => TimeoutAction::ToDuration(Duration::from_secs(1)),
+            Ok(Some(status)) => {
+                tracing::warn!("XWayland exited with {}", status);
+                launcher.child = None;
+                for token in &launcher.tokens {
+                    if let Err(err) = self.loop_handle.enable(token) {
+                        tracing::error!("Failed to re-arm X11 socket: {}", err);
+                    }
+                }
+                self.xwm = None;
+                TimeoutAction::Drop
+            }
+            Err(err) => {
+                tracing::error!("Failed to poll XWayland: {}", err);
+                TimeoutAction::Drop
+            }
+        }
+    }
+}